@@ -0,0 +1,45 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+
+const BACKENDS: &[&str] = &["mysql", "sqlite"];
+
+fn main() -> anyhow::Result<()> {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+
+    let mut out = File::create(format!("{}/migrations.rs", out_dir))?;
+    for backend in BACKENDS {
+        let migrations_dir = format!("{}/migrations/{}", manifest_dir, backend);
+        println!("cargo:rerun-if-changed={}", migrations_dir);
+
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&migrations_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+
+        writeln!(out, "#[cfg(feature = {:?})]", backend)?;
+        writeln!(
+            out,
+            "pub(crate) const {}: &[EmbeddedMigration] = &[",
+            backend.to_uppercase()
+        )?;
+        for name in names {
+            let version = name.split('_').next().unwrap().replace('-', "");
+            let up_sql = format!("{}/{}/up.sql", migrations_dir, name);
+            println!("cargo:rerun-if-changed={}", up_sql);
+            writeln!(
+                out,
+                "    EmbeddedMigration {{ version: {:?}, name: {:?}, up_sql: include_str!({:?}) }},",
+                version, name, up_sql
+            )?;
+        }
+        writeln!(out, "];")?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,2 @@
+ALTER TABLE race_entrants
+    DROP COLUMN gap;
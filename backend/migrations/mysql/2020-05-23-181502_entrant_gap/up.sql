@@ -0,0 +1,8 @@
+ALTER TABLE race_entrants
+    ADD COLUMN gap INTEGER AFTER time;
+
+UPDATE race_entrants AS entrant
+    INNER JOIN race_entrants AS leader
+        ON leader.race_id = entrant.race_id AND leader.position = 1
+    SET entrant.gap = entrant.time - leader.time
+    WHERE entrant.time IS NOT NULL;
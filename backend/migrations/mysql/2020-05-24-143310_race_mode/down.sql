@@ -0,0 +1,2 @@
+ALTER TABLE races
+    DROP COLUMN mode;
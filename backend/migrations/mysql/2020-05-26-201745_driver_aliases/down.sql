@@ -0,0 +1,4 @@
+ALTER TABLE race_entrants
+    DROP COLUMN entered_as;
+
+DROP TABLE driver_aliases;
@@ -0,0 +1,15 @@
+ALTER TABLE races
+    ADD COLUMN track TEXT AFTER date;
+
+UPDATE races
+    INNER JOIN tracks ON tracks.id = races.track_id
+    SET races.track = tracks.name;
+
+ALTER TABLE races
+    MODIFY COLUMN track TEXT NOT NULL,
+    DROP FOREIGN KEY races_track_id_fk;
+
+ALTER TABLE races
+    DROP COLUMN track_id;
+
+DROP TABLE tracks;
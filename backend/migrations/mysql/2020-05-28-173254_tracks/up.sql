@@ -0,0 +1,21 @@
+CREATE TABLE tracks (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL UNIQUE
+);
+
+INSERT INTO tracks (name)
+    SELECT DISTINCT track FROM races ORDER BY track;
+
+ALTER TABLE races
+    ADD COLUMN track_id INTEGER AFTER date;
+
+UPDATE races
+    INNER JOIN tracks ON tracks.name = races.track
+    SET races.track_id = tracks.id;
+
+ALTER TABLE races
+    MODIFY COLUMN track_id INTEGER NOT NULL,
+    ADD CONSTRAINT races_track_id_fk FOREIGN KEY (track_id) REFERENCES tracks(id);
+
+ALTER TABLE races
+    DROP COLUMN track;
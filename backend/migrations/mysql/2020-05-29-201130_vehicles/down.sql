@@ -0,0 +1,14 @@
+ALTER TABLE race_entrants
+    ADD COLUMN vehicle TEXT AFTER position;
+
+UPDATE race_entrants
+    INNER JOIN vehicles ON vehicles.id = race_entrants.vehicle_id
+    SET race_entrants.vehicle = vehicles.name;
+
+ALTER TABLE race_entrants
+    DROP FOREIGN KEY race_entrants_vehicle_id_fk;
+
+ALTER TABLE race_entrants
+    DROP COLUMN vehicle_id;
+
+DROP TABLE vehicles;
@@ -0,0 +1,30 @@
+CREATE TABLE vehicles (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    name VARCHAR(100) NOT NULL,
+    normalized_name VARCHAR(100) NOT NULL UNIQUE
+);
+
+-- Vehicles are matched ignoring case and runs of whitespace, which SQL can't
+-- do quite like `VehicleName::new`. Every name that the collation tells apart
+-- becomes a vehicle of its own, with the name standing in for the normalized
+-- name, and the backend merges the vehicles and normalizes their names after
+-- migrating.
+INSERT INTO vehicles (name, normalized_name)
+    SELECT MIN(vehicle), MIN(vehicle)
+    FROM race_entrants
+    WHERE vehicle IS NOT NULL
+    GROUP BY vehicle
+    ORDER BY MIN(vehicle);
+
+ALTER TABLE race_entrants
+    ADD COLUMN vehicle_id INTEGER AFTER position;
+
+UPDATE race_entrants
+    INNER JOIN vehicles ON vehicles.name = race_entrants.vehicle
+    SET race_entrants.vehicle_id = vehicles.id;
+
+ALTER TABLE race_entrants
+    ADD CONSTRAINT race_entrants_vehicle_id_fk FOREIGN KEY (vehicle_id) REFERENCES vehicles(id);
+
+ALTER TABLE race_entrants
+    DROP COLUMN vehicle;
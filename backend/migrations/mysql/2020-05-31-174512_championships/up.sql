@@ -0,0 +1,29 @@
+CREATE TABLE seasons (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    name VARCHAR(100) NOT NULL UNIQUE
+);
+
+-- A points table is stored as the points for each finishing position,
+-- separated by commas, such as "25,18,15,12,10,8,6,4,2,1".
+CREATE TABLE championships (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    season_id INTEGER NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    points VARCHAR(255) NOT NULL,
+    best_lap_points INTEGER NOT NULL DEFAULT 0,
+    drop_rounds INTEGER NOT NULL DEFAULT 0,
+
+    UNIQUE (season_id, name),
+    FOREIGN KEY (season_id) REFERENCES seasons(id)
+);
+
+CREATE TABLE championship_rounds (
+    championship_id INTEGER NOT NULL,
+    race_id INTEGER NOT NULL,
+
+    PRIMARY KEY (championship_id, race_id),
+    FOREIGN KEY (championship_id) REFERENCES championships(id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (race_id) REFERENCES races(id)
+        ON DELETE CASCADE
+);
@@ -0,0 +1,10 @@
+-- What happened to each file picked up by the watch command. `race_id` is
+-- null if the file name is not a race id.
+CREATE TABLE import_log (
+    id INTEGER PRIMARY KEY AUTO_INCREMENT,
+    file VARCHAR(255) NOT NULL,
+    race_id INTEGER NULL,
+    outcome ENUM('imported', 'replaced', 'unchanged', 'failed') NOT NULL,
+    error TEXT NULL,
+    logged_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+ALTER TABLE race_entrants DROP COLUMN extras;
+ALTER TABLE race_entrants DROP COLUMN name_class;
+ALTER TABLE race_entrants DROP COLUMN row_class;
@@ -0,0 +1,11 @@
+-- The gzipped results document each race was parsed from, so that stored
+-- races can be parsed again with `reparse` when the parser changes. Races
+-- imported before this table existed, or entered as a list of entrants, have
+-- no source.
+CREATE TABLE race_sources (
+    race_id INTEGER NOT NULL PRIMARY KEY,
+    document LONGBLOB NOT NULL,
+
+    FOREIGN KEY (race_id) REFERENCES races(id)
+        ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- The gzipped results documents that failed to import, by the id of the race
+-- they are for, so that `reparse` can try them again once the parser is
+-- fixed. Only the latest document to fail is kept for a race, and it is
+-- removed once the race is stored.
+CREATE TABLE failed_sources (
+    race_id INTEGER NOT NULL PRIMARY KEY,
+    date DATE NOT NULL,
+    document LONGBLOB NOT NULL,
+    error TEXT NOT NULL,
+    failed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
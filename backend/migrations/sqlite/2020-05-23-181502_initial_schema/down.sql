@@ -0,0 +1,3 @@
+DROP TABLE race_entrants;
+DROP TABLE races;
+DROP TABLE drivers;
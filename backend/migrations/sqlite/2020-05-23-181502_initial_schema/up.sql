@@ -0,0 +1,32 @@
+CREATE TABLE drivers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(50) NOT NULL UNIQUE
+);
+
+CREATE TABLE races (
+    id INTEGER PRIMARY KEY,
+    date DATE NOT NULL,
+    track TEXT NOT NULL,
+    laps INTEGER,
+    minutes INTEGER
+);
+
+CREATE TABLE race_entrants (
+    race_id INTEGER NOT NULL,
+    driver_id INTEGER NOT NULL,
+    position INTEGER,
+    vehicle TEXT,
+    time INTEGER,
+    gap INTEGER,
+    best_lap INTEGER,
+    lap INTEGER,
+    reason TEXT CHECK (reason IN ('dns', 'dnf', 'dsq')),
+    ping INTEGER,
+    fps INTEGER,
+    fps_locked BOOLEAN NOT NULL,
+
+    PRIMARY KEY (race_id, driver_id),
+    FOREIGN KEY (race_id) REFERENCES races(id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (driver_id) REFERENCES drivers(id)
+);
@@ -0,0 +1,17 @@
+-- SQLite only drops columns since 3.35, so the table is rebuilt without it.
+-- This needs foreign keys to be off, as they are in the diesel CLI, or
+-- dropping the old table would delete the race entrants.
+CREATE TABLE new_races (
+    id INTEGER PRIMARY KEY,
+    date DATE NOT NULL,
+    track TEXT NOT NULL,
+    laps INTEGER,
+    minutes INTEGER
+);
+
+INSERT INTO new_races (id, date, track, laps, minutes)
+    SELECT id, date, track, laps, minutes FROM races;
+
+DROP TABLE races;
+
+ALTER TABLE new_races RENAME TO races;
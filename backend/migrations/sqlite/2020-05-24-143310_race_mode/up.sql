@@ -0,0 +1,10 @@
+ALTER TABLE races
+    ADD COLUMN mode TEXT CHECK (mode IN ('laps', 'minutes', 'rally', 'point_to_point'));
+
+-- Rally and point-to-point races can't be told apart in existing rows, so
+-- they are left NULL until they are imported again.
+UPDATE races
+    SET mode = CASE
+        WHEN laps IS NOT NULL THEN 'laps'
+        WHEN minutes IS NOT NULL THEN 'minutes'
+    END;
@@ -0,0 +1,30 @@
+-- SQLite only drops columns since 3.35, so the table is rebuilt without it.
+-- This needs foreign keys to be off, as they are in the diesel CLI.
+CREATE TABLE new_race_entrants (
+    race_id INTEGER NOT NULL,
+    driver_id INTEGER NOT NULL,
+    position INTEGER,
+    vehicle TEXT,
+    time INTEGER,
+    gap INTEGER,
+    best_lap INTEGER,
+    lap INTEGER,
+    reason TEXT CHECK (reason IN ('dns', 'dnf', 'dsq')),
+    ping INTEGER,
+    fps INTEGER,
+    fps_locked BOOLEAN NOT NULL,
+
+    PRIMARY KEY (race_id, driver_id),
+    FOREIGN KEY (race_id) REFERENCES races(id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (driver_id) REFERENCES drivers(id)
+);
+
+INSERT INTO new_race_entrants (race_id, driver_id, position, vehicle, time, gap, best_lap, lap, reason, ping, fps, fps_locked)
+    SELECT race_id, driver_id, position, vehicle, time, gap, best_lap, lap, reason, ping, fps, fps_locked FROM race_entrants;
+
+DROP TABLE race_entrants;
+
+ALTER TABLE new_race_entrants RENAME TO race_entrants;
+
+DROP TABLE driver_aliases;
@@ -0,0 +1,15 @@
+CREATE TABLE driver_aliases (
+    name VARCHAR(50) PRIMARY KEY,
+    driver_id INTEGER NOT NULL,
+
+    FOREIGN KEY (driver_id) REFERENCES drivers(id)
+        ON DELETE CASCADE
+);
+
+-- The name an entrant was listed under, so that an alias can be split back
+-- out into a separate driver after a merge.
+ALTER TABLE race_entrants
+    ADD COLUMN entered_as VARCHAR(50);
+
+UPDATE race_entrants
+    SET entered_as = (SELECT name FROM drivers WHERE drivers.id = race_entrants.driver_id);
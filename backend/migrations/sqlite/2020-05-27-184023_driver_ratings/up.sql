@@ -0,0 +1,15 @@
+-- The rating of each driver after each race they were rated in. The table
+-- starts out empty; the ratings of races stored before it existed are
+-- computed when the migrations are run by the backend.
+CREATE TABLE driver_ratings (
+    race_id INTEGER NOT NULL,
+    driver_id INTEGER NOT NULL,
+    rating REAL NOT NULL,
+    delta REAL NOT NULL,
+
+    PRIMARY KEY (race_id, driver_id),
+    FOREIGN KEY (race_id) REFERENCES races(id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (driver_id) REFERENCES drivers(id)
+        ON DELETE CASCADE
+);
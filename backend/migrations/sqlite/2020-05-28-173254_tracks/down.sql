@@ -0,0 +1,22 @@
+-- SQLite only drops columns since 3.35, so the table is rebuilt with `track`
+-- in place of `track_id`. This needs foreign keys to be off, as they are in
+-- the diesel CLI, or dropping the old table would delete the race entrants.
+CREATE TABLE new_races (
+    id INTEGER PRIMARY KEY,
+    date DATE NOT NULL,
+    track TEXT NOT NULL,
+    laps INTEGER,
+    minutes INTEGER,
+    mode TEXT CHECK (mode IN ('laps', 'minutes', 'rally', 'point_to_point'))
+);
+
+INSERT INTO new_races (id, date, track, laps, minutes, mode)
+    SELECT races.id, races.date, tracks.name, races.laps, races.minutes, races.mode
+    FROM races
+    INNER JOIN tracks ON tracks.id = races.track_id;
+
+DROP TABLE races;
+
+ALTER TABLE new_races RENAME TO races;
+
+DROP TABLE tracks;
@@ -0,0 +1,31 @@
+CREATE TABLE tracks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL UNIQUE
+);
+
+INSERT INTO tracks (name)
+    SELECT DISTINCT track FROM races ORDER BY track;
+
+-- SQLite can only add foreign key columns that default to NULL, and only
+-- drops columns since 3.35, so the table is rebuilt with `track_id` in place
+-- of `track`. Foreign keys are off while migrations run, or dropping the old
+-- table would delete the race entrants.
+CREATE TABLE new_races (
+    id INTEGER PRIMARY KEY,
+    date DATE NOT NULL,
+    track_id INTEGER NOT NULL,
+    laps INTEGER,
+    minutes INTEGER,
+    mode TEXT CHECK (mode IN ('laps', 'minutes', 'rally', 'point_to_point')),
+
+    FOREIGN KEY (track_id) REFERENCES tracks(id)
+);
+
+INSERT INTO new_races (id, date, track_id, laps, minutes, mode)
+    SELECT races.id, races.date, tracks.id, races.laps, races.minutes, races.mode
+    FROM races
+    INNER JOIN tracks ON tracks.name = races.track;
+
+DROP TABLE races;
+
+ALTER TABLE new_races RENAME TO races;
@@ -0,0 +1,34 @@
+-- SQLite only drops columns since 3.35, so the table is rebuilt with
+-- `vehicle` in place of `vehicle_id`. This needs foreign keys to be off, as
+-- they are in the diesel CLI.
+CREATE TABLE new_race_entrants (
+    race_id INTEGER NOT NULL,
+    driver_id INTEGER NOT NULL,
+    position INTEGER,
+    vehicle TEXT,
+    time INTEGER,
+    gap INTEGER,
+    best_lap INTEGER,
+    lap INTEGER,
+    reason TEXT CHECK (reason IN ('dns', 'dnf', 'dsq')),
+    ping INTEGER,
+    fps INTEGER,
+    fps_locked BOOLEAN NOT NULL,
+    entered_as VARCHAR(50),
+
+    PRIMARY KEY (race_id, driver_id),
+    FOREIGN KEY (race_id) REFERENCES races(id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (driver_id) REFERENCES drivers(id)
+);
+
+INSERT INTO new_race_entrants (race_id, driver_id, position, vehicle, time, gap, best_lap, lap, reason, ping, fps, fps_locked, entered_as)
+    SELECT race_id, driver_id, position, vehicles.name, time, gap, best_lap, lap, reason, ping, fps, fps_locked, entered_as
+    FROM race_entrants
+    LEFT JOIN vehicles ON vehicles.id = race_entrants.vehicle_id;
+
+DROP TABLE race_entrants;
+
+ALTER TABLE new_race_entrants RENAME TO race_entrants;
+
+DROP TABLE vehicles;
@@ -0,0 +1,49 @@
+CREATE TABLE vehicles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(100) NOT NULL,
+    normalized_name VARCHAR(100) NOT NULL UNIQUE
+);
+
+-- Vehicles are matched ignoring case and runs of whitespace, which SQL can't
+-- do quite like `VehicleName::new`. Every name becomes a vehicle of its own,
+-- with the name standing in for the normalized name, and the backend merges
+-- the vehicles and normalizes their names after migrating.
+INSERT INTO vehicles (name, normalized_name)
+    SELECT DISTINCT vehicle, vehicle
+    FROM race_entrants
+    WHERE vehicle IS NOT NULL
+    ORDER BY vehicle;
+
+-- SQLite can only add foreign key columns that default to NULL, and only
+-- drops columns since 3.35, so the table is rebuilt with `vehicle_id` in
+-- place of `vehicle`.
+CREATE TABLE new_race_entrants (
+    race_id INTEGER NOT NULL,
+    driver_id INTEGER NOT NULL,
+    position INTEGER,
+    vehicle_id INTEGER,
+    time INTEGER,
+    gap INTEGER,
+    best_lap INTEGER,
+    lap INTEGER,
+    reason TEXT CHECK (reason IN ('dns', 'dnf', 'dsq')),
+    ping INTEGER,
+    fps INTEGER,
+    fps_locked BOOLEAN NOT NULL,
+    entered_as VARCHAR(50),
+
+    PRIMARY KEY (race_id, driver_id),
+    FOREIGN KEY (race_id) REFERENCES races(id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (driver_id) REFERENCES drivers(id),
+    FOREIGN KEY (vehicle_id) REFERENCES vehicles(id)
+);
+
+INSERT INTO new_race_entrants (race_id, driver_id, position, vehicle_id, time, gap, best_lap, lap, reason, ping, fps, fps_locked, entered_as)
+    SELECT race_id, driver_id, position, vehicles.id, time, gap, best_lap, lap, reason, ping, fps, fps_locked, entered_as
+    FROM race_entrants
+    LEFT JOIN vehicles ON vehicles.name = race_entrants.vehicle;
+
+DROP TABLE race_entrants;
+
+ALTER TABLE new_race_entrants RENAME TO race_entrants;
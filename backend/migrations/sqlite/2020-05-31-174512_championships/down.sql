@@ -0,0 +1,3 @@
+DROP TABLE championship_rounds;
+DROP TABLE championships;
+DROP TABLE seasons;
@@ -0,0 +1,7 @@
+-- How far the results fetcher has got on each results website, as the last
+-- race it imported from there, or the last day it scanned with `race_id` 0.
+CREATE TABLE fetch_cursors (
+    base_url VARCHAR(255) NOT NULL PRIMARY KEY,
+    race_id INTEGER NOT NULL,
+    date DATE NOT NULL
+);
@@ -0,0 +1,10 @@
+-- What happened to each file picked up by the watch command. `race_id` is
+-- null if the file name is not a race id.
+CREATE TABLE import_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file VARCHAR(255) NOT NULL,
+    race_id INTEGER,
+    outcome TEXT NOT NULL CHECK (outcome IN ('imported', 'replaced', 'unchanged', 'failed')),
+    error TEXT,
+    logged_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
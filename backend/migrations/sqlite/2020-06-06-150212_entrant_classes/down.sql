@@ -0,0 +1,31 @@
+-- SQLite only drops columns since 3.35, so the table is rebuilt without
+-- them. This needs foreign keys to be off, as they are in the diesel CLI.
+CREATE TABLE new_race_entrants (
+    race_id INTEGER NOT NULL,
+    driver_id INTEGER NOT NULL,
+    position INTEGER,
+    vehicle_id INTEGER,
+    time INTEGER,
+    gap INTEGER,
+    best_lap INTEGER,
+    lap INTEGER,
+    reason TEXT CHECK (reason IN ('dns', 'dnf', 'dsq')),
+    ping INTEGER,
+    fps INTEGER,
+    fps_locked BOOLEAN NOT NULL,
+    entered_as VARCHAR(50),
+
+    PRIMARY KEY (race_id, driver_id),
+    FOREIGN KEY (race_id) REFERENCES races(id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (driver_id) REFERENCES drivers(id),
+    FOREIGN KEY (vehicle_id) REFERENCES vehicles(id)
+);
+
+INSERT INTO new_race_entrants (race_id, driver_id, position, vehicle_id, time, gap, best_lap, lap, reason, ping, fps, fps_locked, entered_as)
+    SELECT race_id, driver_id, position, vehicle_id, time, gap, best_lap, lap, reason, ping, fps, fps_locked, entered_as
+    FROM race_entrants;
+
+DROP TABLE race_entrants;
+
+ALTER TABLE new_race_entrants RENAME TO race_entrants;
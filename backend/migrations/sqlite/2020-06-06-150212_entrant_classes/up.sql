@@ -0,0 +1,6 @@
+-- The class of the entrant's row in the score table, such as "dnf", and of
+-- the cell with their name, such as "gt0". Unknown columns of the score table
+-- are kept in `extras`, as a JSON object keyed by column header.
+ALTER TABLE race_entrants ADD COLUMN row_class VARCHAR(255);
+ALTER TABLE race_entrants ADD COLUMN name_class VARCHAR(255);
+ALTER TABLE race_entrants ADD COLUMN extras TEXT;
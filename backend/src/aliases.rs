@@ -0,0 +1,263 @@
+use crate::db::DbConnection;
+use crate::model::{Driver, DriverAlias, DriverName};
+use crate::ratings;
+use anyhow::{bail, Context};
+use diesel::prelude::*;
+
+fn find_driver(db: &DbConnection, id: i32) -> anyhow::Result<Driver> {
+    use crate::schema::drivers::dsl::drivers;
+    with_conn!(db, |conn| drivers.find(id).first(conn).optional())?
+        .with_context(|| format!("no driver with id {}", id))
+}
+
+/// Moves all entries and aliases of driver `source_id` to `target_id` and
+/// deletes `source_id`, keeping its name as an alias. Ratings are rebuilt, as
+/// the histories of both drivers change.
+pub(crate) fn merge_drivers(
+    db: &DbConnection,
+    source_id: i32,
+    target_id: i32,
+) -> anyhow::Result<()> {
+    use crate::schema::driver_aliases::dsl::{self as aliases, driver_aliases};
+    use crate::schema::drivers::dsl::drivers;
+    use crate::schema::race_entrants::dsl::{driver_id, race_entrants, race_id};
+
+    if source_id == target_id {
+        bail!("can't merge driver {} into itself", source_id);
+    }
+    with_conn!(db, |conn| conn.transaction(|| {
+        let source = find_driver(db, source_id)?;
+        find_driver(db, target_id)?;
+
+        let target_races: Vec<i32> = race_entrants
+            .select(race_id)
+            .filter(driver_id.eq(target_id))
+            .load(conn)?;
+        let shared_race: Option<i32> = race_entrants
+            .select(race_id)
+            .filter(driver_id.eq(source_id))
+            .filter(race_id.eq_any(target_races))
+            .first(conn)
+            .optional()?;
+        if let Some(shared_race) = shared_race {
+            bail!(
+                "drivers {} and {} both entered race {}",
+                source_id,
+                target_id,
+                shared_race
+            );
+        }
+
+        diesel::update(race_entrants.filter(driver_id.eq(source_id)))
+            .set(driver_id.eq(target_id))
+            .execute(conn)?;
+        diesel::update(driver_aliases.filter(aliases::driver_id.eq(source_id)))
+            .set(aliases::driver_id.eq(target_id))
+            .execute(conn)?;
+        diesel::delete(drivers.find(source_id)).execute(conn)?;
+        DriverAlias {
+            name: source.name,
+            driver_id: target_id,
+        }
+        .insert_into(driver_aliases)
+        .execute(conn)?;
+        ratings::rebuild(db)
+    }))
+}
+
+/// Turns `alias` back into a separate driver, taking along the entries that
+/// were listed under that name, and rebuilds ratings. Returns the id of the
+/// new driver.
+pub(crate) fn split_alias(db: &DbConnection, alias: &str) -> anyhow::Result<i32> {
+    use crate::schema::driver_aliases::dsl::{driver_aliases, name};
+    use crate::schema::race_entrants::dsl::{driver_id, entered_as, race_entrants};
+
+    with_conn!(db, |conn| conn.transaction(|| {
+        let old_id = DriverAlias::resolve(db, alias)?
+            .with_context(|| format!("{:?} is not an alias", alias))?;
+        diesel::delete(driver_aliases.filter(name.eq(alias))).execute(conn)?;
+        let new_id = DriverName {
+            name: alias.to_string(),
+        }
+        .get_or_insert(db)?;
+
+        diesel::update(
+            race_entrants
+                .filter(driver_id.eq(old_id))
+                .filter(entered_as.eq(alias)),
+        )
+        .set(driver_id.eq(new_id))
+        .execute(conn)?;
+        ratings::rebuild(db)?;
+        Ok(new_id)
+    }))
+}
+
+/// Changes the name of a driver, keeping the old name as an alias so that
+/// results listing it are still attributed to the driver.
+pub(crate) fn rename_driver(db: &DbConnection, id: i32, new_name: &str) -> anyhow::Result<()> {
+    use crate::schema::driver_aliases::dsl::{self as aliases, driver_aliases};
+    use crate::schema::drivers::dsl::{drivers, name};
+
+    with_conn!(db, |conn| conn.transaction(|| {
+        let driver = find_driver(db, id)?;
+        if driver.name == new_name {
+            return Ok(());
+        }
+
+        let existing: Option<i32> = drivers
+            .select(crate::schema::drivers::dsl::id)
+            .filter(name.eq(new_name))
+            .first(conn)
+            .optional()?;
+        if let Some(existing) = existing {
+            bail!(
+                "driver {} is already named {:?}, merge the drivers instead",
+                existing,
+                new_name
+            );
+        }
+        match DriverAlias::resolve(db, new_name)? {
+            Some(owner) if owner == id => {
+                diesel::delete(driver_aliases.filter(aliases::name.eq(new_name))).execute(conn)?;
+            }
+            Some(owner) => bail!(
+                "{:?} is an alias of driver {}, split it out first",
+                new_name,
+                owner
+            ),
+            None => {}
+        }
+
+        diesel::update(drivers.find(id))
+            .set(name.eq(new_name))
+            .execute(conn)?;
+        DriverAlias {
+            name: driver.name,
+            driver_id: id,
+        }
+        .insert_into(driver_aliases)
+        .execute(conn)?;
+        Ok(())
+    }))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::{DbPool, TestDb};
+    use crate::migrations;
+    use crate::schema::{driver_aliases, driver_ratings, drivers, race_entrants};
+    use diesel::connection::SimpleConnection;
+
+    /// The races entered by each driver, by driver name.
+    fn entrants(db: &DbConnection) -> Vec<(i32, String)> {
+        with_conn!(db, |conn| race_entrants::table
+            .inner_join(drivers::table)
+            .select((race_entrants::race_id, drivers::name))
+            .order((race_entrants::race_id, drivers::name))
+            .load(conn))
+        .unwrap()
+    }
+
+    fn ratings(db: &DbConnection) -> Vec<(i32, String, f64)> {
+        with_conn!(db, |conn| driver_ratings::table
+            .inner_join(drivers::table)
+            .select((
+                driver_ratings::race_id,
+                drivers::name,
+                driver_ratings::rating
+            ))
+            .order((driver_ratings::race_id, drivers::name))
+            .load(conn))
+        .unwrap()
+    }
+
+    fn aliases(db: &DbConnection) -> Vec<(String, i32)> {
+        with_conn!(db, |conn| driver_aliases::table
+            .select((driver_aliases::name, driver_aliases::driver_id))
+            .order(driver_aliases::name)
+            .load(conn))
+        .unwrap()
+    }
+
+    #[test]
+    fn merge_and_split() {
+        let test_db = TestDb::new("aliases");
+        let db = DbPool::new(test_db.url()).unwrap().get().unwrap();
+        migrations::run_pending(&db).unwrap();
+        with_conn!(&db, |conn| conn.batch_execute(
+            "INSERT INTO tracks (id, name) VALUES (1, 'Sandpit');
+             INSERT INTO drivers (id, name) VALUES (1, 'Main'), (2, 'Alt'), (3, 'Other');
+             INSERT INTO races (id, date, track_id, laps, mode) VALUES
+                 (10, '2020-05-01', 1, 5, 'laps'),
+                 (11, '2020-05-02', 1, 5, 'laps'),
+                 (12, '2020-05-03', 1, 5, 'laps');
+             INSERT INTO race_entrants
+                 (race_id, driver_id, position, entered_as, fps_locked) VALUES
+                 (10, 1, 1, 'Main', 0),
+                 (10, 3, 2, 'Other', 0),
+                 (11, 2, 1, 'Alt', 0),
+                 (11, 3, 2, 'Other', 0),
+                 (12, 3, 1, 'Other', 0),
+                 (12, 1, 2, 'Main', 0);"
+        ))
+        .unwrap();
+        ratings::rebuild(&db).unwrap();
+        let (original_entrants, original_ratings) = (entrants(&db), ratings(&db));
+
+        assert!(merge_drivers(&db, 1, 1).is_err());
+        // Both entered race 10.
+        assert!(merge_drivers(&db, 3, 1).is_err());
+        assert!(merge_drivers(&db, 2, 4).is_err());
+        assert_eq!(entrants(&db), original_entrants);
+
+        merge_drivers(&db, 2, 1).unwrap();
+        assert!(find_driver(&db, 2).is_err());
+        assert_eq!(
+            entrants(&db),
+            [
+                (10, "Main".to_string()),
+                (10, "Other".to_string()),
+                (11, "Main".to_string()),
+                (11, "Other".to_string()),
+                (12, "Main".to_string()),
+                (12, "Other".to_string()),
+            ]
+        );
+        assert_eq!(aliases(&db), [("Alt".to_string(), 1)]);
+        let merged_ratings = ratings(&db);
+        assert_eq!(merged_ratings.len(), 6);
+        assert!(merged_ratings.iter().all(|(_, name, _)| name != "Alt"));
+        assert_ne!(merged_ratings, original_ratings);
+
+        assert!(split_alias(&db, "Main").is_err());
+        let id = split_alias(&db, "Alt").unwrap();
+        assert_eq!(find_driver(&db, id).unwrap().name, "Alt");
+        assert_eq!(aliases(&db), []);
+        assert_eq!(entrants(&db), original_entrants);
+        assert_eq!(ratings(&db), original_ratings);
+    }
+
+    #[test]
+    fn rename() {
+        let test_db = TestDb::new("rename");
+        let db = DbPool::new(test_db.url()).unwrap().get().unwrap();
+        migrations::run_pending(&db).unwrap();
+        with_conn!(&db, |conn| conn.batch_execute(
+            "INSERT INTO drivers (id, name) VALUES (1, 'Main'), (2, 'Other');"
+        ))
+        .unwrap();
+
+        rename_driver(&db, 1, "New").unwrap();
+        assert_eq!(find_driver(&db, 1).unwrap().name, "New");
+        assert_eq!(aliases(&db), [("Main".to_string(), 1)]);
+        assert!(rename_driver(&db, 1, "Other").is_err());
+        assert!(rename_driver(&db, 2, "Main").is_err());
+
+        // Taking back an old name drops it as an alias.
+        rename_driver(&db, 1, "Main").unwrap();
+        assert_eq!(find_driver(&db, 1).unwrap().name, "Main");
+        assert_eq!(aliases(&db), [("New".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,140 @@
+use super::Context;
+use crate::championships::Standing;
+use crate::db::DbConnection;
+use crate::model::{parse_points_table, Championship, Driver, NewChampionship, Race, Season};
+use anyhow::Context as _;
+use diesel::prelude::*;
+use juniper::{FieldResult, GraphQLInputObject};
+
+#[derive(Debug, Clone, GraphQLInputObject)]
+pub(crate) struct ChampionshipInput {
+    season_id: i32,
+    name: String,
+    /// Points for each finishing position, starting with the winner.
+    points: Vec<i32>,
+    /// Points for the fastest lap of each round. Defaults to none.
+    best_lap_points: Option<i32>,
+    /// How many of each driver's worst rounds don't count. Defaults to none.
+    drop_rounds: Option<i32>,
+}
+
+impl From<ChampionshipInput> for NewChampionship {
+    fn from(input: ChampionshipInput) -> NewChampionship {
+        let points: Vec<String> = input.points.iter().map(i32::to_string).collect();
+        NewChampionship {
+            season_id: input.season_id,
+            name: input.name,
+            points: points.join(","),
+            best_lap_points: input.best_lap_points.unwrap_or(0),
+            drop_rounds: input.drop_rounds.unwrap_or(0),
+        }
+    }
+}
+
+/// Loads a championship bypassing the request's loaders, which may hold a
+/// copy from before a mutation.
+pub(crate) fn stored_championship(db: &DbConnection, id: i32) -> anyhow::Result<Championship> {
+    use crate::schema::championships::dsl::championships;
+    Ok(with_conn!(db, |conn| championships.find(id).first(conn))?)
+}
+
+#[juniper::object(Context = Context)]
+impl Season {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn championships(&self, context: &Context) -> FieldResult<Vec<Championship>> {
+        Ok(context
+            .loaders
+            .season_championships(&context.db()?, self.id)?)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Championship {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn season(&self, context: &Context) -> FieldResult<Season> {
+        Ok(context
+            .loaders
+            .season(&context.db()?, self.season_id)?
+            .context("season not found")?)
+    }
+
+    /// Points for each finishing position, starting with the winner.
+    fn points_table(&self) -> FieldResult<Vec<i32>> {
+        Ok(parse_points_table(&self.points)?)
+    }
+
+    fn best_lap_points(&self) -> i32 {
+        self.best_lap_points
+    }
+
+    fn drop_rounds(&self) -> i32 {
+        self.drop_rounds
+    }
+
+    /// The races of the championship, in the order they were run.
+    fn rounds(&self, context: &Context) -> FieldResult<Vec<Race>> {
+        Ok(context
+            .loaders
+            .championship_rounds(&context.db()?, self.id)?)
+    }
+
+    /// The drivers by points, with ties broken on countback.
+    fn standings(&self, context: &Context) -> FieldResult<Vec<Standing>> {
+        Ok(context
+            .loaders
+            .championship_standings(&context.db()?, self.id)?)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Standing {
+    /// Drivers that are level on points and on countback share a position.
+    fn position(&self) -> i32 {
+        self.position
+    }
+
+    fn driver(&self, context: &Context) -> FieldResult<Driver> {
+        Ok(context
+            .loaders
+            .driver(&context.db()?, self.driver_id)?
+            .context("driver not found")?)
+    }
+
+    fn points(&self) -> i32 {
+        self.points
+    }
+
+    /// The points scored in each round, or null for rounds the driver did not
+    /// enter. Includes the points of dropped rounds.
+    fn round_points(&self) -> &[Option<i32>] {
+        &self.round_points
+    }
+
+    fn dropped_points(&self) -> i32 {
+        self.dropped_points
+    }
+
+    fn wins(&self) -> i32 {
+        self.finishes.first().copied().unwrap_or(0)
+    }
+
+    /// How many times the driver finished in each position, starting with
+    /// wins, as used for countback.
+    fn finishes(&self) -> &[i32] {
+        &self.finishes
+    }
+}
@@ -0,0 +1,293 @@
+use super::pagination::{finish_page, page_size, PageInfo};
+use super::Context;
+use crate::db::DbConnection;
+use crate::model::Driver;
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Date, Integer, Nullable};
+use juniper::{FieldResult, GraphQLObject};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(Context = Context, Scalar = juniper::DefaultScalarValue)]
+pub(crate) struct DriverConnection {
+    edges: Vec<DriverEdge>,
+    page_info: PageInfo,
+    total_count: i32,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(Context = Context, Scalar = juniper::DefaultScalarValue)]
+pub(crate) struct DriverEdge {
+    cursor: String,
+    node: Driver,
+}
+
+/// Drivers are ordered and paged by their (unique) name.
+pub(crate) fn drivers(
+    context: &Context,
+    first: Option<i32>,
+    after: Option<String>,
+    search: Option<String>,
+    fuzzy: bool,
+) -> FieldResult<DriverConnection> {
+    use crate::schema::drivers::dsl::*;
+
+    let limit = page_size(first)?;
+    let pattern = search.map(|search| {
+        if fuzzy {
+            fuzzy_pattern(&search)
+        } else {
+            format!("{}%", escape_like(&search))
+        }
+    });
+
+    let db = context.db()?;
+    let (total_count, mut nodes) = with_conn!(&db, |conn| {
+        let filtered = || {
+            let mut query = drivers.into_boxed();
+            if let Some(pattern) = &pattern {
+                query = query.filter(name.like(pattern.clone()).escape('\\'));
+            }
+            query
+        };
+
+        let total_count: i64 = filtered().count().get_result(conn)?;
+
+        let mut query = filtered();
+        if let Some(after) = &after {
+            query = query.filter(name.gt(after.clone()));
+        }
+        let nodes: Vec<Driver> = query.order(name.asc()).limit(limit + 1).load(conn)?;
+        (total_count, nodes)
+    });
+
+    let page_info = finish_page(&mut nodes, limit, |driver| driver.name.clone());
+    context.loaders.prime_drivers(&nodes);
+    Ok(DriverConnection {
+        edges: nodes
+            .into_iter()
+            .map(|node| DriverEdge {
+                cursor: node.name.clone(),
+                node,
+            })
+            .collect(),
+        page_info,
+        total_count: total_count as i32,
+    })
+}
+
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || c == '%' || c == '_' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Matches names that contain the characters of `search` in order, so that
+/// `grm` finds `R1O_GrmPls`.
+fn fuzzy_pattern(search: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in search.chars().filter(|c| !c.is_whitespace()) {
+        pattern.push_str(&escape_like(&c.to_string()));
+        pattern.push('%');
+    }
+    pattern
+}
+
+/// Career statistics of a driver, aggregated over all of their entries.
+#[derive(Debug, Clone, Default, GraphQLObject)]
+pub(crate) struct DriverStats {
+    /// Number of races entered, including ones the driver did not start.
+    race_count: i32,
+    wins: i32,
+    podiums: i32,
+    dnf_count: i32,
+    dns_count: i32,
+    dsq_count: i32,
+    /// Average position over the races the driver finished.
+    average_position: Option<f64>,
+    first_race_date: Option<NaiveDate>,
+    last_race_date: Option<NaiveDate>,
+}
+
+#[derive(QueryableByName)]
+struct StatsRow {
+    #[sql_type = "Integer"]
+    driver_id: i32,
+    #[sql_type = "BigInt"]
+    race_count: i64,
+    #[sql_type = "BigInt"]
+    wins: i64,
+    #[sql_type = "BigInt"]
+    podiums: i64,
+    #[sql_type = "BigInt"]
+    dnf_count: i64,
+    #[sql_type = "BigInt"]
+    dns_count: i64,
+    #[sql_type = "BigInt"]
+    dsq_count: i64,
+    #[sql_type = "BigInt"]
+    finishes: i64,
+    #[sql_type = "Nullable<BigInt>"]
+    position_sum: Option<i64>,
+    #[sql_type = "Nullable<Date>"]
+    first_race_date: Option<NaiveDate>,
+    #[sql_type = "Nullable<Date>"]
+    last_race_date: Option<NaiveDate>,
+}
+
+// Only COUNT and a cast SUM are used so that the result types are the same on
+// every backend; MySQL would otherwise return DECIMAL for SUM and AVG.
+const STATS_QUERY: &str = "
+    SELECT
+        e.driver_id,
+        COUNT(*) AS race_count,
+        COUNT(CASE WHEN e.reason IS NULL AND e.position = 1 THEN 1 END) AS wins,
+        COUNT(CASE WHEN e.reason IS NULL AND e.position <= 3 THEN 1 END) AS podiums,
+        COUNT(CASE WHEN e.reason = 'dnf' THEN 1 END) AS dnf_count,
+        COUNT(CASE WHEN e.reason = 'dns' THEN 1 END) AS dns_count,
+        COUNT(CASE WHEN e.reason = 'dsq' THEN 1 END) AS dsq_count,
+        COUNT(CASE WHEN e.reason IS NULL THEN e.position END) AS finishes,
+        CAST(SUM(CASE WHEN e.reason IS NULL THEN e.position END) AS SIGNED) AS position_sum,
+        MIN(r.date) AS first_race_date,
+        MAX(r.date) AS last_race_date
+    FROM race_entrants e
+    INNER JOIN races r ON r.id = e.race_id
+    WHERE e.driver_id IN ({})
+    GROUP BY e.driver_id
+";
+
+/// Loads the statistics of several drivers at once. Drivers without any
+/// entries are left out.
+pub(crate) fn driver_stats(
+    db: &DbConnection,
+    driver_ids: &[i32],
+) -> anyhow::Result<HashMap<i32, DriverStats>> {
+    if driver_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    // The ids are integers, so they can be formatted into the query directly
+    // instead of binding a variable number of parameters.
+    let ids: Vec<String> = driver_ids.iter().map(i32::to_string).collect();
+    let query = STATS_QUERY.replace("{}", &ids.join(", "));
+    let rows: Vec<StatsRow> = with_conn!(db, |conn| diesel::sql_query(query).load(conn))?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.driver_id, row.into()))
+        .collect())
+}
+
+impl From<StatsRow> for DriverStats {
+    fn from(row: StatsRow) -> DriverStats {
+        DriverStats {
+            race_count: row.race_count as i32,
+            wins: row.wins as i32,
+            podiums: row.podiums as i32,
+            dnf_count: row.dnf_count as i32,
+            dns_count: row.dns_count as i32,
+            dsq_count: row.dsq_count as i32,
+            average_position: row
+                .position_sum
+                .filter(|_| row.finishes > 0)
+                .map(|sum| sum as f64 / row.finishes as f64),
+            first_race_date: row.first_race_date,
+            last_race_date: row.last_race_date,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn like_patterns() {
+        assert_eq!(escape_like("100%_a\\b"), "100\\%\\_a\\\\b");
+        assert_eq!(fuzzy_pattern("g p_"), "%g%p%\\_%");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn stats_query() {
+        use crate::api::run_query;
+        use crate::db::TestDb;
+        use diesel::connection::SimpleConnection;
+        use serde_json::json;
+
+        let db = TestDb::new("driver_stats");
+        let context = Context::new(db.url()).unwrap();
+        with_conn!(&context.db().unwrap(), |conn| conn.batch_execute(
+            "INSERT INTO tracks (id, name) VALUES (1, 'Sandpit');
+             INSERT INTO drivers (id, name) VALUES (1, 'A'), (2, 'B'), (3, 'C');
+             INSERT INTO races (id, date, track_id, laps, mode) VALUES
+                 (10, '2020-05-01', 1, 5, 'laps'),
+                 (11, '2020-05-02', 1, 5, 'laps'),
+                 (12, '2020-05-03', 1, 5, 'laps'),
+                 (13, '2020-05-04', 1, 5, 'laps');
+             INSERT INTO race_entrants (race_id, driver_id, position, reason, fps_locked) VALUES
+                 (10, 1, 1, NULL, 0),
+                 (10, 2, 2, NULL, 0),
+                 (11, 1, 3, NULL, 0),
+                 (11, 2, 1, 'dsq', 0),
+                 (12, 1, 4, 'dnf', 0),
+                 (12, 2, NULL, 'dns', 0),
+                 (13, 1, 5, NULL, 0);"
+        ))
+        .unwrap();
+
+        let stats = "stats {
+            raceCount wins podiums dnfCount dnsCount dsqCount
+            averagePosition firstRaceDate lastRaceDate
+        }";
+        let data = run_query(
+            &context,
+            &format!(
+                "{{ a: driver(id: 1) {{ {0} }} b: driver(id: 2) {{ {0} }} c: driver(id: 3) {{ {0} }} }}",
+                stats
+            ),
+        );
+        assert_eq!(
+            data,
+            json!({
+                "a": { "stats": {
+                    "raceCount": 4,
+                    "wins": 1,
+                    "podiums": 2,
+                    "dnfCount": 1,
+                    "dnsCount": 0,
+                    "dsqCount": 0,
+                    "averagePosition": 3.0,
+                    "firstRaceDate": "2020-05-01",
+                    "lastRaceDate": "2020-05-04",
+                }},
+                "b": { "stats": {
+                    "raceCount": 3,
+                    "wins": 0,
+                    "podiums": 1,
+                    "dnfCount": 0,
+                    "dnsCount": 1,
+                    "dsqCount": 1,
+                    "averagePosition": 2.0,
+                    "firstRaceDate": "2020-05-01",
+                    "lastRaceDate": "2020-05-03",
+                }},
+                "c": { "stats": {
+                    "raceCount": 0,
+                    "wins": 0,
+                    "podiums": 0,
+                    "dnfCount": 0,
+                    "dnsCount": 0,
+                    "dsqCount": 0,
+                    "averagePosition": null,
+                    "firstRaceDate": null,
+                    "lastRaceDate": null,
+                }},
+            })
+        );
+    }
+}
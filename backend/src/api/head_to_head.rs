@@ -0,0 +1,261 @@
+use super::Context;
+use crate::model::{Driver, Race, RaceEntrant, Reason, Track};
+use crate::ratings::Placing;
+use anyhow::Context as _;
+use juniper::{FieldResult, GraphQLObject};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+
+/// How two drivers compare over the races they both entered.
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(Context = Context, Scalar = juniper::DefaultScalarValue)]
+pub(crate) struct HeadToHead {
+    driver_a: Driver,
+    driver_b: Driver,
+    shared_race_count: i32,
+    /// Races in which driver A finished ahead of driver B. Not finishing
+    /// ranks below every finisher, and races either driver did not start
+    /// are not counted.
+    a_ahead: i32,
+    b_ahead: i32,
+    /// The average of A's position minus B's over the races both finished,
+    /// so negative when A usually finishes ahead.
+    average_position_delta: Option<f64>,
+    /// The best laps of both drivers on every track they have both set one
+    /// on, in any race, by track name.
+    best_laps: Vec<HeadToHeadLaps>,
+    /// The races both drivers entered, newest first.
+    races: Vec<SharedRace>,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(Context = Context, Scalar = juniper::DefaultScalarValue)]
+pub(crate) struct HeadToHeadLaps {
+    track: Track,
+    a: RaceEntrant,
+    b: RaceEntrant,
+    /// A's best lap minus B's, so negative when A is faster.
+    delta: i32,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(Context = Context, Scalar = juniper::DefaultScalarValue)]
+pub(crate) struct SharedRace {
+    race: Race,
+    a: RaceEntrant,
+    b: RaceEntrant,
+}
+
+pub(crate) fn head_to_head(context: &Context, a: i32, b: i32) -> FieldResult<HeadToHead> {
+    if a == b {
+        return Err("can't compare a driver with themselves".into());
+    }
+    let db = context.db()?;
+    let loaders = &context.loaders;
+    let driver = |id| -> FieldResult<Driver> {
+        Ok(loaders
+            .driver(&db, id)?
+            .with_context(|| format!("no driver with id {}", id))?)
+    };
+    let (driver_a, driver_b) = (driver(a)?, driver(b)?);
+    let (entries_a, entries_b) = (
+        loaders.driver_entries(&db, a)?,
+        loaders.driver_entries(&db, b)?,
+    );
+    let race = |id| -> FieldResult<Race> { Ok(loaders.race(&db, id)?.context("race not found")?) };
+
+    let mut races = Vec::new();
+    let (mut a_ahead, mut b_ahead) = (0, 0);
+    let (mut position_delta, mut both_finished) = (0, 0);
+    for entry_a in &entries_a {
+        let entry_b = match entries_b.iter().find(|b| b.race_id == entry_a.race_id) {
+            Some(entry_b) => entry_b,
+            None => continue,
+        };
+        let placing_a = Placing::new(entry_a.position, entry_a.reason);
+        let placing_b = Placing::new(entry_b.position, entry_b.reason);
+        if let (Some(placing_a), Some(placing_b)) = (placing_a, placing_b) {
+            match placing_a.cmp(&placing_b) {
+                Ordering::Less => a_ahead += 1,
+                Ordering::Greater => b_ahead += 1,
+                Ordering::Equal => {}
+            }
+        }
+        if let (Some(Placing::Finished(position_a)), Some(Placing::Finished(position_b))) =
+            (placing_a, placing_b)
+        {
+            position_delta += position_a - position_b;
+            both_finished += 1;
+        }
+        races.push(SharedRace {
+            race: race(entry_a.race_id)?,
+            a: entry_a.clone(),
+            b: entry_b.clone(),
+        });
+    }
+    races.sort_by_key(|shared| Reverse((shared.race.date, shared.race.id)));
+
+    // The fastest lap of each driver on each track, keyed by track id.
+    // Laps from disqualified entrants don't count, as on the track leaderboards.
+    type BestLaps = BTreeMap<i32, (i32, RaceEntrant)>;
+    let best_laps = |entries: &[RaceEntrant]| -> FieldResult<BestLaps> {
+        let mut best = BestLaps::new();
+        for entry in entries {
+            let lap = match entry.best_lap {
+                Some(lap) if entry.reason != Some(Reason::Dsq) => lap,
+                _ => continue,
+            };
+            let track_id = race(entry.race_id)?.track_id;
+            let faster = match best.get(&track_id) {
+                Some(&(best, _)) => lap < best,
+                None => true,
+            };
+            if faster {
+                best.insert(track_id, (lap, entry.clone()));
+            }
+        }
+        Ok(best)
+    };
+    let (mut laps_a, laps_b) = (best_laps(&entries_a)?, best_laps(&entries_b)?);
+    let mut laps = Vec::new();
+    for (track_id, (lap_b, b)) in laps_b {
+        let (lap_a, a) = match laps_a.remove(&track_id) {
+            Some(best) => best,
+            None => continue,
+        };
+        laps.push(HeadToHeadLaps {
+            track: loaders.track(&db, track_id)?.context("track not found")?,
+            a,
+            b,
+            delta: lap_a - lap_b,
+        });
+    }
+    laps.sort_by(|x, y| x.track.name.cmp(&y.track.name));
+
+    Ok(HeadToHead {
+        driver_a,
+        driver_b,
+        shared_race_count: races.len() as i32,
+        a_ahead,
+        b_ahead,
+        average_position_delta: if both_finished > 0 {
+            Some(position_delta as f64 / both_finished as f64)
+        } else {
+            None
+        },
+        best_laps: laps,
+        races,
+    })
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::api::run_query;
+    use crate::db::TestDb;
+    use diesel::connection::SimpleConnection;
+    use serde_json::json;
+
+    #[test]
+    fn head_to_head_queries() {
+        let db = TestDb::new("head_to_head");
+        let context = Context::new(db.url()).unwrap();
+        with_conn!(&context.db().unwrap(), |conn| conn.batch_execute(
+            "INSERT INTO tracks (id, name) VALUES (1, 'Sandpit'), (2, 'Monaco');
+             INSERT INTO drivers (id, name) VALUES (1, 'A'), (2, 'B'), (3, 'C');
+             INSERT INTO races (id, date, track_id, laps, mode) VALUES
+                 (10, '2020-05-01', 1, 5, 'laps'),
+                 (11, '2020-05-02', 1, 5, 'laps'),
+                 (12, '2020-05-03', 2, 5, 'laps'),
+                 (13, '2020-05-04', 2, 5, 'laps'),
+                 (14, '2020-05-05', 1, 5, 'laps'),
+                 (15, '2020-05-06', 2, 5, 'laps');
+             INSERT INTO race_entrants
+                 (race_id, driver_id, position, best_lap, reason, fps_locked) VALUES
+                 (10, 1, 1, 60000, NULL, 0),
+                 (10, 2, 2, 59000, NULL, 0),
+                 (11, 1, 3, 50000, 'dsq', 0),
+                 (11, 2, 4, NULL, NULL, 0),
+                 (12, 1, NULL, NULL, 'dnf', 0),
+                 (12, 2, NULL, NULL, 'dsq', 0),
+                 (13, 1, 2, 70000, NULL, 0),
+                 (13, 2, NULL, NULL, 'dns', 0),
+                 (14, 1, 1, 65000, NULL, 0),
+                 (14, 3, 2, 66000, NULL, 0),
+                 (15, 2, 1, 71000, NULL, 0),
+                 (15, 1, 2, 72000, NULL, 0);"
+        ))
+        .unwrap();
+
+        let data = run_query(
+            &context,
+            r#"{
+                headToHead(a: 1, b: 2) {
+                    driverA { name }
+                    driverB { name }
+                    sharedRaceCount
+                    aAhead
+                    bAhead
+                    averagePositionDelta
+                    bestLaps { track { name } a { race { id } } b { race { id } } delta }
+                    races { race { id } }
+                }
+                none: headToHead(a: 2, b: 3) {
+                    sharedRaceCount
+                    aAhead
+                    bAhead
+                    averagePositionDelta
+                    bestLaps { track { name } delta }
+                    races { race { id } }
+                }
+            }"#,
+        );
+        let races = |ids: &[i32]| -> Vec<_> {
+            ids.iter()
+                .map(|id| json!({ "race": { "id": id } }))
+                .collect()
+        };
+        assert_eq!(
+            data,
+            json!({
+                "headToHead": {
+                    "driverA": { "name": "A" },
+                    "driverB": { "name": "B" },
+                    "sharedRaceCount": 5,
+                    // A won race 10; B beat a disqualification in 11 and won
+                    // 15. Both retired in 12, and B did not start 13.
+                    "aAhead": 1,
+                    "bAhead": 2,
+                    "averagePositionDelta": 0.0,
+                    "bestLaps": [
+                        {
+                            "track": { "name": "Monaco" },
+                            "a": { "race": { "id": 13 } },
+                            "b": { "race": { "id": 15 } },
+                            "delta": -1000,
+                        },
+                        {
+                            "track": { "name": "Sandpit" },
+                            "a": { "race": { "id": 10 } },
+                            "b": { "race": { "id": 10 } },
+                            "delta": 1000,
+                        },
+                    ],
+                    "races": races(&[15, 13, 12, 11, 10]),
+                },
+                "none": {
+                    "sharedRaceCount": 0,
+                    "aAhead": 0,
+                    "bAhead": 0,
+                    "averagePositionDelta": null,
+                    "bestLaps": [{ "track": { "name": "Sandpit" }, "delta": -7000 }],
+                    "races": [],
+                },
+            })
+        );
+
+        assert!(head_to_head(&context, 1, 1).is_err());
+        assert!(head_to_head(&context, 1, 4).is_err());
+        assert!(head_to_head(&context, 4, 1).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use super::Context;
+use crate::model::{Race, RaceMode, Reason};
+use crate::parser::{self, parse_race, GapMode, ParseOptions};
+use crate::AddMode;
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use juniper::{FieldResult, GraphQLInputObject, GraphQLObject};
+use std::time::Duration;
+
+/// A race given as a list of entrants instead of a results document. Times
+/// are in milliseconds.
+#[derive(Debug, Clone, GraphQLInputObject)]
+pub(crate) struct RaceInput {
+    track: String,
+    mode: RaceMode,
+    laps: Option<i32>,
+    minutes: Option<i32>,
+    /// The entrants in finishing order.
+    entrants: Vec<EntrantInput>,
+}
+
+#[derive(Debug, Clone, GraphQLInputObject)]
+pub(crate) struct EntrantInput {
+    position: i32,
+    name: String,
+    vehicle: String,
+    time: Option<i32>,
+    best_lap: Option<i32>,
+    lap: Option<i32>,
+    reason: Option<Reason>,
+    ping: Option<i32>,
+    fps: Option<i32>,
+    fps_locked: Option<bool>,
+}
+
+/// A race as it would be stored, returned by the dry-run mutations.
+#[derive(Debug, Clone, GraphQLObject)]
+pub(crate) struct ParsedRace {
+    track: String,
+    mode: RaceMode,
+    laps: Option<i32>,
+    minutes: Option<i32>,
+    entrants: Vec<ParsedEntrant>,
+    /// Whether a race with this id is already stored and would be replaced.
+    replaces: bool,
+    warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+pub(crate) struct ParsedEntrant {
+    position: i32,
+    driver_name: String,
+    vehicle: String,
+    time: Option<i32>,
+    gap: Option<i32>,
+    best_lap: Option<i32>,
+    lap: Option<i32>,
+    reason: Option<Reason>,
+    ping: Option<i32>,
+    fps: Option<i32>,
+    fps_locked: bool,
+    row_class: Option<String>,
+    name_class: Option<String>,
+    extras: Vec<EntrantExtra>,
+}
+
+/// A cell of a results table column that isn't otherwise stored.
+#[derive(Debug, Clone, GraphQLObject)]
+pub(crate) struct EntrantExtra {
+    /// The header of the column.
+    name: String,
+    value: String,
+}
+
+impl EntrantExtra {
+    pub(crate) fn from_pairs(extras: Vec<(String, String)>) -> Vec<EntrantExtra> {
+        extras
+            .into_iter()
+            .map(|(name, value)| EntrantExtra { name, value })
+            .collect()
+    }
+}
+
+pub(crate) fn parse_results(
+    results: &str,
+    gaps: Option<GapMode>,
+    lenient: Option<bool>,
+) -> anyhow::Result<parser::Race> {
+    let options = ParseOptions {
+        gaps: gaps.unwrap_or_default(),
+        lenient: lenient.unwrap_or(false),
+    };
+    Ok(parse_race(results, &options)?)
+}
+
+impl RaceInput {
+    /// The race to store, or an error naming the first field that doesn't
+    /// make sense: a negative time, or a length that doesn't match the mode.
+    pub(crate) fn into_race(self) -> FieldResult<parser::Race> {
+        match (self.mode, self.laps, self.minutes) {
+            (RaceMode::Laps, Some(_), None)
+            | (RaceMode::Minutes, None, Some(_))
+            | (RaceMode::Rally, None, None)
+            | (RaceMode::PointToPoint, None, None) => {}
+            (mode, _, _) => {
+                let expected = match mode {
+                    RaceMode::Laps => "`laps` but not `minutes`",
+                    RaceMode::Minutes => "`minutes` but not `laps`",
+                    RaceMode::Rally | RaceMode::PointToPoint => "neither `laps` nor `minutes`",
+                };
+                return Err(format!("a {:?} race must have {}", mode, expected).into());
+            }
+        }
+
+        let millis = |position: i32, field: &str, ms: Option<i32>| -> FieldResult<_> {
+            match ms {
+                Some(ms) if ms < 0 => Err(format!(
+                    "`{}` of the entrant in position {} must not be negative",
+                    field, position
+                )
+                .into()),
+                ms => Ok(ms.map(|ms| Duration::from_millis(ms as u64))),
+            }
+        };
+        let mut entrants = Vec::with_capacity(self.entrants.len());
+        let mut leader_time = None;
+        for entrant in self.entrants {
+            let time = millis(entrant.position, "time", entrant.time)?;
+            leader_time = leader_time.or(time);
+            entrants.push(parser::Entrant {
+                position: entrant.position,
+                name: entrant.name,
+                vehicle: entrant.vehicle,
+                time,
+                gap: time.and_then(|time| time.checked_sub(leader_time?)),
+                best_lap: millis(entrant.position, "bestLap", entrant.best_lap)?,
+                lap: entrant.lap,
+                reason: entrant.reason.map(Into::into),
+                ping: entrant.ping,
+                fps: entrant.fps,
+                fps_locked: entrant.fps_locked.unwrap_or(false),
+                row_class: None,
+                name_class: None,
+                extras: Vec::new(),
+            });
+        }
+        let mut race = parser::Race {
+            track: self.track,
+            mode: self.mode.into(),
+            laps: self.laps,
+            minutes: self.minutes,
+            entrants,
+            warnings: Vec::new(),
+        };
+        race.check_time_order();
+        Ok(race)
+    }
+}
+
+/// Stores `race`, parsed from the results document `source` if there is one,
+/// and returns it as it was stored.
+pub(crate) fn add_race(
+    context: &Context,
+    id: i32,
+    date: NaiveDate,
+    race: parser::Race,
+    source: Option<&str>,
+    replace: bool,
+) -> FieldResult<Race> {
+    use crate::schema::races::dsl::races;
+
+    let mode = if replace {
+        AddMode::Replace
+    } else {
+        AddMode::Insert
+    };
+    let db = context.db()?;
+    race.insert_into(&db, id, date, mode, source, &context.events)?;
+    Ok(with_conn!(&db, |conn| races.find(id).first(conn))?)
+}
+
+/// Describes what `add_race` would store, without writing anything.
+pub(crate) fn preview_race(
+    context: &Context,
+    id: i32,
+    race: parser::Race,
+) -> FieldResult<ParsedRace> {
+    use crate::schema::races::dsl::races;
+
+    let db = context.db()?;
+    let replaces = with_conn!(&db, |conn| diesel::select(diesel::dsl::exists(
+        races.find(id)
+    ))
+    .get_result(conn))?;
+
+    let millis = |dur: Option<Duration>| dur.map(|dur| dur.as_millis() as i32);
+    Ok(ParsedRace {
+        track: race.track,
+        mode: race.mode.into(),
+        laps: race.laps,
+        minutes: race.minutes,
+        entrants: race
+            .entrants
+            .into_iter()
+            .map(|entrant| ParsedEntrant {
+                position: entrant.position,
+                driver_name: entrant.name,
+                vehicle: entrant.vehicle,
+                time: millis(entrant.time),
+                gap: millis(entrant.gap),
+                best_lap: millis(entrant.best_lap),
+                lap: entrant.lap,
+                reason: entrant.reason.map(Into::into),
+                ping: entrant.ping,
+                fps: entrant.fps,
+                fps_locked: entrant.fps_locked,
+                row_class: entrant.row_class,
+                name_class: entrant.name_class,
+                extras: EntrantExtra::from_pairs(entrant.extras),
+            })
+            .collect(),
+        replaces,
+        warnings: race.warnings.iter().map(ToString::to_string).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entrant(position: i32, time: Option<i32>) -> EntrantInput {
+        EntrantInput {
+            position,
+            name: format!("driver{}", position),
+            vehicle: "Formula One".to_string(),
+            time,
+            best_lap: None,
+            lap: None,
+            reason: None,
+            ping: None,
+            fps: None,
+            fps_locked: None,
+        }
+    }
+
+    fn race(mode: RaceMode, laps: Option<i32>, minutes: Option<i32>) -> RaceInput {
+        RaceInput {
+            track: "Monaco".to_string(),
+            mode,
+            laps,
+            minutes,
+            entrants: vec![entrant(1, Some(60_000)), entrant(2, Some(61_500))],
+        }
+    }
+
+    #[test]
+    fn race_input() {
+        let parsed = race(RaceMode::Laps, Some(5), None).into_race().unwrap();
+        assert_eq!(parsed.entrants[1].gap, Some(Duration::from_millis(1500)));
+        assert!(race(RaceMode::Minutes, None, Some(10)).into_race().is_ok());
+        assert!(race(RaceMode::Rally, None, None).into_race().is_ok());
+
+        assert!(race(RaceMode::Laps, None, None).into_race().is_err());
+        assert!(race(RaceMode::Laps, Some(5), Some(10)).into_race().is_err());
+        assert!(race(RaceMode::Minutes, Some(5), None).into_race().is_err());
+        assert!(race(RaceMode::PointToPoint, Some(5), None)
+            .into_race()
+            .is_err());
+
+        let mut negative = race(RaceMode::Rally, None, None);
+        negative.entrants[1].time = Some(-1);
+        assert!(negative.into_race().is_err());
+        let mut negative = race(RaceMode::Rally, None, None);
+        negative.entrants[0].best_lap = Some(-1);
+        assert!(negative.into_race().is_err());
+    }
+}
@@ -0,0 +1,571 @@
+use super::drivers::{self, DriverStats};
+use super::ratings::RatingChange;
+use super::tracks::TrackWins;
+use super::vehicles::{self, VehicleStats, VehicleStatsKey};
+use crate::championships::{self, Standing};
+use crate::db::DbConnection;
+use crate::model::{
+    Championship, Driver, DriverAlias, Race, RaceEntrant, Reason, Season, Track, Vehicle,
+    VEHICLE_COLUMNS,
+};
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// Caches lookups by key for the duration of a request and loads them in
+/// batches.
+///
+/// Juniper resolves fields one at a time, so the keys of sibling fields can't
+/// be collected while the query runs. Instead, whenever a list of objects is
+/// loaded, the keys that those objects refer to are registered with `prime`,
+/// and the first lookup that misses the cache fetches all of them at once.
+pub(crate) struct Loader<K, V> {
+    state: Mutex<LoaderState<K, V>>,
+}
+
+struct LoaderState<K, V> {
+    cache: HashMap<K, Option<V>>,
+    pending: HashSet<K>,
+}
+
+impl<K, V> Default for Loader<K, V> {
+    fn default() -> Self {
+        Loader {
+            state: Mutex::new(LoaderState {
+                cache: HashMap::new(),
+                pending: HashSet::new(),
+            }),
+        }
+    }
+}
+
+impl<K, V> Loader<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    /// Registers keys that are likely to be looked up later.
+    pub(crate) fn prime(&self, keys: impl IntoIterator<Item = K>) {
+        let mut state = self.state.lock().unwrap();
+        for key in keys {
+            if !state.cache.contains_key(&key) {
+                state.pending.insert(key);
+            }
+        }
+    }
+
+    /// Looks up `key`, calling `fetch` with it and every pending key if it is
+    /// not cached yet.
+    pub(crate) fn load<F>(&self, key: K, fetch: F) -> anyhow::Result<Option<V>>
+    where
+        F: FnOnce(&[K]) -> anyhow::Result<HashMap<K, V>>,
+    {
+        let mut state = self.state.lock().unwrap();
+        if let Some(value) = state.cache.get(&key) {
+            return Ok(value.clone());
+        }
+
+        state.pending.insert(key.clone());
+        let keys: Vec<K> = state.pending.drain().collect();
+        let mut values = fetch(&keys)?;
+        for key in keys {
+            let value = values.remove(&key);
+            state.cache.insert(key, value);
+        }
+        Ok(state.cache[&key].clone())
+    }
+}
+
+/// The loaders for a single request.
+#[derive(Default)]
+pub(crate) struct Loaders {
+    races: Loader<i32, Race>,
+    drivers: Loader<i32, Driver>,
+    race_entrants: Loader<i32, Vec<RaceEntrant>>,
+    driver_entries: Loader<i32, Vec<RaceEntrant>>,
+    driver_stats: Loader<i32, DriverStats>,
+    driver_aliases: Loader<i32, Vec<String>>,
+    driver_ratings: Loader<i32, Vec<RatingChange>>,
+    driver_vehicles: Loader<i32, Vec<VehicleStats>>,
+    tracks: Loader<i32, Track>,
+    track_best_laps: Loader<i32, Vec<RaceEntrant>>,
+    track_wins: Loader<i32, Vec<TrackWins>>,
+    vehicles: Loader<i32, Vehicle>,
+    vehicle_stats: Loader<i32, VehicleStats>,
+    vehicle_best_laps: Loader<i32, Vec<RaceEntrant>>,
+    seasons: Loader<i32, Season>,
+    season_championships: Loader<i32, Vec<Championship>>,
+    championship_rounds: Loader<i32, Vec<Race>>,
+    championship_standings: Loader<i32, Vec<Standing>>,
+}
+
+impl Loaders {
+    pub(crate) fn prime_races(&self, races: &[Race]) {
+        self.race_entrants.prime(races.iter().map(|race| race.id));
+        self.tracks.prime(races.iter().map(|race| race.track_id));
+    }
+
+    pub(crate) fn prime_tracks(&self, tracks: &[Track]) {
+        let ids = || tracks.iter().map(|track| track.id);
+        self.track_best_laps.prime(ids());
+        self.track_wins.prime(ids());
+    }
+
+    pub(crate) fn prime_drivers(&self, drivers: &[Driver]) {
+        let ids = || drivers.iter().map(|driver| driver.id);
+        self.driver_entries.prime(ids());
+        self.driver_stats.prime(ids());
+        self.driver_aliases.prime(ids());
+        self.driver_ratings.prime(ids());
+        self.driver_vehicles.prime(ids());
+    }
+
+    pub(crate) fn prime_vehicles(&self, vehicles: &[Vehicle]) {
+        let ids = || vehicles.iter().map(|vehicle| vehicle.id);
+        self.vehicle_stats.prime(ids());
+        self.vehicle_best_laps.prime(ids());
+    }
+
+    pub(crate) fn prime_seasons(&self, seasons: &[Season]) {
+        self.season_championships
+            .prime(seasons.iter().map(|season| season.id));
+    }
+
+    pub(crate) fn prime_championships(&self, championships: &[Championship]) {
+        let ids = || championships.iter().map(|championship| championship.id);
+        self.seasons.prime(
+            championships
+                .iter()
+                .map(|championship| championship.season_id),
+        );
+        self.championship_rounds.prime(ids());
+        self.championship_standings.prime(ids());
+    }
+
+    fn prime_entrants(&self, entrants: &[RaceEntrant]) {
+        self.races.prime(entrants.iter().map(|entrant| entrant.race_id));
+        self.drivers
+            .prime(entrants.iter().map(|entrant| entrant.driver_id));
+        self.vehicles
+            .prime(entrants.iter().filter_map(|entrant| entrant.vehicle_id));
+    }
+
+    pub(crate) fn race(&self, db: &DbConnection, id: i32) -> anyhow::Result<Option<Race>> {
+        let race = self.races.load(id, |ids| {
+            use crate::schema::races::dsl;
+            let races: Vec<Race> = with_conn!(db, |conn| dsl::races
+                .filter(dsl::id.eq_any(ids))
+                .load(conn))?;
+            self.prime_races(&races);
+            Ok(races.into_iter().map(|race| (race.id, race)).collect())
+        })?;
+        Ok(race)
+    }
+
+    pub(crate) fn driver(&self, db: &DbConnection, id: i32) -> anyhow::Result<Option<Driver>> {
+        self.drivers.load(id, |ids| {
+            use crate::schema::drivers::dsl;
+            let drivers: Vec<Driver> = with_conn!(db, |conn| dsl::drivers
+                .filter(dsl::id.eq_any(ids))
+                .load(conn))?;
+            self.prime_drivers(&drivers);
+            Ok(drivers
+                .into_iter()
+                .map(|driver| (driver.id, driver))
+                .collect())
+        })
+    }
+
+    pub(crate) fn race_entrants(
+        &self,
+        db: &DbConnection,
+        race_id: i32,
+    ) -> anyhow::Result<Vec<RaceEntrant>> {
+        let entrants = self.race_entrants.load(race_id, |ids| {
+            use crate::schema::race_entrants::dsl;
+            let entrants: Vec<RaceEntrant> = with_conn!(db, |conn| dsl::race_entrants
+                .filter(dsl::race_id.eq_any(ids))
+                .order((dsl::race_id, dsl::position))
+                .load(conn))?;
+            self.prime_entrants(&entrants);
+            Ok(group_by(entrants, |entrant| entrant.race_id))
+        })?;
+        Ok(entrants.unwrap_or_default())
+    }
+
+    pub(crate) fn driver_entries(
+        &self,
+        db: &DbConnection,
+        driver_id: i32,
+    ) -> anyhow::Result<Vec<RaceEntrant>> {
+        let entries = self.driver_entries.load(driver_id, |ids| {
+            use crate::schema::race_entrants::dsl;
+            let entries: Vec<RaceEntrant> = with_conn!(db, |conn| dsl::race_entrants
+                .filter(dsl::driver_id.eq_any(ids))
+                .order((dsl::driver_id, dsl::race_id))
+                .load(conn))?;
+            self.prime_entrants(&entries);
+            Ok(group_by(entries, |entry| entry.driver_id))
+        })?;
+        Ok(entries.unwrap_or_default())
+    }
+
+    pub(crate) fn driver_stats(
+        &self,
+        db: &DbConnection,
+        driver_id: i32,
+    ) -> anyhow::Result<DriverStats> {
+        let stats = self
+            .driver_stats
+            .load(driver_id, |ids| drivers::driver_stats(db, ids))?;
+        Ok(stats.unwrap_or_default())
+    }
+
+    pub(crate) fn driver_aliases(
+        &self,
+        db: &DbConnection,
+        driver_id: i32,
+    ) -> anyhow::Result<Vec<String>> {
+        let aliases = self.driver_aliases.load(driver_id, |ids| {
+            use crate::schema::driver_aliases::dsl;
+            let aliases: Vec<DriverAlias> = with_conn!(db, |conn| dsl::driver_aliases
+                .filter(dsl::driver_id.eq_any(ids))
+                .order(dsl::name)
+                .load(conn))?;
+            let mut groups = HashMap::new();
+            for alias in aliases {
+                groups
+                    .entry(alias.driver_id)
+                    .or_insert_with(Vec::new)
+                    .push(alias.name);
+            }
+            Ok(groups)
+        })?;
+        Ok(aliases.unwrap_or_default())
+    }
+
+    /// The rating history of a driver, oldest first.
+    pub(crate) fn driver_ratings(
+        &self,
+        db: &DbConnection,
+        driver_id: i32,
+    ) -> anyhow::Result<Vec<RatingChange>> {
+        let ratings = self.driver_ratings.load(driver_id, |ids| {
+            use crate::schema::{driver_ratings, races};
+            let ratings: Vec<(i32, i32, f64, f64, NaiveDate)> = with_conn!(db, |conn| {
+                driver_ratings::table
+                    .inner_join(races::table)
+                    .filter(driver_ratings::driver_id.eq_any(ids))
+                    .order((races::date, races::id))
+                    .select((
+                        driver_ratings::driver_id,
+                        driver_ratings::race_id,
+                        driver_ratings::rating,
+                        driver_ratings::delta,
+                        races::date,
+                    ))
+                    .load(conn)
+            })?;
+            let mut groups: HashMap<i32, Vec<RatingChange>> = HashMap::new();
+            for (driver_id, race_id, rating, delta, date) in ratings {
+                groups.entry(driver_id).or_default().push(RatingChange {
+                    race_id,
+                    date,
+                    rating,
+                    delta,
+                });
+            }
+            Ok(groups)
+        })?;
+        Ok(ratings.unwrap_or_default())
+    }
+
+    pub(crate) fn track(&self, db: &DbConnection, id: i32) -> anyhow::Result<Option<Track>> {
+        self.tracks.load(id, |ids| {
+            use crate::schema::tracks::dsl;
+            let tracks: Vec<Track> = with_conn!(db, |conn| dsl::tracks
+                .filter(dsl::id.eq_any(ids))
+                .load(conn))?;
+            self.prime_tracks(&tracks);
+            Ok(tracks.into_iter().map(|track| (track.id, track)).collect())
+        })
+    }
+
+    /// The entry with the fastest lap of each driver on a track, fastest
+    /// first. Laps from disqualified entrants don't count.
+    pub(crate) fn track_best_laps(
+        &self,
+        db: &DbConnection,
+        track_id: i32,
+    ) -> anyhow::Result<Vec<RaceEntrant>> {
+        let best_laps = self.track_best_laps.load(track_id, |ids| {
+            use crate::schema::{race_entrants, races};
+            let rows: Vec<(i32, RaceEntrant)> = with_conn!(db, |conn| race_entrants::table
+                .inner_join(races::table)
+                .filter(races::track_id.eq_any(ids))
+                .filter(race_entrants::best_lap.is_not_null())
+                .filter(
+                    race_entrants::reason
+                        .is_null()
+                        .or(race_entrants::reason.ne(Reason::Dsq)),
+                )
+                .order((race_entrants::best_lap, races::date, races::id))
+                .select((races::track_id, race_entrants::all_columns))
+                .load(conn))?;
+
+            let mut seen = HashSet::new();
+            let mut groups: HashMap<i32, Vec<RaceEntrant>> = HashMap::new();
+            for (track_id, entrant) in rows {
+                if seen.insert((track_id, entrant.driver_id)) {
+                    groups.entry(track_id).or_default().push(entrant);
+                }
+            }
+            for entrants in groups.values() {
+                self.prime_entrants(entrants);
+            }
+            Ok(groups)
+        })?;
+        Ok(best_laps.unwrap_or_default())
+    }
+
+    /// The number of wins of each driver who has won on a track, most first.
+    /// Drivers with as many wins are listed in the order they first won.
+    pub(crate) fn track_wins(
+        &self,
+        db: &DbConnection,
+        track_id: i32,
+    ) -> anyhow::Result<Vec<TrackWins>> {
+        let wins = self.track_wins.load(track_id, |ids| {
+            use crate::schema::{race_entrants, races};
+            let rows: Vec<(i32, i32)> = with_conn!(db, |conn| race_entrants::table
+                .inner_join(races::table)
+                .filter(races::track_id.eq_any(ids))
+                .filter(race_entrants::position.eq(1))
+                .filter(race_entrants::reason.is_null())
+                .order((races::date, races::id))
+                .select((races::track_id, race_entrants::driver_id))
+                .load(conn))?;
+
+            let mut groups: HashMap<i32, Vec<TrackWins>> = HashMap::new();
+            for (track_id, driver_id) in rows {
+                let wins = groups.entry(track_id).or_default();
+                match wins.iter_mut().find(|wins| wins.driver_id == driver_id) {
+                    Some(wins) => wins.wins += 1,
+                    None => wins.push(TrackWins { driver_id, wins: 1 }),
+                }
+            }
+            for wins in groups.values_mut() {
+                // Stable, so ties stay in the order of the first win.
+                wins.sort_by_key(|wins| -wins.wins);
+                self.drivers.prime(wins.iter().map(|wins| wins.driver_id));
+            }
+            Ok(groups)
+        })?;
+        Ok(wins.unwrap_or_default())
+    }
+
+    pub(crate) fn vehicle(&self, db: &DbConnection, id: i32) -> anyhow::Result<Option<Vehicle>> {
+        self.vehicles.load(id, |ids| {
+            use crate::schema::vehicles::dsl;
+            let vehicles: Vec<Vehicle> = with_conn!(db, |conn| dsl::vehicles
+                .select(VEHICLE_COLUMNS)
+                .filter(dsl::id.eq_any(ids))
+                .load(conn))?;
+            self.prime_vehicles(&vehicles);
+            Ok(vehicles
+                .into_iter()
+                .map(|vehicle| (vehicle.id, vehicle))
+                .collect())
+        })
+    }
+
+    pub(crate) fn vehicle_stats(
+        &self,
+        db: &DbConnection,
+        vehicle_id: i32,
+    ) -> anyhow::Result<Option<VehicleStats>> {
+        self.vehicle_stats.load(vehicle_id, |ids| {
+            Ok(vehicles::vehicle_stats(db, VehicleStatsKey::Vehicle, ids)?
+                .into_iter()
+                .collect())
+        })
+    }
+
+    /// The vehicles a driver has used, most raced first.
+    pub(crate) fn driver_vehicles(
+        &self,
+        db: &DbConnection,
+        driver_id: i32,
+    ) -> anyhow::Result<Vec<VehicleStats>> {
+        let stats = self.driver_vehicles.load(driver_id, |ids| {
+            let mut groups: HashMap<i32, Vec<VehicleStats>> = HashMap::new();
+            for (driver_id, stats) in vehicles::vehicle_stats(db, VehicleStatsKey::Driver, ids)? {
+                groups.entry(driver_id).or_default().push(stats);
+            }
+            for stats in groups.values_mut() {
+                stats.sort_by_key(|stats| (-stats.race_count, -stats.wins, stats.vehicle_id));
+                self.vehicles
+                    .prime(stats.iter().map(|stats| stats.vehicle_id));
+            }
+            Ok(groups)
+        })?;
+        Ok(stats.unwrap_or_default())
+    }
+
+    /// The entry with the fastest lap set with a vehicle on each track,
+    /// ordered by track name. Laps from disqualified entrants don't count.
+    pub(crate) fn vehicle_best_laps(
+        &self,
+        db: &DbConnection,
+        vehicle_id: i32,
+    ) -> anyhow::Result<Vec<RaceEntrant>> {
+        let best_laps = self.vehicle_best_laps.load(vehicle_id, |ids| {
+            use crate::schema::{race_entrants, races, tracks};
+            let rows: Vec<(String, RaceEntrant)> = with_conn!(db, |conn| race_entrants::table
+                .inner_join(races::table.inner_join(tracks::table))
+                .filter(race_entrants::vehicle_id.eq_any(ids))
+                .filter(race_entrants::best_lap.is_not_null())
+                .filter(
+                    race_entrants::reason
+                        .is_null()
+                        .or(race_entrants::reason.ne(Reason::Dsq)),
+                )
+                .order((race_entrants::best_lap, races::date, races::id))
+                .select((tracks::name, race_entrants::all_columns))
+                .load(conn))?;
+
+            let mut seen = HashSet::new();
+            let mut groups: HashMap<i32, Vec<(String, RaceEntrant)>> = HashMap::new();
+            for (track, entrant) in rows {
+                let vehicle_id = entrant.vehicle_id.unwrap_or_default();
+                if seen.insert((vehicle_id, track.clone())) {
+                    groups.entry(vehicle_id).or_default().push((track, entrant));
+                }
+            }
+            Ok(groups
+                .into_iter()
+                .map(|(vehicle_id, mut best_laps)| {
+                    best_laps.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    let entrants: Vec<RaceEntrant> =
+                        best_laps.into_iter().map(|(_, entrant)| entrant).collect();
+                    self.prime_entrants(&entrants);
+                    (vehicle_id, entrants)
+                })
+                .collect())
+        })?;
+        Ok(best_laps.unwrap_or_default())
+    }
+
+    pub(crate) fn season(&self, db: &DbConnection, id: i32) -> anyhow::Result<Option<Season>> {
+        self.seasons.load(id, |ids| {
+            use crate::schema::seasons::dsl;
+            let seasons: Vec<Season> = with_conn!(db, |conn| dsl::seasons
+                .filter(dsl::id.eq_any(ids))
+                .load(conn))?;
+            self.prime_seasons(&seasons);
+            Ok(seasons
+                .into_iter()
+                .map(|season| (season.id, season))
+                .collect())
+        })
+    }
+
+    /// The championships of a season, by name.
+    pub(crate) fn season_championships(
+        &self,
+        db: &DbConnection,
+        season_id: i32,
+    ) -> anyhow::Result<Vec<Championship>> {
+        let championships = self.season_championships.load(season_id, |ids| {
+            use crate::schema::championships::dsl;
+            let championships: Vec<Championship> = with_conn!(db, |conn| dsl::championships
+                .filter(dsl::season_id.eq_any(ids))
+                .order(dsl::name)
+                .load(conn))?;
+            self.prime_championships(&championships);
+            Ok(group_by(championships, |championship| {
+                championship.season_id
+            }))
+        })?;
+        Ok(championships.unwrap_or_default())
+    }
+
+    /// The races of a championship, in the order they were run.
+    pub(crate) fn championship_rounds(
+        &self,
+        db: &DbConnection,
+        championship_id: i32,
+    ) -> anyhow::Result<Vec<Race>> {
+        let rounds = self.championship_rounds.load(championship_id, |ids| {
+            use crate::schema::{championship_rounds, races};
+            let rows: Vec<(i32, Race)> = with_conn!(db, |conn| championship_rounds::table
+                .inner_join(races::table)
+                .filter(championship_rounds::championship_id.eq_any(ids))
+                .order((races::date, races::id))
+                .select((championship_rounds::championship_id, races::all_columns))
+                .load(conn))?;
+            let mut groups: HashMap<i32, Vec<Race>> = HashMap::new();
+            for (championship_id, race) in rows {
+                groups.entry(championship_id).or_default().push(race);
+            }
+            for races in groups.values() {
+                self.prime_races(races);
+            }
+            Ok(groups)
+        })?;
+        Ok(rounds.unwrap_or_default())
+    }
+
+    pub(crate) fn championship_standings(
+        &self,
+        db: &DbConnection,
+        championship_id: i32,
+    ) -> anyhow::Result<Vec<Standing>> {
+        let standings = self.championship_standings.load(championship_id, |ids| {
+            let standings = championships::standings(db, ids)?;
+            for standings in standings.values() {
+                self.drivers
+                    .prime(standings.iter().map(|standing| standing.driver_id));
+            }
+            Ok(standings)
+        })?;
+        Ok(standings.unwrap_or_default())
+    }
+}
+
+fn group_by<T>(items: Vec<T>, key: impl Fn(&T) -> i32) -> HashMap<i32, Vec<T>> {
+    let mut groups: HashMap<i32, Vec<T>> = HashMap::new();
+    for item in items {
+        groups.entry(key(&item)).or_default().push(item);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn batches_pending_keys() {
+        let loader: Loader<i32, i32> = Loader::default();
+        let fetches = RefCell::new(Vec::new());
+        let fetch = |keys: &[i32]| {
+            let mut keys = keys.to_vec();
+            keys.sort();
+            fetches.borrow_mut().push(keys.clone());
+            Ok(keys.into_iter().filter(|&k| k != 3).map(|k| (k, k * 10)).collect())
+        };
+
+        loader.prime(vec![1, 2, 3]);
+        assert_eq!(loader.load(2, fetch).unwrap(), Some(20));
+        assert_eq!(loader.load(1, fetch).unwrap(), Some(10));
+        assert_eq!(loader.load(3, fetch).unwrap(), None);
+        loader.prime(vec![1, 4]);
+        assert_eq!(loader.load(5, fetch).unwrap(), Some(50));
+
+        assert_eq!(*fetches.borrow(), vec![vec![1, 2, 3], vec![4, 5]]);
+    }
+}
@@ -0,0 +1,610 @@
+mod championships;
+mod drivers;
+mod head_to_head;
+mod ingest;
+mod loader;
+mod pagination;
+mod races;
+mod ratings;
+mod subscriptions;
+mod tracks;
+mod vehicles;
+
+use self::championships::ChampionshipInput;
+use self::drivers::{DriverConnection, DriverStats};
+use self::head_to_head::HeadToHead;
+use self::ingest::{EntrantExtra, ParsedRace, RaceInput};
+use self::loader::Loaders;
+use self::races::{RaceConnection, RaceFilter, RaceOrder};
+use self::ratings::RatingChange;
+pub(crate) use self::subscriptions::{subscription_filter, Subscription, SubscriptionSchema};
+use self::vehicles::VehicleStats;
+use crate::db::{DbConnection, DbPool};
+use crate::events::{Broadcaster, Event};
+use crate::model::{
+    decode_extras, Championship, Driver, DriverAlias, Race, RaceEntrant, RaceMode, Reason, Role,
+    Season, Track, Vehicle, VehicleName, VEHICLE_COLUMNS,
+};
+use crate::parser::GapMode;
+use crate::{aliases, auth};
+use anyhow::{bail, Context as _};
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use juniper::FieldResult;
+use std::sync::Arc;
+
+pub(crate) type Schema = juniper::RootNode<'static, Query, Mutation>;
+
+pub(crate) struct Context {
+    pub(crate) db: Option<DbPool>,
+    loaders: Loaders,
+    /// The value of the request's `Authorization` header.
+    authorization: Option<String>,
+    pub(crate) events: Arc<Broadcaster>,
+    /// The event that subscriptions are being resolved for.
+    event: Option<Event>,
+}
+
+impl Context {
+    /// A context using `pool`, which must already be migrated, whose
+    /// subscriptions listen to `events`.
+    pub(crate) fn with_pool(pool: DbPool, events: Arc<Broadcaster>) -> Context {
+        Context {
+            db: Some(pool),
+            loaders: Loaders::default(),
+            authorization: None,
+            events,
+            event: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new(db_url: &str) -> anyhow::Result<Context> {
+        let pool = DbPool::new(db_url)?;
+        crate::migrations::run_pending(&pool.get()?)?;
+        Ok(Context::with_pool(pool, Arc::default()))
+    }
+
+    pub(crate) fn without_database() -> Context {
+        Context {
+            db: None,
+            loaders: Loaders::default(),
+            authorization: None,
+            events: Arc::default(),
+            event: None,
+        }
+    }
+
+    /// A context sharing the connection pool, but with empty caches, so that
+    /// every request sees the current data.
+    pub(crate) fn for_request(&self, authorization: Option<String>) -> Context {
+        Context {
+            db: self.db.clone(),
+            loaders: Loaders::default(),
+            authorization,
+            events: self.events.clone(),
+            event: None,
+        }
+    }
+
+    /// A context for resolving subscriptions to `event`.
+    pub(crate) fn for_event(&self, event: Option<Event>) -> Context {
+        Context {
+            event,
+            ..self.for_request(None)
+        }
+    }
+
+    /// The role of the API token sent with the request, if any.
+    ///
+    /// Tokens are looked up when they are needed rather than by the warp
+    /// filter, so that requests which don't need one don't touch the
+    /// database, and so that the lookup runs on the blocking thread pool.
+    fn role(&self) -> anyhow::Result<Option<Role>> {
+        let header = match &self.authorization {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let token = auth::bearer_token(header).context("malformed Authorization header")?;
+        match auth::token_role(&self.db()?, token)? {
+            Some(role) => Ok(Some(role)),
+            None => bail!("invalid or revoked API token"),
+        }
+    }
+
+    /// Fails unless the request was made with a token of at least `role`.
+    fn require(&self, role: Role) -> anyhow::Result<()> {
+        match self.role()? {
+            Some(actual) if actual >= role => Ok(()),
+            Some(_) => bail!("this operation requires the {} role", role),
+            None => bail!("this operation requires an API token"),
+        }
+    }
+
+    fn db(&self) -> anyhow::Result<DbConnection> {
+        Ok(self
+            .db
+            .as_ref()
+            .context("not connected to a database")?
+            .get()?)
+    }
+}
+
+/// Runs the GraphQL query `document` and returns the data it resolved to as
+/// JSON, failing the test if there were any errors.
+#[cfg(test)]
+pub(crate) fn run_query(context: &Context, document: &str) -> serde_json::Value {
+    let schema = Schema::new(Query, Mutation);
+    let variables = juniper::Variables::new();
+    let (value, errors) =
+        juniper::execute(document, None, &schema, &variables, context).expect("invalid query");
+    assert!(errors.is_empty(), "{:?}", errors);
+    serde_json::to_value(&value).unwrap()
+}
+
+impl juniper::Context for Context {}
+
+pub(crate) struct Query;
+
+#[juniper::object(Context = Context)]
+impl Query {
+    /// The role of the API token sent with the request.
+    fn role(context: &Context) -> FieldResult<Option<Role>> {
+        Ok(context.role()?)
+    }
+
+    fn driver(context: &Context, id: i32) -> FieldResult<Option<Driver>> {
+        Ok(context.loaders.driver(&context.db()?, id)?)
+    }
+
+    /// Finds a driver by their current name or one of their aliases.
+    fn driver_name(context: &Context, name: String) -> FieldResult<Option<Driver>> {
+        let db = context.db()?;
+        use crate::schema::drivers::dsl::{self, drivers};
+        let driver: Option<Driver> = with_conn!(&db, |conn| drivers
+            .filter(dsl::name.eq(&name))
+            .first(conn)
+            .optional())?;
+        if driver.is_none() {
+            if let Some(id) = DriverAlias::resolve(&db, &name)? {
+                return Ok(context.loaders.driver(&db, id)?);
+            }
+        }
+        context.loaders.prime_drivers(driver.as_slice());
+        Ok(driver)
+    }
+
+    /// Lists drivers by name, optionally only those whose name starts with
+    /// `search`, or contains its characters in order if `fuzzy` is set.
+    fn drivers(
+        context: &Context,
+        first: Option<i32>,
+        after: Option<String>,
+        search: Option<String>,
+        fuzzy: Option<bool>,
+    ) -> FieldResult<DriverConnection> {
+        drivers::drivers(context, first, after, search, fuzzy.unwrap_or(false))
+    }
+
+    /// Compares drivers `a` and `b` over the races they both entered.
+    fn head_to_head(context: &Context, a: i32, b: i32) -> FieldResult<HeadToHead> {
+        head_to_head::head_to_head(context, a, b)
+    }
+
+    fn race(context: &Context, id: i32) -> FieldResult<Option<Race>> {
+        Ok(context.loaders.race(&context.db()?, id)?)
+    }
+
+    fn track(context: &Context, id: i32) -> FieldResult<Option<Track>> {
+        Ok(context.loaders.track(&context.db()?, id)?)
+    }
+
+    fn track_name(context: &Context, name: String) -> FieldResult<Option<Track>> {
+        let db = context.db()?;
+        use crate::schema::tracks::dsl::{self, tracks};
+        let track: Option<Track> = with_conn!(&db, |conn| tracks
+            .filter(dsl::name.eq(name))
+            .first(conn)
+            .optional())?;
+        context.loaders.prime_tracks(track.as_slice());
+        Ok(track)
+    }
+
+    /// Lists all tracks by name.
+    fn tracks(context: &Context) -> FieldResult<Vec<Track>> {
+        let db = context.db()?;
+        use crate::schema::tracks::dsl::{self, tracks};
+        let all: Vec<Track> = with_conn!(&db, |conn| tracks.order(dsl::name.asc()).load(conn))?;
+        context.loaders.prime_tracks(&all);
+        Ok(all)
+    }
+
+    fn vehicle(context: &Context, id: i32) -> FieldResult<Option<Vehicle>> {
+        Ok(context.loaders.vehicle(&context.db()?, id)?)
+    }
+
+    /// Finds a vehicle by name, ignoring case and whitespace differences.
+    fn vehicle_name(context: &Context, name: String) -> FieldResult<Option<Vehicle>> {
+        let db = context.db()?;
+        use crate::schema::vehicles::dsl::{self, vehicles};
+        let vehicle: Option<Vehicle> = with_conn!(&db, |conn| vehicles
+            .select(VEHICLE_COLUMNS)
+            .filter(dsl::normalized_name.eq(VehicleName::new(&name).normalized_name))
+            .first(conn)
+            .optional())?;
+        context.loaders.prime_vehicles(vehicle.as_slice());
+        Ok(vehicle)
+    }
+
+    /// Lists all vehicles by name.
+    fn vehicles(context: &Context) -> FieldResult<Vec<Vehicle>> {
+        let db = context.db()?;
+        use crate::schema::vehicles::dsl::{self, vehicles};
+        let all: Vec<Vehicle> = with_conn!(&db, |conn| vehicles
+            .select(VEHICLE_COLUMNS)
+            .order(dsl::name.asc())
+            .load(conn))?;
+        context.loaders.prime_vehicles(&all);
+        Ok(all)
+    }
+
+    /// Lists all seasons by name.
+    fn seasons(context: &Context) -> FieldResult<Vec<Season>> {
+        let db = context.db()?;
+        use crate::schema::seasons::dsl::{self, seasons};
+        let all: Vec<Season> = with_conn!(&db, |conn| seasons.order(dsl::name.asc()).load(conn))?;
+        context.loaders.prime_seasons(&all);
+        Ok(all)
+    }
+
+    fn season(context: &Context, id: i32) -> FieldResult<Option<Season>> {
+        Ok(context.loaders.season(&context.db()?, id)?)
+    }
+
+    fn championship(context: &Context, id: i32) -> FieldResult<Option<Championship>> {
+        let db = context.db()?;
+        use crate::schema::championships::dsl::championships;
+        let championship: Option<Championship> =
+            with_conn!(&db, |conn| championships.find(id).first(conn).optional())?;
+        context.loaders.prime_championships(championship.as_slice());
+        Ok(championship)
+    }
+
+    fn races(
+        context: &Context,
+        first: Option<i32>,
+        after: Option<String>,
+        filter: Option<RaceFilter>,
+        order: Option<RaceOrder>,
+    ) -> FieldResult<RaceConnection> {
+        races::races(
+            context,
+            first,
+            after,
+            filter.unwrap_or_default(),
+            order.unwrap_or(RaceOrder::DateDesc),
+        )
+    }
+}
+
+pub(crate) struct Mutation;
+
+#[juniper::object(Context = Context)]
+impl Mutation {
+    /// Parses a results document and stores it as race `id`. Fails if the
+    /// race is already stored, unless `replace` is set. With `lenient`, rows
+    /// that can't be parsed are left out; `previewRace` lists them.
+    ///
+    /// Requires the organiser role, or admin to replace a race.
+    fn add_race(
+        context: &Context,
+        id: i32,
+        date: NaiveDate,
+        results: String,
+        gaps: Option<GapMode>,
+        lenient: Option<bool>,
+        replace: Option<bool>,
+    ) -> FieldResult<Race> {
+        let replace = replace.unwrap_or(false);
+        context.require(if replace {
+            Role::Admin
+        } else {
+            Role::Organiser
+        })?;
+        let race = ingest::parse_results(&results, gaps, lenient)?;
+        ingest::add_race(context, id, date, race, Some(&results), replace)
+    }
+
+    /// Stores race `id` from a list of entrants. Requires the same roles as
+    /// `addRace`.
+    fn add_race_entrants(
+        context: &Context,
+        id: i32,
+        date: NaiveDate,
+        race: RaceInput,
+        replace: Option<bool>,
+    ) -> FieldResult<Race> {
+        let replace = replace.unwrap_or(false);
+        context.require(if replace {
+            Role::Admin
+        } else {
+            Role::Organiser
+        })?;
+        ingest::add_race(context, id, date, race.into_race()?, None, replace)
+    }
+
+    /// Parses a results document like `addRace`, without storing it.
+    /// Requires the viewer role.
+    fn preview_race(
+        context: &Context,
+        id: i32,
+        results: String,
+        gaps: Option<GapMode>,
+        lenient: Option<bool>,
+    ) -> FieldResult<ParsedRace> {
+        context.require(Role::Viewer)?;
+        let race = ingest::parse_results(&results, gaps, lenient)?;
+        ingest::preview_race(context, id, race)
+    }
+
+    /// Checks a list of entrants like `addRaceEntrants`, without storing it.
+    /// Requires the viewer role.
+    fn preview_race_entrants(
+        context: &Context,
+        id: i32,
+        race: RaceInput,
+    ) -> FieldResult<ParsedRace> {
+        context.require(Role::Viewer)?;
+        ingest::preview_race(context, id, race.into_race()?)
+    }
+
+    /// Merges driver `source` into `target`, keeping the name of `source` as
+    /// an alias. Requires the admin role.
+    fn merge_drivers(context: &Context, source: i32, target: i32) -> FieldResult<Driver> {
+        context.require(Role::Admin)?;
+        let db = context.db()?;
+        aliases::merge_drivers(&db, source, target)?;
+        Ok(stored_driver(&db, target)?)
+    }
+
+    /// Turns an alias back into a separate driver, along with the entries
+    /// listed under it. Requires the admin role.
+    fn split_driver_alias(context: &Context, alias: String) -> FieldResult<Driver> {
+        context.require(Role::Admin)?;
+        let db = context.db()?;
+        let id = aliases::split_alias(&db, &alias)?;
+        Ok(stored_driver(&db, id)?)
+    }
+
+    /// Renames a driver, keeping the old name as an alias. Requires the admin
+    /// role.
+    fn rename_driver(context: &Context, id: i32, name: String) -> FieldResult<Driver> {
+        context.require(Role::Admin)?;
+        let db = context.db()?;
+        aliases::rename_driver(&db, id, &name)?;
+        Ok(stored_driver(&db, id)?)
+    }
+
+    /// Creates a season to group championships in. Requires the organiser
+    /// role.
+    fn create_season(context: &Context, name: String) -> FieldResult<Season> {
+        context.require(Role::Organiser)?;
+        let db = context.db()?;
+        let id = crate::championships::create_season(&db, &name)?;
+        Ok(Season { id, name })
+    }
+
+    /// Creates a championship without any rounds. Requires the organiser
+    /// role.
+    fn create_championship(
+        context: &Context,
+        championship: ChampionshipInput,
+    ) -> FieldResult<Championship> {
+        context.require(Role::Organiser)?;
+        let db = context.db()?;
+        let id = crate::championships::create_championship(&db, championship.into())?;
+        Ok(championships::stored_championship(&db, id)?)
+    }
+
+    /// Adds a race to a championship. Requires the organiser role.
+    fn add_championship_round(
+        context: &Context,
+        championship: i32,
+        race: i32,
+    ) -> FieldResult<Championship> {
+        context.require(Role::Organiser)?;
+        let db = context.db()?;
+        crate::championships::add_round(&db, championship, race)?;
+        Ok(championships::stored_championship(&db, championship)?)
+    }
+
+    /// Removes a race from a championship. Requires the organiser role.
+    fn remove_championship_round(
+        context: &Context,
+        championship: i32,
+        race: i32,
+    ) -> FieldResult<Championship> {
+        context.require(Role::Organiser)?;
+        let db = context.db()?;
+        crate::championships::remove_round(&db, championship, race)?;
+        Ok(championships::stored_championship(&db, championship)?)
+    }
+}
+
+/// Loads a driver bypassing the request's loaders, which may hold a copy from
+/// before a mutation.
+fn stored_driver(db: &DbConnection, id: i32) -> anyhow::Result<Driver> {
+    use crate::schema::drivers::dsl::drivers;
+    Ok(with_conn!(db, |conn| drivers.find(id).first(conn))?)
+}
+
+#[juniper::object(Context = Context)]
+impl Driver {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn stats(&self, context: &Context) -> FieldResult<DriverStats> {
+        Ok(context.loaders.driver_stats(&context.db()?, self.id)?)
+    }
+
+    /// The driver's current rating, or null if they have not been rated yet.
+    fn rating(&self, context: &Context) -> FieldResult<Option<f64>> {
+        let history = context.loaders.driver_ratings(&context.db()?, self.id)?;
+        Ok(history.last().map(|change| change.rating))
+    }
+
+    /// The driver's rating after each race they were rated in, oldest first.
+    fn rating_history(&self, context: &Context) -> FieldResult<Vec<RatingChange>> {
+        Ok(context.loaders.driver_ratings(&context.db()?, self.id)?)
+    }
+
+    /// Other names that the driver has entered races under.
+    fn aliases(&self, context: &Context) -> FieldResult<Vec<String>> {
+        Ok(context.loaders.driver_aliases(&context.db()?, self.id)?)
+    }
+
+    fn entries(&self, context: &Context) -> FieldResult<Vec<RaceEntrant>> {
+        Ok(context.loaders.driver_entries(&context.db()?, self.id)?)
+    }
+
+    /// The vehicles the driver has raced with, most raced first.
+    fn vehicles(&self, context: &Context) -> FieldResult<Vec<VehicleStats>> {
+        Ok(context.loaders.driver_vehicles(&context.db()?, self.id)?)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Race {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    fn track(&self, context: &Context) -> FieldResult<Track> {
+        Ok(context
+            .loaders
+            .track(&context.db()?, self.track_id)?
+            .context("track not found")?)
+    }
+
+    fn laps(&self) -> Option<i32> {
+        self.laps
+    }
+
+    fn minutes(&self) -> Option<i32> {
+        self.minutes
+    }
+
+    fn mode(&self) -> Option<RaceMode> {
+        self.mode
+    }
+
+    fn entrants(&self, context: &Context) -> FieldResult<Vec<RaceEntrant>> {
+        Ok(context.loaders.race_entrants(&context.db()?, self.id)?)
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl RaceEntrant {
+    fn race_id(&self) -> i32 {
+        self.race_id
+    }
+
+    fn race(&self, context: &Context) -> FieldResult<Race> {
+        Ok(context
+            .loaders
+            .race(&context.db()?, self.race_id)?
+            .context("race not found")?)
+    }
+
+    fn driver_id(&self) -> i32 {
+        self.driver_id
+    }
+
+    fn driver(&self, context: &Context) -> FieldResult<Driver> {
+        Ok(context
+            .loaders
+            .driver(&context.db()?, self.driver_id)?
+            .context("driver not found")?)
+    }
+
+    fn position(&self) -> Option<i32> {
+        self.position
+    }
+
+    fn vehicle(&self, context: &Context) -> FieldResult<Option<Vehicle>> {
+        match self.vehicle_id {
+            Some(id) => Ok(Some(
+                context
+                    .loaders
+                    .vehicle(&context.db()?, id)?
+                    .context("vehicle not found")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn time(&self) -> Option<i32> {
+        self.time
+    }
+
+    fn gap(&self) -> Option<i32> {
+        self.gap
+    }
+
+    fn best_lap(&self) -> Option<i32> {
+        self.best_lap
+    }
+
+    fn lap(&self) -> Option<i32> {
+        self.lap
+    }
+
+    fn reason(&self) -> Option<Reason> {
+        self.reason
+    }
+
+    fn ping(&self) -> Option<i32> {
+        self.ping
+    }
+
+    fn fps(&self) -> Option<i32> {
+        self.fps
+    }
+
+    fn fps_locked(&self) -> bool {
+        self.fps_locked
+    }
+
+    /// The name the driver was listed under in the results, if it was
+    /// recorded.
+    fn entered_as(&self) -> Option<&str> {
+        self.entered_as.as_deref()
+    }
+
+    /// The class of the entrant's row in the results, such as `dnf` or
+    /// `dsq`. Not recorded for races imported before it was.
+    fn row_class(&self) -> Option<&str> {
+        self.row_class.as_deref()
+    }
+
+    /// The class of the cell with the entrant's name in the results, such as
+    /// `gt0`.
+    fn name_class(&self) -> Option<&str> {
+        self.name_class.as_deref()
+    }
+
+    /// The columns of the results that aren't otherwise stored, by header.
+    fn extras(&self) -> FieldResult<Vec<EntrantExtra>> {
+        let extras = decode_extras(self.extras.as_deref())?;
+        Ok(EntrantExtra::from_pairs(extras))
+    }
+}
@@ -0,0 +1,69 @@
+use juniper::{FieldResult, GraphQLObject};
+
+const DEFAULT_PAGE_SIZE: i32 = 20;
+const MAX_PAGE_SIZE: i32 = 100;
+
+#[derive(Debug, Clone, GraphQLObject)]
+pub(crate) struct PageInfo {
+    pub(crate) has_next_page: bool,
+    pub(crate) end_cursor: Option<String>,
+}
+
+/// Validates the `first` argument of a connection field.
+pub(crate) fn page_size(first: Option<i32>) -> FieldResult<i64> {
+    match first.unwrap_or(DEFAULT_PAGE_SIZE) {
+        n if n < 0 => Err("`first` must not be negative".into()),
+        n => Ok(i64::from(n.min(MAX_PAGE_SIZE))),
+    }
+}
+
+/// Splits off the extra item that was fetched to find out whether there is a
+/// next page, returning the page info for the remaining items.
+pub(crate) fn finish_page<T>(
+    items: &mut Vec<T>,
+    limit: i64,
+    cursor: impl Fn(&T) -> String,
+) -> PageInfo {
+    let has_next_page = items.len() as i64 > limit;
+    items.truncate(limit as usize);
+    PageInfo {
+        has_next_page,
+        end_cursor: items.last().map(cursor),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_sizes() {
+        assert_eq!(page_size(None).unwrap(), i64::from(DEFAULT_PAGE_SIZE));
+        assert_eq!(page_size(Some(5)).unwrap(), 5);
+        assert_eq!(page_size(Some(0)).unwrap(), 0);
+        assert_eq!(page_size(Some(1000)).unwrap(), i64::from(MAX_PAGE_SIZE));
+        assert!(page_size(Some(-1)).is_err());
+    }
+
+    #[test]
+    fn pages() {
+        let mut items = vec![1, 2, 3];
+        let page = finish_page(&mut items, 2, i32::to_string);
+        assert_eq!(items, [1, 2]);
+        assert!(page.has_next_page);
+        assert_eq!(page.end_cursor.as_deref(), Some("2"));
+
+        // The last page has no extra item.
+        let mut items = vec![3];
+        let page = finish_page(&mut items, 2, i32::to_string);
+        assert_eq!(items, [3]);
+        assert!(!page.has_next_page);
+        assert_eq!(page.end_cursor.as_deref(), Some("3"));
+
+        let mut items = vec![1];
+        let page = finish_page(&mut items, 0, i32::to_string);
+        assert!(items.is_empty());
+        assert!(page.has_next_page);
+        assert_eq!(page.end_cursor, None);
+    }
+}
@@ -0,0 +1,278 @@
+use super::pagination::{finish_page, page_size, PageInfo};
+use super::Context;
+use crate::model::{Race, RaceMode};
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use juniper::{FieldResult, GraphQLEnum, GraphQLInputObject, GraphQLObject};
+
+#[derive(Debug, Clone, Default, GraphQLInputObject)]
+pub(crate) struct RaceFilter {
+    /// Only include races on or after this date.
+    from: Option<NaiveDate>,
+    /// Only include races on or before this date.
+    to: Option<NaiveDate>,
+    /// Only include races on the track with this name.
+    track: Option<String>,
+    track_id: Option<i32>,
+    mode: Option<RaceMode>,
+    /// Only include races that this driver took part in.
+    driver_id: Option<i32>,
+}
+
+impl RaceFilter {
+    pub(crate) fn track(id: i32) -> RaceFilter {
+        RaceFilter {
+            track_id: Some(id),
+            ..RaceFilter::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, GraphQLEnum)]
+pub(crate) enum RaceOrder {
+    DateDesc,
+    DateAsc,
+    IdDesc,
+    IdAsc,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(Context = Context, Scalar = juniper::DefaultScalarValue)]
+pub(crate) struct RaceConnection {
+    edges: Vec<RaceEdge>,
+    page_info: PageInfo,
+    total_count: i32,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(Context = Context, Scalar = juniper::DefaultScalarValue)]
+pub(crate) struct RaceEdge {
+    cursor: String,
+    node: Race,
+}
+
+/// Races are paged by their sort key, which is encoded as `<date>:<id>`.
+struct Cursor {
+    date: NaiveDate,
+    id: i32,
+}
+
+impl Cursor {
+    fn encode(race: &Race) -> String {
+        format!("{}:{}", race.date, race.id)
+    }
+
+    fn decode(cursor: &str) -> FieldResult<Cursor> {
+        let mut parts = cursor.splitn(2, ':');
+        match (
+            parts.next().and_then(|date| date.parse().ok()),
+            parts.next().and_then(|id| id.parse().ok()),
+        ) {
+            (Some(date), Some(id)) => Ok(Cursor { date, id }),
+            _ => Err(format!("invalid cursor {:?}", cursor).into()),
+        }
+    }
+}
+
+pub(crate) fn races(
+    context: &Context,
+    first: Option<i32>,
+    after: Option<String>,
+    filter: RaceFilter,
+    order: RaceOrder,
+) -> FieldResult<RaceConnection> {
+    use crate::schema::race_entrants::dsl as entrants;
+    use crate::schema::races::dsl::*;
+    use crate::schema::tracks::dsl as tracks;
+
+    let limit = page_size(first)?;
+    let after = after.as_deref().map(Cursor::decode).transpose()?;
+
+    let db = context.db()?;
+    let (total_count, mut nodes) = with_conn!(&db, |conn| {
+        let filtered = || {
+            let mut query = races.into_boxed();
+            if let Some(from) = filter.from {
+                query = query.filter(date.ge(from));
+            }
+            if let Some(to) = filter.to {
+                query = query.filter(date.le(to));
+            }
+            if let Some(name) = &filter.track {
+                query = query.filter(
+                    track_id.eq_any(
+                        tracks::tracks
+                            .select(tracks::id)
+                            .filter(tracks::name.eq(name.clone())),
+                    ),
+                );
+            }
+            if let Some(track) = filter.track_id {
+                query = query.filter(track_id.eq(track));
+            }
+            if let Some(race_mode) = filter.mode {
+                query = query.filter(mode.eq(race_mode));
+            }
+            if let Some(driver) = filter.driver_id {
+                query = query.filter(
+                    id.eq_any(
+                        entrants::race_entrants
+                            .select(entrants::race_id)
+                            .filter(entrants::driver_id.eq(driver)),
+                    ),
+                );
+            }
+            query
+        };
+
+        let total_count: i64 = filtered().count().get_result(conn)?;
+
+        let mut query = filtered();
+        if let Some(after) = &after {
+            query = match order {
+                RaceOrder::DateDesc => query.filter(
+                    date.lt(after.date)
+                        .or(date.eq(after.date).and(id.lt(after.id))),
+                ),
+                RaceOrder::DateAsc => query.filter(
+                    date.gt(after.date)
+                        .or(date.eq(after.date).and(id.gt(after.id))),
+                ),
+                RaceOrder::IdDesc => query.filter(id.lt(after.id)),
+                RaceOrder::IdAsc => query.filter(id.gt(after.id)),
+            };
+        }
+        query = match order {
+            RaceOrder::DateDesc => query.order((date.desc(), id.desc())),
+            RaceOrder::DateAsc => query.order((date.asc(), id.asc())),
+            RaceOrder::IdDesc => query.order(id.desc()),
+            RaceOrder::IdAsc => query.order(id.asc()),
+        };
+        let nodes: Vec<Race> = query.limit(limit + 1).load(conn)?;
+        (total_count, nodes)
+    });
+
+    let page_info = finish_page(&mut nodes, limit, Cursor::encode);
+    context.loaders.prime_races(&nodes);
+    Ok(RaceConnection {
+        edges: nodes
+            .into_iter()
+            .map(|node| RaceEdge {
+                cursor: Cursor::encode(&node),
+                node,
+            })
+            .collect(),
+        page_info,
+        total_count: total_count as i32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors() {
+        let race = Race {
+            id: 1234,
+            date: "2020-05-24".parse().unwrap(),
+            track_id: 1,
+            laps: Some(5),
+            minutes: None,
+            mode: Some(RaceMode::Laps),
+        };
+        let cursor = Cursor::decode(&Cursor::encode(&race)).unwrap();
+        assert_eq!((cursor.date, cursor.id), (race.date, race.id));
+
+        for invalid in &[
+            "",
+            "2020-05-24",
+            "2020-05-24:",
+            "yesterday:1",
+            "2020-05-24:x",
+        ] {
+            assert!(Cursor::decode(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn filtered_pages() {
+        use crate::api::run_query;
+        use crate::db::TestDb;
+        use diesel::connection::SimpleConnection;
+
+        let db = TestDb::new("race_pages");
+        let context = Context::new(db.url()).unwrap();
+        with_conn!(&context.db().unwrap(), |conn| conn.batch_execute(
+            "INSERT INTO tracks (id, name) VALUES (1, 'Sandpit'), (2, 'Monaco');
+             INSERT INTO drivers (id, name) VALUES (1, 'A'), (2, 'B');
+             INSERT INTO races (id, date, track_id, laps, minutes, mode) VALUES
+                 (10, '2020-05-01', 1, 5, NULL, 'laps'),
+                 (11, '2020-05-02', 1, NULL, 10, 'minutes'),
+                 (12, '2020-05-02', 2, 5, NULL, 'laps'),
+                 (13, '2020-05-03', 2, NULL, 10, 'minutes');
+             INSERT INTO race_entrants (race_id, driver_id, position, fps_locked) VALUES
+                 (10, 1, 1, 0),
+                 (11, 2, 1, 0),
+                 (12, 1, 1, 0),
+                 (12, 2, 2, 0),
+                 (13, 2, 1, 0);"
+        ))
+        .unwrap();
+
+        // The total count, the ids on the page and the cursor after it.
+        let page = |arguments: &str| {
+            let data = run_query(
+                &context,
+                &format!(
+                    "{{ races({}) {{ totalCount edges {{ node {{ id }} }} pageInfo {{ endCursor }} }} }}",
+                    arguments
+                ),
+            );
+            let races = &data["races"];
+            let ids: Vec<i64> = races["edges"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|edge| edge["node"]["id"].as_i64().unwrap())
+                .collect();
+            let cursor = races["pageInfo"]["endCursor"].as_str().map(str::to_string);
+            (races["totalCount"].as_i64().unwrap(), ids, cursor)
+        };
+
+        let (total, ids, cursor) = page("first: 2");
+        assert_eq!((total, ids), (4, vec![13, 12]));
+        let cursor = cursor.unwrap();
+        assert_eq!(cursor, "2020-05-02:12");
+        let (_, ids, _) = page(&format!("first: 2, after: {:?}", cursor));
+        assert_eq!(ids, [11, 10]);
+        let (_, ids, _) = page(&format!("order: DATE_ASC, after: {:?}", cursor));
+        assert_eq!(ids, [13]);
+
+        for (filter, expected) in &[
+            ("from: \"2020-05-02\"", vec![13, 12, 11]),
+            ("to: \"2020-05-02\"", vec![12, 11, 10]),
+            ("from: \"2020-05-02\", to: \"2020-05-02\"", vec![12, 11]),
+            ("track: \"Sandpit\"", vec![11, 10]),
+            ("track: \"Nowhere\"", vec![]),
+            ("trackId: 2", vec![13, 12]),
+            ("mode: MINUTES", vec![13, 11]),
+            ("driverId: 1", vec![12, 10]),
+            ("driverId: 2, mode: LAPS", vec![12]),
+        ] {
+            let (total, ids, _) = page(&format!("filter: {{ {} }}", filter));
+            assert_eq!(ids, *expected, "{}", filter);
+            assert_eq!(total, expected.len() as i64, "{}", filter);
+        }
+
+        // Filters apply to every page, and to the total count.
+        let (total, ids, cursor) = page("first: 1, filter: { driverId: 2 }");
+        assert_eq!((total, ids), (3, vec![13]));
+        let (total, ids, _) = page(&format!(
+            "first: 5, after: {:?}, filter: {{ driverId: 2 }}",
+            cursor.unwrap()
+        ));
+        assert_eq!((total, ids), (3, vec![12, 11]));
+    }
+}
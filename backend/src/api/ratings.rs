@@ -0,0 +1,38 @@
+use super::Context;
+use crate::model::Race;
+use anyhow::Context as _;
+use chrono::naive::NaiveDate;
+use juniper::FieldResult;
+
+/// A driver's rating after a race.
+#[derive(Debug, Clone)]
+pub(crate) struct RatingChange {
+    pub(crate) race_id: i32,
+    pub(crate) date: NaiveDate,
+    pub(crate) rating: f64,
+    pub(crate) delta: f64,
+}
+
+#[juniper::object(Context = Context)]
+impl RatingChange {
+    fn race(&self, context: &Context) -> FieldResult<Race> {
+        Ok(context
+            .loaders
+            .race(&context.db()?, self.race_id)?
+            .context("race not found")?)
+    }
+
+    fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    /// The rating after the race.
+    fn rating(&self) -> f64 {
+        self.rating
+    }
+
+    /// How much the rating changed in the race.
+    fn delta(&self) -> f64 {
+        self.delta
+    }
+}
@@ -0,0 +1,382 @@
+//! GraphQL subscriptions over WebSocket, using the `graphql-ws` protocol of
+//! subscriptions-transport-ws.
+//!
+//! Juniper does not execute subscriptions itself, so every subscription
+//! operation is rewritten into a query against the `Subscription` root and
+//! executed once for each event, with the event in the context. Fields that
+//! don't match the event resolve to null, and results with nothing but nulls
+//! are not sent.
+
+use super::Context;
+use crate::events::{Event, RatingUpdate, TrackRecord};
+use crate::model::{Driver, Race, RaceEntrant, Track};
+use anyhow::Context as _;
+use futures::future::{self, Future};
+use futures::stream::{self, Stream};
+use futures::Sink;
+use juniper::http::GraphQLRequest;
+use juniper::{FieldResult, GraphQLObject, InputValue};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use warp::filters::ws::{Message, WebSocket, Ws2};
+use warp::filters::BoxedFilter;
+use warp::reply::Reply;
+use warp::Filter;
+
+pub(crate) type SubscriptionSchema =
+    juniper::RootNode<'static, Subscription, juniper::EmptyMutation<Context>>;
+
+pub(crate) struct Subscription;
+
+#[juniper::object(Context = Context)]
+impl Subscription {
+    /// A race was imported, or replaced with new results.
+    fn race_added(context: &Context) -> FieldResult<Option<AddedRace>> {
+        let (race_id, replaced) = match context.event {
+            Some(Event::RaceAdded { race_id, replaced }) => (race_id, replaced),
+            _ => return Ok(None),
+        };
+        let race = context
+            .loaders
+            .race(&context.db()?, race_id)?
+            .context("race not found")?;
+        Ok(Some(AddedRace { race, replaced }))
+    }
+
+    /// A driver's current rating changed, optionally only for the driver with
+    /// id `driver`.
+    fn rating_changed(context: &Context, driver: Option<i32>) -> Option<RatingUpdate> {
+        match &context.event {
+            Some(Event::RatingChanged(update))
+                if driver.is_none() || driver == Some(update.driver_id) =>
+            {
+                Some(update.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// A new fastest lap was set on a track, optionally only on the track
+    /// with id `track`.
+    fn track_record(context: &Context, track: Option<i32>) -> Option<TrackRecord> {
+        match &context.event {
+            Some(Event::TrackRecord(record))
+                if track.is_none() || track == Some(record.track_id) =>
+            {
+                Some(record.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(Context = Context, Scalar = juniper::DefaultScalarValue)]
+pub(crate) struct AddedRace {
+    race: Race,
+    /// Whether the race replaced a stored one with the same id.
+    replaced: bool,
+}
+
+#[juniper::object(Context = Context)]
+impl RatingUpdate {
+    fn driver(&self, context: &Context) -> FieldResult<Driver> {
+        Ok(context
+            .loaders
+            .driver(&context.db()?, self.driver_id)?
+            .context("driver not found")?)
+    }
+
+    /// The race that caused the change.
+    fn race(&self, context: &Context) -> FieldResult<Race> {
+        Ok(context
+            .loaders
+            .race(&context.db()?, self.race_id)?
+            .context("race not found")?)
+    }
+
+    fn rating(&self) -> f64 {
+        self.rating
+    }
+
+    /// The rating before the change, or null for a driver's first rating.
+    fn previous_rating(&self) -> Option<f64> {
+        self.previous_rating
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl TrackRecord {
+    fn track(&self, context: &Context) -> FieldResult<Track> {
+        Ok(context
+            .loaders
+            .track(&context.db()?, self.track_id)?
+            .context("track not found")?)
+    }
+
+    /// The entry that set the record.
+    fn entrant(&self, context: &Context) -> FieldResult<RaceEntrant> {
+        let entrants = context
+            .loaders
+            .race_entrants(&context.db()?, self.race_id)?;
+        Ok(entrants
+            .into_iter()
+            .find(|entrant| entrant.driver_id == self.driver_id)
+            .context("entrant not found")?)
+    }
+
+    fn best_lap(&self) -> i32 {
+        self.best_lap
+    }
+
+    /// The record that was broken, or null if this is the first lap set on
+    /// the track.
+    fn previous_best_lap(&self) -> Option<i32> {
+        self.previous_best_lap
+    }
+}
+
+/// Accepts WebSocket connections for subscriptions.
+pub(crate) fn subscription_filter(
+    context: Arc<Context>,
+    schema: SubscriptionSchema,
+) -> BoxedFilter<(impl Reply,)> {
+    let schema = Arc::new(schema);
+    warp::ws2()
+        .map(move |ws: Ws2| {
+            let (context, schema) = (context.clone(), schema.clone());
+            let reply = ws.on_upgrade(move |socket| serve(socket, context, schema));
+            warp::reply::with_header(reply, "sec-websocket-protocol", "graphql-ws")
+        })
+        .boxed()
+}
+
+enum Input {
+    Client(Message),
+    Event(Event),
+    Closed,
+}
+
+fn serve(
+    socket: WebSocket,
+    context: Arc<Context>,
+    schema: Arc<SubscriptionSchema>,
+) -> impl Future<Item = (), Error = ()> {
+    let events = context.events.subscribe().map(Input::Event);
+    let connection = Arc::new(Mutex::new(Connection {
+        context,
+        schema,
+        operations: BTreeMap::new(),
+    }));
+    let (sink, client) = socket.split();
+    let client = client
+        .map(Input::Client)
+        .map_err(drop)
+        .chain(stream::once(Ok(Input::Closed)));
+    client
+        .select(events)
+        .and_then(move |input| {
+            let connection = connection.clone();
+            blocking(move || connection.lock().unwrap().handle(input))
+        })
+        .take_while(|replies| Ok(replies.is_some()))
+        .map(|replies| stream::iter_ok(replies.unwrap_or_default()))
+        .flatten()
+        .map(|reply| Message::text(reply.to_string()))
+        .forward(sink.sink_map_err(drop))
+        .map(drop)
+}
+
+/// Runs `f` on the blocking thread pool, as juniper_warp does for queries.
+fn blocking<T>(f: impl FnOnce() -> T) -> impl Future<Item = T, Error = ()> {
+    let mut f = Some(f);
+    future::poll_fn(move || tokio_threadpool::blocking(|| (f.take().unwrap())()).map_err(drop))
+}
+
+struct Connection {
+    context: Arc<Context>,
+    schema: Arc<SubscriptionSchema>,
+    /// The started subscriptions by the id the client gave them.
+    operations: BTreeMap<String, GraphQLRequest>,
+}
+
+impl Connection {
+    /// Returns the messages to send in response to `input`, or `None` to
+    /// close the connection.
+    fn handle(&mut self, input: Input) -> Option<Vec<Value>> {
+        match input {
+            Input::Client(message) if message.is_close() => None,
+            Input::Client(message) => match message.to_str() {
+                Ok(text) => self.receive(text),
+                Err(()) => Some(Vec::new()),
+            },
+            Input::Event(event) => Some(self.publish(event)),
+            Input::Closed => None,
+        }
+    }
+
+    fn receive(&mut self, text: &str) -> Option<Vec<Value>> {
+        let message: Value = match serde_json::from_str(text) {
+            Ok(message) => message,
+            Err(e) => {
+                let payload = json!({ "message": e.to_string() });
+                return Some(vec![
+                    json!({ "type": "connection_error", "payload": payload }),
+                ]);
+            }
+        };
+        let id = message["id"].as_str().map(str::to_string);
+        let reply = match (message["type"].as_str(), id) {
+            (Some("connection_init"), _) => json!({ "type": "connection_ack" }),
+            (Some("connection_terminate"), _) => return None,
+            (Some("start"), Some(id)) => match self.start(id.clone(), &message["payload"]) {
+                Ok(()) => return Some(Vec::new()),
+                Err(errors) => json!({ "type": "error", "id": id, "payload": errors }),
+            },
+            (Some("stop"), Some(id)) => {
+                self.operations.remove(&id);
+                json!({ "type": "complete", "id": id })
+            }
+            (kind, id) => {
+                let message = format!("unexpected message type {}", kind.unwrap_or("null"));
+                json!({ "type": "error", "id": id, "payload": [{ "message": message }] })
+            }
+        };
+        Some(vec![reply])
+    }
+
+    /// Validates and stores a subscription, returning the GraphQL errors if
+    /// it is invalid.
+    fn start(&mut self, id: String, payload: &Value) -> Result<(), Value> {
+        let error = |message: &str| json!([{ "message": message }]);
+        let query = payload["query"]
+            .as_str()
+            .ok_or_else(|| error("missing query"))?;
+        let query = subscription_as_query(query).map_err(error)?;
+        let variables: InputValue = serde_json::from_value(payload["variables"].clone())
+            .map_err(|e| error(&format!("invalid variables: {}", e)))?;
+        let operation_name = payload["operationName"].as_str().map(str::to_string);
+        let request = GraphQLRequest::new(query, operation_name, Some(variables));
+
+        // Without an event every field resolves to null, so this only checks
+        // that the subscription is valid.
+        let response = request.execute(&self.schema, &self.context.for_event(None));
+        if !response.is_ok() {
+            let response = serde_json::to_value(&response).unwrap_or_default();
+            return Err(response["errors"].clone());
+        }
+        self.operations.insert(id, request);
+        Ok(())
+    }
+
+    fn publish(&self, event: Event) -> Vec<Value> {
+        let context = self.context.for_event(Some(event));
+        let mut replies = Vec::new();
+        for (id, request) in &self.operations {
+            let response = request.execute(&self.schema, &context);
+            let payload = serde_json::to_value(&response).unwrap_or_default();
+            let has_data = match payload["data"].as_object() {
+                Some(fields) => fields.values().any(|value| !value.is_null()),
+                None => false,
+            };
+            if has_data || !response.is_ok() {
+                replies.push(json!({ "type": "data", "id": id, "payload": payload }));
+            }
+        }
+        replies
+    }
+}
+
+/// Turns the subscription operations in a GraphQL document into queries, so
+/// that juniper executes them. Only the keyword changes, so that positions in
+/// errors still match the original document. Fails if the document contains
+/// other operations.
+fn subscription_as_query(document: &str) -> Result<String, &'static str> {
+    const KEYWORD: &str = "subscription";
+    let bytes = document.as_bytes();
+    let mut keywords = Vec::new();
+    // Selection sets and arguments of the current definition, and whether one
+    // has been started by its keyword.
+    let (mut depth, mut parens, mut definition) = (0, 0, false);
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'(' => parens += 1,
+            b')' => parens -= 1,
+            b'{' => {
+                if depth == 0 && parens == 0 && !definition {
+                    return Err("only subscription operations are supported");
+                }
+                depth += 1;
+            }
+            b'}' => {
+                depth -= 1;
+                if depth == 0 && parens == 0 {
+                    definition = false;
+                }
+            }
+            b if b == b'_' || b.is_ascii_alphabetic() => {
+                let start = i;
+                while i + 1 < bytes.len()
+                    && (bytes[i + 1] == b'_' || bytes[i + 1].is_ascii_alphanumeric())
+                {
+                    i += 1;
+                }
+                if depth == 0 && parens == 0 && !definition {
+                    match &document[start..=i] {
+                        KEYWORD => keywords.push(start),
+                        "fragment" => {}
+                        _ => return Err("only subscription operations are supported"),
+                    }
+                    definition = true;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    let mut query = document.to_string();
+    for start in keywords {
+        query.replace_range(
+            start..start + KEYWORD.len(),
+            &format!("{:1$}", "query", KEYWORD.len()),
+        );
+    }
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriptions_become_queries() {
+        let document = "# a subscription { }\n\
+                        subscription Records($track: Int) { trackRecord(track: $track) { ...lap } }\n\
+                        fragment lap on TrackRecord { bestLap }";
+        let query = subscription_as_query(document).unwrap();
+        assert_eq!(query.len(), document.len());
+        assert!(query.starts_with("# a subscription { }\nquery        Records("));
+        assert!(query.contains("fragment lap on TrackRecord"));
+
+        assert!(subscription_as_query("{ raceAdded { replaced } }").is_err());
+        assert!(subscription_as_query("query { seasons { id } }").is_err());
+        assert!(subscription_as_query("subscription { a } mutation { b }").is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use super::races::{self, RaceConnection, RaceFilter, RaceOrder};
+use super::Context;
+use crate::model::{Driver, RaceEntrant, Track};
+use anyhow::Context as _;
+use juniper::FieldResult;
+
+/// How many entries the track leaderboards list by default.
+const DEFAULT_LEADERBOARD_SIZE: usize = 10;
+
+fn leaderboard_size(first: Option<i32>) -> FieldResult<usize> {
+    match first {
+        None => Ok(DEFAULT_LEADERBOARD_SIZE),
+        Some(first) if first >= 0 => Ok(first as usize),
+        Some(first) => Err(format!("invalid leaderboard size {}", first).into()),
+    }
+}
+
+/// The number of races a driver has won on a track.
+#[derive(Debug, Clone)]
+pub(crate) struct TrackWins {
+    pub(crate) driver_id: i32,
+    pub(crate) wins: i32,
+}
+
+#[juniper::object(Context = Context)]
+impl TrackWins {
+    fn driver(&self, context: &Context) -> FieldResult<Driver> {
+        Ok(context
+            .loaders
+            .driver(&context.db()?, self.driver_id)?
+            .context("driver not found")?)
+    }
+
+    fn wins(&self) -> i32 {
+        self.wins
+    }
+}
+
+#[juniper::object(Context = Context)]
+impl Track {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn races(
+        &self,
+        context: &Context,
+        first: Option<i32>,
+        after: Option<String>,
+        order: Option<RaceOrder>,
+    ) -> FieldResult<RaceConnection> {
+        races::races(
+            context,
+            first,
+            after,
+            RaceFilter::track(self.id),
+            order.unwrap_or(RaceOrder::DateDesc),
+        )
+    }
+
+    /// The entry with the fastest lap ever set on the track.
+    fn lap_record(&self, context: &Context) -> FieldResult<Option<RaceEntrant>> {
+        let best_laps = context.loaders.track_best_laps(&context.db()?, self.id)?;
+        Ok(best_laps.into_iter().next())
+    }
+
+    /// The entry with the fastest lap of each driver, fastest first.
+    fn best_laps(&self, context: &Context, first: Option<i32>) -> FieldResult<Vec<RaceEntrant>> {
+        let mut best_laps = context.loaders.track_best_laps(&context.db()?, self.id)?;
+        best_laps.truncate(leaderboard_size(first)?);
+        Ok(best_laps)
+    }
+
+    /// The drivers with the most wins on the track.
+    fn most_wins(&self, context: &Context, first: Option<i32>) -> FieldResult<Vec<TrackWins>> {
+        let mut wins = context.loaders.track_wins(&context.db()?, self.id)?;
+        wins.truncate(leaderboard_size(first)?);
+        Ok(wins)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::api::run_query;
+    use crate::db::TestDb;
+    use diesel::connection::SimpleConnection;
+    use serde_json::json;
+
+    #[test]
+    fn track_queries() {
+        let db = TestDb::new("track_queries");
+        let context = Context::new(db.url()).unwrap();
+        with_conn!(&context.db().unwrap(), |conn| conn.batch_execute(
+            "INSERT INTO tracks (id, name) VALUES (1, 'Sandpit'), (2, 'Monaco');
+             INSERT INTO drivers (id, name) VALUES (1, 'A'), (2, 'B'), (3, 'C');
+             INSERT INTO races (id, date, track_id, laps, mode) VALUES
+                 (10, '2020-05-01', 1, 5, 'laps'),
+                 (11, '2020-05-02', 1, 5, 'laps'),
+                 (12, '2020-05-03', 2, 5, 'laps');
+             INSERT INTO race_entrants
+                 (race_id, driver_id, position, best_lap, reason, fps_locked) VALUES
+                 (10, 1, 1, 60000, NULL, 0),
+                 (10, 2, 2, 59500, NULL, 0),
+                 (11, 1, 1, 61000, NULL, 0),
+                 (11, 2, 2, 59000, NULL, 0),
+                 (11, 3, 3, 50000, 'dsq', 0),
+                 (12, 3, 1, 70000, NULL, 0);"
+        ))
+        .unwrap();
+
+        let data = run_query(
+            &context,
+            r#"{
+                tracks { name }
+                track(id: 2) { name }
+                missing: trackName(name: "Nowhere") { id }
+                trackName(name: "Sandpit") {
+                    id
+                    lapRecord { bestLap driver { name } race { id } }
+                    bestLaps { bestLap driver { name } }
+                    fastest: bestLaps(first: 1) { driver { name } }
+                    mostWins { wins driver { name } }
+                    races { totalCount edges { node { id } } }
+                }
+            }"#,
+        );
+        assert_eq!(
+            data,
+            json!({
+                "tracks": [{ "name": "Monaco" }, { "name": "Sandpit" }],
+                "track": { "name": "Monaco" },
+                "missing": null,
+                "trackName": {
+                    "id": 1,
+                    "lapRecord": {
+                        "bestLap": 59000,
+                        "driver": { "name": "B" },
+                        "race": { "id": 11 },
+                    },
+                    "bestLaps": [
+                        { "bestLap": 59000, "driver": { "name": "B" } },
+                        { "bestLap": 60000, "driver": { "name": "A" } },
+                    ],
+                    "fastest": [{ "driver": { "name": "B" } }],
+                    "mostWins": [{ "wins": 2, "driver": { "name": "A" } }],
+                    "races": {
+                        "totalCount": 2,
+                        "edges": [{ "node": { "id": 11 } }, { "node": { "id": 10 } }],
+                    },
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use super::Context;
+use crate::db::DbConnection;
+use crate::model::{RaceEntrant, Vehicle};
+use anyhow::Context as _;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Nullable};
+use juniper::FieldResult;
+
+/// How often a vehicle was picked and how it did, either overall or by a
+/// single driver.
+#[derive(Debug, Clone)]
+pub(crate) struct VehicleStats {
+    pub(crate) vehicle_id: i32,
+    pub(crate) race_count: i32,
+    pub(crate) wins: i32,
+    starts: i32,
+    finishes: i32,
+    position_sum: Option<i64>,
+}
+
+#[juniper::object(Context = Context)]
+impl VehicleStats {
+    fn vehicle(&self, context: &Context) -> FieldResult<Vehicle> {
+        Ok(context
+            .loaders
+            .vehicle(&context.db()?, self.vehicle_id)?
+            .context("vehicle not found")?)
+    }
+
+    /// Number of entries with the vehicle, including ones that did not start.
+    fn race_count(&self) -> i32 {
+        self.race_count
+    }
+
+    fn wins(&self) -> i32 {
+        self.wins
+    }
+
+    /// Wins per race started.
+    fn win_rate(&self) -> Option<f64> {
+        if self.starts > 0 {
+            Some(self.wins as f64 / self.starts as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Average position over the races finished with the vehicle.
+    fn average_position(&self) -> Option<f64> {
+        self.position_sum
+            .filter(|_| self.finishes > 0)
+            .map(|sum| sum as f64 / self.finishes as f64)
+    }
+}
+
+#[derive(QueryableByName)]
+struct StatsRow {
+    #[sql_type = "Integer"]
+    key_id: i32,
+    #[sql_type = "Integer"]
+    vehicle_id: i32,
+    #[sql_type = "BigInt"]
+    race_count: i64,
+    #[sql_type = "BigInt"]
+    wins: i64,
+    #[sql_type = "BigInt"]
+    starts: i64,
+    #[sql_type = "BigInt"]
+    finishes: i64,
+    #[sql_type = "Nullable<BigInt>"]
+    position_sum: Option<i64>,
+}
+
+// See `drivers::STATS_QUERY` for why only COUNT and a cast SUM are used.
+const STATS_QUERY: &str = "
+    SELECT
+        e.{key} AS key_id,
+        e.vehicle_id,
+        COUNT(*) AS race_count,
+        COUNT(CASE WHEN e.reason IS NULL AND e.position = 1 THEN 1 END) AS wins,
+        COUNT(CASE WHEN e.reason IS NULL OR e.reason <> 'dns' THEN 1 END) AS starts,
+        COUNT(CASE WHEN e.reason IS NULL THEN e.position END) AS finishes,
+        CAST(SUM(CASE WHEN e.reason IS NULL THEN e.position END) AS SIGNED) AS position_sum
+    FROM race_entrants e
+    WHERE e.{key} IN ({ids}) AND e.vehicle_id IS NOT NULL
+    GROUP BY e.{key}, e.vehicle_id
+";
+
+/// What vehicle statistics are grouped by, besides the vehicle.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum VehicleStatsKey {
+    /// The overall statistics of each vehicle.
+    Vehicle,
+    /// The vehicles each driver has used.
+    Driver,
+}
+
+impl VehicleStatsKey {
+    /// The entrant column holding the key.
+    fn column(self) -> &'static str {
+        match self {
+            VehicleStatsKey::Vehicle => "vehicle_id",
+            VehicleStatsKey::Driver => "driver_id",
+        }
+    }
+}
+
+/// Loads vehicle statistics grouped by `key`. Returns pairs of the key and
+/// the statistics.
+pub(crate) fn vehicle_stats(
+    db: &DbConnection,
+    key: VehicleStatsKey,
+    key_ids: &[i32],
+) -> anyhow::Result<Vec<(i32, VehicleStats)>> {
+    if key_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ids: Vec<String> = key_ids.iter().map(i32::to_string).collect();
+    let query = STATS_QUERY
+        .replace("{key}", key.column())
+        .replace("{ids}", &ids.join(", "));
+    let rows: Vec<StatsRow> = with_conn!(db, |conn| diesel::sql_query(query).load(conn))?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let stats = VehicleStats {
+                vehicle_id: row.vehicle_id,
+                race_count: row.race_count as i32,
+                wins: row.wins as i32,
+                starts: row.starts as i32,
+                finishes: row.finishes as i32,
+                position_sum: row.position_sum,
+            };
+            (row.key_id, stats)
+        })
+        .collect())
+}
+
+#[juniper::object(Context = Context)]
+impl Vehicle {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn stats(&self, context: &Context) -> FieldResult<Option<VehicleStats>> {
+        Ok(context.loaders.vehicle_stats(&context.db()?, self.id)?)
+    }
+
+    /// The entry with the fastest lap set with the vehicle on each track,
+    /// ordered by track name.
+    fn best_laps(&self, context: &Context) -> FieldResult<Vec<RaceEntrant>> {
+        Ok(context.loaders.vehicle_best_laps(&context.db()?, self.id)?)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::api::run_query;
+    use crate::db::TestDb;
+    use diesel::connection::SimpleConnection;
+    use serde_json::json;
+
+    #[test]
+    fn vehicle_queries() {
+        let db = TestDb::new("vehicle_queries");
+        let context = Context::new(db.url()).unwrap();
+        with_conn!(&context.db().unwrap(), |conn| conn.batch_execute(
+            "INSERT INTO tracks (id, name) VALUES (1, 'Sandpit'), (2, 'Monaco');
+             INSERT INTO drivers (id, name) VALUES (1, 'A'), (2, 'B');
+             INSERT INTO vehicles (id, name, normalized_name) VALUES
+                 (1, 'Formula', 'formula'),
+                 (2, 'Kart', 'kart');
+             INSERT INTO races (id, date, track_id, laps, mode) VALUES
+                 (10, '2020-05-01', 1, 5, 'laps'),
+                 (11, '2020-05-02', 1, 5, 'laps'),
+                 (12, '2020-05-03', 2, 5, 'laps');
+             INSERT INTO race_entrants
+                 (race_id, driver_id, position, vehicle_id, best_lap, reason, fps_locked) VALUES
+                 (10, 1, 1, 1, 60000, NULL, 0),
+                 (10, 2, 2, 2, 59000, NULL, 0),
+                 (11, 1, 1, 2, 58000, 'dsq', 0),
+                 (11, 2, 2, 1, 61000, NULL, 0),
+                 (12, 1, 1, 1, 70000, NULL, 0),
+                 (12, 2, NULL, 1, NULL, 'dns', 0);"
+        ))
+        .unwrap();
+
+        let data = run_query(
+            &context,
+            r#"{
+                vehicles {
+                    name
+                    stats { raceCount wins winRate averagePosition }
+                    bestLaps { bestLap driver { name } race { track { name } } }
+                }
+                driver(id: 1) { vehicles { vehicle { name } raceCount wins } }
+            }"#,
+        );
+        assert_eq!(
+            data,
+            json!({
+                "vehicles": [
+                    {
+                        "name": "Formula",
+                        "stats": {
+                            "raceCount": 4,
+                            "wins": 2,
+                            "winRate": 2.0 / 3.0,
+                            "averagePosition": 4.0 / 3.0,
+                        },
+                        "bestLaps": [
+                            {
+                                "bestLap": 70000,
+                                "driver": { "name": "A" },
+                                "race": { "track": { "name": "Monaco" } },
+                            },
+                            {
+                                "bestLap": 60000,
+                                "driver": { "name": "A" },
+                                "race": { "track": { "name": "Sandpit" } },
+                            },
+                        ],
+                    },
+                    {
+                        "name": "Kart",
+                        "stats": {
+                            "raceCount": 2,
+                            "wins": 0,
+                            "winRate": 0.0,
+                            "averagePosition": 2.0,
+                        },
+                        "bestLaps": [{
+                            "bestLap": 59000,
+                            "driver": { "name": "B" },
+                            "race": { "track": { "name": "Sandpit" } },
+                        }],
+                    },
+                ],
+                "driver": {
+                    "vehicles": [
+                        { "vehicle": { "name": "Formula" }, "raceCount": 2, "wins": 2 },
+                        { "vehicle": { "name": "Kart" }, "raceCount": 1, "wins": 0 },
+                    ],
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::db::DbConnection;
+use crate::model::{ApiToken, NewApiToken, Role};
+use anyhow::bail;
+use diesel::prelude::*;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Creates a random token. Only its hash is stored, so it can't be recovered
+/// later.
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Extracts the token from an `Authorization: Bearer <token>` header.
+pub(crate) fn bearer_token(header: &str) -> Option<&str> {
+    let token = header.trim().strip_prefix("Bearer ")?.trim();
+    if token.is_empty() {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+/// Returns the role of `token`, or `None` if it is unknown or revoked.
+pub(crate) fn token_role(db: &DbConnection, token: &str) -> anyhow::Result<Option<Role>> {
+    use crate::schema::api_tokens::dsl::*;
+    Ok(with_conn!(db, |conn| api_tokens
+        .select(role)
+        .filter(token_hash.eq(hash_token(token)))
+        .filter(revoked_at.is_null())
+        .first(conn)
+        .optional())?)
+}
+
+/// Stores a new token, returning its id and the token itself.
+pub(crate) fn create_token(
+    db: &DbConnection,
+    token_name: &str,
+    token_role: Role,
+) -> anyhow::Result<(i32, String)> {
+    use crate::schema::api_tokens::dsl::*;
+    let token = generate_token();
+    let new_token = NewApiToken {
+        name: token_name.to_string(),
+        token_hash: hash_token(&token),
+        role: token_role,
+        created_at: chrono::Utc::now().naive_utc(),
+    };
+    let token_id = with_conn!(db, |conn| conn.transaction(|| {
+        new_token.clone().insert_into(api_tokens).execute(conn)?;
+        api_tokens
+            .select(id)
+            .filter(token_hash.eq(&new_token.token_hash))
+            .first(conn)
+    }))?;
+    Ok((token_id, token))
+}
+
+pub(crate) fn revoke_token(db: &DbConnection, token_id: i32) -> anyhow::Result<()> {
+    use crate::schema::api_tokens::dsl::*;
+    let updated = with_conn!(db, |conn| diesel::update(
+        api_tokens.find(token_id).filter(revoked_at.is_null())
+    )
+    .set(revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(conn))?;
+    if updated == 0 {
+        bail!("no active token with id {}", token_id);
+    }
+    Ok(())
+}
+
+pub(crate) fn list_tokens(db: &DbConnection) -> anyhow::Result<Vec<ApiToken>> {
+    use crate::schema::api_tokens::dsl::*;
+    Ok(with_conn!(db, |conn| api_tokens
+        .select((id, name, role, created_at, revoked_at))
+        .order(id.asc())
+        .load(conn))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), token);
+
+        assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("Basic abc"), None);
+    }
+}
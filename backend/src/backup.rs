@@ -0,0 +1,823 @@
+//! Backups of the whole database in a format that doesn't depend on the
+//! database engine, so that they can be restored into either backend.
+//!
+//! An archive is a `.tar.gz` with a `manifest.json` followed by the rows of
+//! every table, with a JSON object per row. The rows of a table are split
+//! into batches, `tables/<table>/<batch>.ndjson`, so that a table never has
+//! to be held in memory. Tables come in an order in which every table follows
+//! the ones it refers to, so that references can be checked as the archive is
+//! read.
+
+use crate::db::DbConnection;
+use crate::migrations;
+use crate::model::{ImportOutcome, RaceMode, Reason, Role};
+use crate::schema::{
+    api_tokens, championship_rounds, championships, driver_aliases, driver_ratings, drivers,
+    failed_sources, fetch_cursors, import_log, race_entrants, race_sources, races, seasons, tracks,
+    vehicles,
+};
+use anyhow::{bail, Context};
+use chrono::naive::{NaiveDate, NaiveDateTime};
+use chrono::Utc;
+use diesel::prelude::*;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+const FORMAT: &str = "phr-backup";
+
+/// The version of the archive layout, which is increased when archives
+/// written by a newer build can't be read by an older one.
+const VERSION: u64 = 2;
+
+const MANIFEST: &str = "manifest.json";
+
+/// The number of rows that are read, and written to the archive as one file,
+/// at a time.
+const BATCH_SIZE: i64 = 500;
+
+/// A value of a column, as it is stored in an archive.
+trait ArchiveValue: Sized {
+    fn to_json(&self) -> Value;
+    fn from_json(value: &Value) -> Option<Self>;
+}
+
+impl ArchiveValue for i32 {
+    fn to_json(&self) -> Value {
+        (*self).into()
+    }
+
+    fn from_json(value: &Value) -> Option<i32> {
+        i32::try_from(value.as_i64()?).ok()
+    }
+}
+
+impl ArchiveValue for f64 {
+    fn to_json(&self) -> Value {
+        (*self).into()
+    }
+
+    fn from_json(value: &Value) -> Option<f64> {
+        value.as_f64()
+    }
+}
+
+impl ArchiveValue for bool {
+    fn to_json(&self) -> Value {
+        (*self).into()
+    }
+
+    fn from_json(value: &Value) -> Option<bool> {
+        value.as_bool()
+    }
+}
+
+impl ArchiveValue for String {
+    fn to_json(&self) -> Value {
+        self.clone().into()
+    }
+
+    fn from_json(value: &Value) -> Option<String> {
+        value.as_str().map(str::to_string)
+    }
+}
+
+impl ArchiveValue for Vec<u8> {
+    fn to_json(&self) -> Value {
+        hex::encode(self).into()
+    }
+
+    fn from_json(value: &Value) -> Option<Vec<u8>> {
+        hex::decode(value.as_str()?).ok()
+    }
+}
+
+impl ArchiveValue for NaiveDate {
+    fn to_json(&self) -> Value {
+        self.to_string().into()
+    }
+
+    fn from_json(value: &Value) -> Option<NaiveDate> {
+        value.as_str()?.parse().ok()
+    }
+}
+
+impl ArchiveValue for NaiveDateTime {
+    fn to_json(&self) -> Value {
+        self.format("%Y-%m-%dT%H:%M:%S%.f").to_string().into()
+    }
+
+    fn from_json(value: &Value) -> Option<NaiveDateTime> {
+        value.as_str()?.parse().ok()
+    }
+}
+
+impl<T: ArchiveValue> ArchiveValue for Option<T> {
+    fn to_json(&self) -> Value {
+        self.as_ref().map_or(Value::Null, T::to_json)
+    }
+
+    /// Missing columns are read as null too, so that archives from before a
+    /// nullable column was added can be restored.
+    fn from_json(value: &Value) -> Option<Option<T>> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_json(value).map(Some),
+        }
+    }
+}
+
+/// Stores the variants of an enum by name.
+macro_rules! archive_enum {
+    ($ty:ident { $($variant:ident => $name:literal),* $(,)? }) => {
+        impl ArchiveValue for $ty {
+            fn to_json(&self) -> Value {
+                match self {
+                    $($ty::$variant => $name.into(),)*
+                }
+            }
+
+            fn from_json(value: &Value) -> Option<$ty> {
+                match value.as_str()? {
+                    $($name => Some($ty::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+archive_enum!(Role {
+    Viewer => "viewer",
+    Organiser => "organiser",
+    Admin => "admin",
+});
+
+archive_enum!(Reason {
+    Dns => "dns",
+    Dnf => "dnf",
+    Dsq => "dsq",
+});
+
+archive_enum!(RaceMode {
+    Laps => "laps",
+    Minutes => "minutes",
+    Rally => "rally",
+    PointToPoint => "point_to_point",
+});
+
+archive_enum!(ImportOutcome {
+    Imported => "imported",
+    Replaced => "replaced",
+    Unchanged => "unchanged",
+    Failed => "failed",
+});
+
+fn read_field<T: ArchiveValue>(
+    row: &Map<String, Value>,
+    table: &str,
+    column: &str,
+) -> anyhow::Result<T> {
+    let value = row.get(column).unwrap_or(&Value::Null);
+    T::from_json(value).with_context(|| format!("invalid {}.{}: {}", table, column, value))
+}
+
+/// Declares the tables that are archived, each with a row struct whose
+/// fields are in the same order as the columns in `schema.rs`.
+///
+/// The tables are listed in the order they are restored in, so every table
+/// must come after the ones it refers to.
+macro_rules! archive_tables {
+    ($($table:ident: $row:ident { $($field:ident),* $(,)? }),* $(,)?) => {
+        const TABLES: &[&str] = &[$(stringify!($table)),*];
+
+        $(
+            impl $row {
+                fn to_json(&self) -> Value {
+                    let mut row = Map::new();
+                    $(row.insert(stringify!($field).to_string(), self.$field.to_json());)*
+                    Value::Object(row)
+                }
+
+                fn from_json(row: &Map<String, Value>) -> anyhow::Result<$row> {
+                    Ok($row {
+                        $($field: read_field(row, stringify!($table), stringify!($field))?,)*
+                    })
+                }
+            }
+        )*
+
+        /// Loads up to `BATCH_SIZE` rows of a table, skipping the first
+        /// `offset` in the order of its primary key.
+        fn load_batch(db: &DbConnection, table: &str, offset: i64) -> anyhow::Result<Vec<Value>> {
+            match table {
+                $(stringify!($table) => {
+                    let rows: Vec<$row> = with_conn!(db, |conn| $table::table
+                        .order($table::table.primary_key())
+                        .limit(BATCH_SIZE)
+                        .offset(offset)
+                        .load(conn))?;
+                    Ok(rows.iter().map($row::to_json).collect())
+                })*
+                _ => bail!("unknown table {:?}", table),
+            }
+        }
+
+        fn count_rows(db: &DbConnection, table: &str) -> anyhow::Result<i64> {
+            match table {
+                $(stringify!($table) => Ok(with_conn!(db, |conn| {
+                    $table::table.count().get_result(conn)
+                })?),)*
+                _ => bail!("unknown table {:?}", table),
+            }
+        }
+
+        fn insert_row(
+            db: &DbConnection,
+            table: &str,
+            row: &Map<String, Value>,
+        ) -> anyhow::Result<()> {
+            match table {
+                $(stringify!($table) => {
+                    let row = $row::from_json(row)?;
+                    with_conn!(db, |conn| row.insert_into($table::table).execute(conn))?;
+                })*
+                _ => bail!("unknown table {:?}", table),
+            }
+            Ok(())
+        }
+    };
+}
+
+archive_tables! {
+    tracks: TrackRow { id, name },
+    vehicles: VehicleRow { id, name, normalized_name },
+    drivers: DriverRow { id, name },
+    driver_aliases: DriverAliasRow { name, driver_id },
+    races: RaceRow { id, date, track_id, laps, minutes, mode },
+    race_entrants: RaceEntrantRow {
+        race_id, driver_id, position, vehicle_id, time, gap, best_lap, lap, reason, ping, fps,
+        fps_locked, entered_as, row_class, name_class, extras,
+    },
+    race_sources: RaceSourceRow { race_id, document },
+    failed_sources: FailedSourceRow { race_id, date, document, error, failed_at },
+    driver_ratings: DriverRatingRow { race_id, driver_id, rating, delta },
+    seasons: SeasonRow { id, name },
+    championships: ChampionshipRow {
+        id, season_id, name, points, best_lap_points, drop_rounds,
+    },
+    championship_rounds: ChampionshipRoundRow { championship_id, race_id },
+    fetch_cursors: FetchCursorRow { base_url, race_id, date },
+    import_log: ImportLogRow { id, file, race_id, outcome, error, logged_at },
+    api_tokens: ApiTokenRow { id, name, token_hash, role, created_at, revoked_at },
+}
+
+/// The columns that refer to the `id` of another table, as in the
+/// `joinable!` declarations of `schema.rs`.
+const REFERENCES: &[(&str, &str, &str)] = &[
+    ("championship_rounds", "championship_id", "championships"),
+    ("championship_rounds", "race_id", "races"),
+    ("championships", "season_id", "seasons"),
+    ("driver_aliases", "driver_id", "drivers"),
+    ("driver_ratings", "driver_id", "drivers"),
+    ("driver_ratings", "race_id", "races"),
+    ("race_entrants", "driver_id", "drivers"),
+    ("race_entrants", "race_id", "races"),
+    ("race_entrants", "vehicle_id", "vehicles"),
+    ("race_sources", "race_id", "races"),
+    ("races", "track_id", "tracks"),
+];
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "tracks"]
+struct TrackRow {
+    id: i32,
+    name: String,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "vehicles"]
+struct VehicleRow {
+    id: i32,
+    name: String,
+    normalized_name: String,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "drivers"]
+struct DriverRow {
+    id: i32,
+    name: String,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "driver_aliases"]
+struct DriverAliasRow {
+    name: String,
+    driver_id: i32,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "races"]
+struct RaceRow {
+    id: i32,
+    date: NaiveDate,
+    track_id: i32,
+    laps: Option<i32>,
+    minutes: Option<i32>,
+    mode: Option<RaceMode>,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "race_entrants"]
+struct RaceEntrantRow {
+    race_id: i32,
+    driver_id: i32,
+    position: Option<i32>,
+    vehicle_id: Option<i32>,
+    time: Option<i32>,
+    gap: Option<i32>,
+    best_lap: Option<i32>,
+    lap: Option<i32>,
+    reason: Option<Reason>,
+    ping: Option<i32>,
+    fps: Option<i32>,
+    fps_locked: bool,
+    entered_as: Option<String>,
+    row_class: Option<String>,
+    name_class: Option<String>,
+    extras: Option<String>,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "race_sources"]
+struct RaceSourceRow {
+    race_id: i32,
+    document: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "failed_sources"]
+struct FailedSourceRow {
+    race_id: i32,
+    date: NaiveDate,
+    document: Vec<u8>,
+    error: String,
+    failed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "driver_ratings"]
+struct DriverRatingRow {
+    race_id: i32,
+    driver_id: i32,
+    rating: f64,
+    delta: f64,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "seasons"]
+struct SeasonRow {
+    id: i32,
+    name: String,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "championships"]
+struct ChampionshipRow {
+    id: i32,
+    season_id: i32,
+    name: String,
+    points: String,
+    best_lap_points: i32,
+    drop_rounds: i32,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "championship_rounds"]
+struct ChampionshipRoundRow {
+    championship_id: i32,
+    race_id: i32,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "fetch_cursors"]
+struct FetchCursorRow {
+    base_url: String,
+    race_id: i32,
+    date: NaiveDate,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "import_log"]
+struct ImportLogRow {
+    id: i32,
+    file: String,
+    race_id: Option<i32>,
+    outcome: ImportOutcome,
+    error: Option<String>,
+    logged_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "api_tokens"]
+struct ApiTokenRow {
+    id: i32,
+    name: String,
+    token_hash: String,
+    role: Role,
+    created_at: NaiveDateTime,
+    revoked_at: Option<NaiveDateTime>,
+}
+
+/// The number of rows of each table that was backed up or restored.
+pub type TableCounts = Vec<(&'static str, usize)>;
+
+/// Writes every table to `out` as a gzipped archive. The tables are read in
+/// one transaction, so that they are consistent with each other.
+pub(crate) fn backup<W: Write>(db: &DbConnection, out: W) -> anyhow::Result<TableCounts> {
+    let mut archive = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+    let now = Utc::now();
+
+    let mut append = |path: &str, data: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(now.timestamp() as u64);
+        archive.append_data(&mut header, path, data)
+    };
+
+    let manifest = json!({
+        "format": FORMAT,
+        "version": VERSION,
+        "schema": migrations::latest_version(db),
+        "created_at": now.naive_utc().to_json(),
+        "tables": TABLES,
+    });
+    append(MANIFEST, &serde_json::to_vec_pretty(&manifest)?)?;
+
+    let counts = with_conn!(db, |conn| conn.transaction(|| {
+        let mut counts = Vec::new();
+        for &table in TABLES {
+            // Every table has at least one batch, even if it is empty, so
+            // that restoring can tell that no table is missing.
+            let mut count = 0;
+            for batch in 0.. {
+                let rows = load_batch(db, table, count as i64)?;
+                let mut data = Vec::new();
+                for row in &rows {
+                    serde_json::to_writer(&mut data, row)?;
+                    data.push(b'\n');
+                }
+                append(&batch_path(table, batch), &data)?;
+                count += rows.len();
+                if rows.len() < BATCH_SIZE as usize {
+                    break;
+                }
+            }
+            counts.push((table, count));
+        }
+        Ok::<_, anyhow::Error>(counts)
+    }))?;
+
+    archive.into_inner()?.finish()?.flush()?;
+    Ok(counts)
+}
+
+fn batch_path(table: &str, batch: usize) -> String {
+    format!("tables/{}/{}.ndjson", table, batch)
+}
+
+/// The table whose rows are in the file at `path`. Archives of version 1
+/// have a single `tables/<table>.ndjson` for every table.
+fn archived_table(path: &Path) -> Option<&'static str> {
+    let name = path
+        .to_str()?
+        .strip_prefix("tables/")?
+        .strip_suffix(".ndjson")?;
+    let table = match name.split_once('/') {
+        Some((table, batch)) if batch.parse::<usize>().is_ok() => table,
+        Some(_) => return None,
+        None => name,
+    };
+    TABLES.iter().copied().find(|&archived| archived == table)
+}
+
+/// Restores a backup written by `backup` into a database without any rows.
+///
+/// Every row is checked to only refer to rows that come before it, every
+/// table that the manifest lists must be in the archive, and the whole
+/// restore happens in one transaction, so a broken archive leaves the
+/// database empty.
+pub(crate) fn restore<R: Read>(db: &DbConnection, input: R) -> anyhow::Result<TableCounts> {
+    if !migrations::pending(db)?.is_empty() {
+        bail!("database has pending migrations");
+    }
+    for &table in TABLES {
+        if count_rows(db, table)? > 0 {
+            bail!(
+                "can only restore into an empty database, but {} has rows",
+                table
+            );
+        }
+    }
+
+    let mut archive = tar::Archive::new(GzDecoder::new(input));
+    let mut entries = archive.entries()?;
+
+    let mut manifest = entries.next().context("archive is empty")??;
+    if manifest.path()?.to_str() != Some(MANIFEST) {
+        bail!("archive does not start with {}", MANIFEST);
+    }
+    let manifest: Value = serde_json::from_reader(&mut manifest).context("invalid manifest")?;
+    check_manifest(&manifest, migrations::latest_version(db))?;
+    let archived: Vec<&str> = manifest["tables"]
+        .as_array()
+        .and_then(|tables| tables.iter().map(Value::as_str).collect())
+        .context("manifest does not list the archived tables")?;
+
+    with_conn!(db, |conn| conn.transaction(|| {
+        let mut keys = Keys::default();
+        let mut counts: TableCounts = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path()?.into_owned();
+            let table = archived_table(&path)
+                .with_context(|| format!("unexpected {} in archive", path.display()))?;
+            let position = |table| TABLES.iter().position(|&t| t == table);
+            match counts.last() {
+                Some(&(last, _)) if last == table => {}
+                Some(&(last, _)) if position(last) > position(table) => {
+                    bail!("{} is out of order in archive", path.display());
+                }
+                _ => counts.push((table, 0)),
+            }
+
+            let count = &mut counts.last_mut().unwrap().1;
+            for line in BufReader::new(entry).lines() {
+                *count += 1;
+                let context = || format!("{} row {}", table, count);
+                let row: Map<String, Value> = serde_json::from_str(&line?).with_context(context)?;
+                keys.check(table, &row).with_context(context)?;
+                insert_row(db, table, &row).with_context(context)?;
+                keys.add(table, &row);
+            }
+        }
+
+        let missing: Vec<&str> = archived
+            .iter()
+            .copied()
+            .filter(|&table| !counts.iter().any(|&(restored, _)| restored == table))
+            .collect();
+        if !missing.is_empty() {
+            bail!("archive is missing tables {}", missing.join(", "));
+        }
+        Ok::<_, anyhow::Error>(counts)
+    }))
+}
+
+fn check_manifest(manifest: &Value, schema: &str) -> anyhow::Result<()> {
+    if manifest["format"] != FORMAT {
+        bail!("not a PHR backup");
+    }
+    match manifest["version"].as_u64() {
+        Some(version) if version <= VERSION => {}
+        _ => bail!(
+            "backup version {} is newer than this build supports",
+            manifest["version"]
+        ),
+    }
+    // Migration versions are the same for every backend, so comparing them
+    // works across database engines.
+    match manifest["schema"].as_str() {
+        Some(archived) if archived <= schema => Ok(()),
+        _ => bail!(
+            "backup is of schema {}, which is newer than this build's {}",
+            manifest["schema"],
+            schema
+        ),
+    }
+}
+
+/// The ids of the rows read so far from the tables that others refer to.
+#[derive(Debug, Default)]
+struct Keys {
+    ids: HashMap<&'static str, HashSet<i64>>,
+}
+
+impl Keys {
+    fn add(&mut self, table: &str, row: &Map<String, Value>) {
+        let referenced = REFERENCES.iter().find(|&&(_, _, parent)| parent == table);
+        if let (Some(&(_, _, parent)), Some(id)) =
+            (referenced, row.get("id").and_then(Value::as_i64))
+        {
+            self.ids.entry(parent).or_default().insert(id);
+        }
+    }
+
+    /// Fails if the row refers to a row that has not been read.
+    fn check(&self, table: &str, row: &Map<String, Value>) -> anyhow::Result<()> {
+        for &(_, column, parent) in REFERENCES.iter().filter(|&&(child, _, _)| child == table) {
+            let value = row.get(column).unwrap_or(&Value::Null);
+            if value.is_null() {
+                continue;
+            }
+            let found = value
+                .as_i64()
+                .is_some_and(|id| self.ids.get(parent).is_some_and(|ids| ids.contains(&id)));
+            if !found {
+                bail!("{} {} refers to a missing row of {}", column, value, parent);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The names in the `$macro! { <name> ... }` declarations of `schema.rs`.
+    fn schema_declarations(schema: &str, declaration: &str) -> Vec<String> {
+        schema
+            .lines()
+            .map(str::trim)
+            .filter_map(|line| line.strip_prefix(declaration))
+            .map(|rest| rest.trim_start_matches(['(', ' ']).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn covers_schema() {
+        let schema = include_str!("schema.rs");
+        let mut archived: Vec<&str> = TABLES.to_vec();
+        archived.sort();
+        let mut tables: Vec<String> = schema
+            .split("table! {")
+            .skip(1)
+            .filter_map(|table| table.split_whitespace().next().map(str::to_string))
+            .collect();
+        tables.sort();
+        assert_eq!(archived, tables);
+
+        let mut references: Vec<String> = REFERENCES
+            .iter()
+            .map(|(table, column, parent)| format!("{} -> {} ({}));", table, parent, column))
+            .collect();
+        references.sort();
+        let mut joinables = schema_declarations(schema, "joinable!");
+        joinables.sort();
+        assert_eq!(references, joinables);
+
+        for (table, _, parent) in REFERENCES {
+            let position = |name| TABLES.iter().position(|t| t == name).unwrap();
+            assert!(
+                position(parent) < position(table),
+                "{} before {}",
+                table,
+                parent
+            );
+        }
+    }
+
+    #[test]
+    fn rows_round_trip() {
+        let row = RaceEntrantRow {
+            race_id: 1,
+            driver_id: 2,
+            position: Some(1),
+            vehicle_id: None,
+            time: Some(312_345),
+            gap: Some(0),
+            best_lap: Some(61_234),
+            lap: Some(5),
+            reason: Some(Reason::Dsq),
+            ping: None,
+            fps: Some(60),
+            fps_locked: true,
+            entered_as: Some("Some Driver".to_string()),
+            row_class: None,
+            name_class: Some("gt0".to_string()),
+            extras: None,
+        };
+        let json = row.to_json();
+        let read = RaceEntrantRow::from_json(json.as_object().unwrap()).unwrap();
+        assert_eq!(read.to_json(), json);
+
+        let logged_at = "2020-06-04T18:30:15.250".parse::<NaiveDateTime>().unwrap();
+        assert_eq!(
+            NaiveDateTime::from_json(&logged_at.to_json()),
+            Some(logged_at)
+        );
+
+        let mut older = json.as_object().unwrap().clone();
+        older.remove("extras");
+        assert!(RaceEntrantRow::from_json(&older).is_ok());
+        older.remove("fps_locked");
+        assert!(RaceEntrantRow::from_json(&older).is_err());
+    }
+
+    #[test]
+    fn checks_references() {
+        let row = |value: Value| value.as_object().unwrap().clone();
+        let mut keys = Keys::default();
+        keys.add("tracks", &row(json!({ "id": 3, "name": "Sandpit" })));
+
+        let race = row(json!({ "id": 1, "track_id": 3 }));
+        assert!(keys.check("races", &race).is_ok());
+        assert!(keys
+            .check("races", &row(json!({ "id": 2, "track_id": 4 })))
+            .is_err());
+        keys.add("races", &race);
+
+        let entrant = json!({ "race_id": 1, "driver_id": 5, "vehicle_id": null });
+        assert!(keys.check("race_entrants", &row(entrant)).is_err());
+        keys.add("drivers", &row(json!({ "id": 5, "name": "Some Driver" })));
+        let entrant = json!({ "race_id": 1, "driver_id": 5, "vehicle_id": null });
+        assert!(keys.check("race_entrants", &row(entrant)).is_ok());
+    }
+
+    #[test]
+    fn archived_tables() {
+        let table = |path: &str| archived_table(Path::new(path));
+        assert_eq!(table("tables/drivers/2.ndjson"), Some("drivers"));
+        assert_eq!(table("tables/drivers.ndjson"), Some("drivers"));
+        assert_eq!(table("tables/drivers/x.ndjson"), None);
+        assert_eq!(table("tables/pit_stops/0.ndjson"), None);
+        assert_eq!(table("drivers.ndjson"), None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn restores_queries() {
+        use crate::api::{run_query, Context};
+        use crate::db::TestDb;
+        use diesel::connection::SimpleConnection;
+
+        let query = r#"{
+            drivers(first: 3) { totalCount edges { node { name rating aliases } } }
+            race(id: 10) {
+                date
+                track { name }
+                laps
+                mode
+                entrants {
+                    position
+                    driver { name }
+                    vehicle { name }
+                    time
+                    gap
+                    reason
+                    fpsLocked
+                    enteredAs
+                }
+            }
+            driver(id: 2) { ratingHistory { race { id } rating delta } }
+        }"#;
+
+        let original = TestDb::new("backup");
+        let context = Context::new(original.url()).unwrap();
+        let db = context.db.as_ref().unwrap().get().unwrap();
+        // More drivers than fit in a batch, so that they are split.
+        let drivers = BATCH_SIZE + 10;
+        with_conn!(&db, |conn| conn.batch_execute(&format!(
+            "INSERT INTO tracks (id, name) VALUES (1, 'Sandpit');
+             INSERT INTO vehicles (id, name, normalized_name) VALUES (1, 'Formula', 'formula');
+             WITH RECURSIVE n(id) AS (SELECT 1 UNION ALL SELECT id + 1 FROM n WHERE id < {})
+                 INSERT INTO drivers (id, name) SELECT id, 'Driver ' || id FROM n;
+             INSERT INTO driver_aliases (name, driver_id) VALUES ('Alias', 1);
+             INSERT INTO races (id, date, track_id, laps, mode) VALUES
+                 (10, '2020-05-01', 1, 5, 'laps'),
+                 (11, '2020-05-02', 1, 5, 'laps');
+             INSERT INTO race_entrants
+                 (race_id, driver_id, position, vehicle_id, time, gap, reason, fps_locked,
+                  entered_as) VALUES
+                 (10, 1, 1, 1, 312345, 0, NULL, 1, 'Alias'),
+                 (10, 2, 2, 1, 313345, 1000, NULL, 0, NULL),
+                 (10, 3, 3, NULL, NULL, NULL, 'dnf', 0, NULL),
+                 (11, 2, 1, 1, 300000, 0, NULL, 0, NULL),
+                 (11, 1, 2, 1, 301000, 1000, NULL, 0, NULL);",
+            drivers
+        )))
+        .unwrap();
+        crate::ratings::rebuild(&db).unwrap();
+
+        let mut archive = Vec::new();
+        let counts = backup(&db, &mut archive).unwrap();
+        assert!(counts.contains(&("drivers", drivers as usize)));
+
+        let copy = TestDb::new("restore");
+        let restored = Context::new(copy.url()).unwrap();
+        let restored_counts = restore(&restored.db.as_ref().unwrap().get().unwrap(), &archive[..]);
+        assert_eq!(restored_counts.unwrap(), counts);
+
+        let data = run_query(&context, query);
+        assert_eq!(data["drivers"]["totalCount"], drivers);
+        assert_eq!(run_query(&restored, query), data);
+    }
+}
@@ -1,23 +1,40 @@
-use anyhow::Context;
+use anyhow::{bail, Context};
 use chrono::naive::NaiveDate;
 use dotenv::dotenv;
-use phr_backend::Database;
+use phr_backend::{AddMode, Database, ParseOptions};
 use std::{env, fs};
 
-const USAGE: &str = "usage: add_race <file> <id> <YYYY-MM-dd>";
+const USAGE: &str =
+    "usage: add_race [--replace] [--gaps=interval|leader] [--lenient] <file> <id> <YYYY-MM-dd>";
 
 fn main() -> anyhow::Result<()> {
-    let mut args = env::args();
-    let file = args.nth(1).context(USAGE)?;
+    let mut args = env::args().skip(1).peekable();
+    let mut mode = AddMode::Insert;
+    let mut options = ParseOptions::default();
+    while let Some(flag) = args.peek().filter(|arg| arg.starts_with("--")).cloned() {
+        args.next();
+        if flag == "--replace" {
+            mode = AddMode::Replace;
+        } else if flag.starts_with("--gaps=") {
+            options.gaps = flag["--gaps=".len()..].parse()?;
+        } else if flag == "--lenient" {
+            options.lenient = true;
+        } else {
+            bail!(USAGE);
+        }
+    }
+    let file = args.next().context(USAGE)?;
     let id: i32 = args.next().context(USAGE)?.parse().context(USAGE)?;
     let date: NaiveDate = args.next().context(USAGE)?.parse().context(USAGE)?;
     let results = fs::read_to_string(&file)?;
 
     dotenv().ok();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let database = Database::connect(&database_url)?;
+    let mut database = Database::connect(&database_url)?;
+    database.set_parse_options(options);
 
-    database.add_race(id, date, &results)?;
+    let added = database.add_race(id, date, &results, mode)?;
+    println!("{}", added);
 
     Ok(())
 }
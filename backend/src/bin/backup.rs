@@ -0,0 +1,22 @@
+use anyhow::Context;
+use dotenv::dotenv;
+use phr_backend::Database;
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+
+const USAGE: &str = "usage: backup <archive.tar.gz>";
+
+fn main() -> anyhow::Result<()> {
+    let path = env::args().nth(1).context(USAGE)?;
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Database::connect(&database_url)?;
+
+    let file = File::create(&path).with_context(|| format!("cannot create {}", path))?;
+    for (table, rows) in database.backup(BufWriter::new(file))? {
+        println!("{:>8} {}", rows, table);
+    }
+    Ok(())
+}
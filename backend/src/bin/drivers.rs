@@ -0,0 +1,38 @@
+use anyhow::{bail, Context};
+use dotenv::dotenv;
+use phr_backend::Database;
+use std::env;
+
+const USAGE: &str = "usage: drivers merge <source-id> <target-id>
+       drivers split <alias>
+       drivers rename <id> <name>";
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Database::connect(&database_url)?;
+
+    match args.as_slice() {
+        ["merge", source, target] => {
+            let source: i32 = source.parse().context(USAGE)?;
+            let target: i32 = target.parse().context(USAGE)?;
+            database.merge_drivers(source, target)?;
+            println!("merged driver {} into {}", source, target);
+        }
+        ["split", alias] => {
+            let id = database.split_alias(alias)?;
+            println!("split {:?} out into driver {}", alias, id);
+        }
+        ["rename", id, name] => {
+            let id: i32 = id.parse().context(USAGE)?;
+            database.rename_driver(id, name)?;
+            println!("renamed driver {} to {:?}", id, name);
+        }
+        _ => bail!(USAGE),
+    }
+
+    Ok(())
+}
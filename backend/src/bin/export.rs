@@ -0,0 +1,35 @@
+use anyhow::{bail, Context};
+use dotenv::dotenv;
+use phr_backend::{Database, ExportFilter, ExportFormat, ExportTable};
+use std::env;
+use std::io::{self, BufWriter};
+
+const USAGE: &str = "usage: export [--format=csv|json|ndjson] [--from=YYYY-MM-dd] [--to=YYYY-MM-dd] [--track=<name>] <races|entrants|drivers>";
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1).peekable();
+    let mut format = ExportFormat::Csv;
+    let mut filter = ExportFilter::default();
+    while let Some(flag) = args.peek().filter(|arg| arg.starts_with("--")).cloned() {
+        args.next();
+        if let Some(name) = flag.strip_prefix("--format=") {
+            format = name.parse()?;
+        } else if let Some(date) = flag.strip_prefix("--from=") {
+            filter.from = Some(date.parse().context(USAGE)?);
+        } else if let Some(date) = flag.strip_prefix("--to=") {
+            filter.to = Some(date.parse().context(USAGE)?);
+        } else if let Some(track) = flag.strip_prefix("--track=") {
+            filter.track = Some(track.to_string());
+        } else {
+            bail!(USAGE);
+        }
+    }
+    let table: ExportTable = args.next().context(USAGE)?.parse()?;
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Database::connect(&database_url)?;
+
+    let stdout = io::stdout();
+    database.export(table, format, &filter, BufWriter::new(stdout.lock()))
+}
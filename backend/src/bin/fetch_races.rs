@@ -0,0 +1,63 @@
+use anyhow::{bail, Context};
+use chrono::naive::NaiveDate;
+use dotenv::dotenv;
+use phr_backend::{Database, FetchOptions, Fetcher, ParseOptions};
+use std::time::Duration;
+use std::{env, thread};
+
+const USAGE: &str = "usage: fetch_races [--gaps=interval|leader] [--lenient] \
+                     [--after=<id>,<YYYY-MM-dd>] [--every=<seconds>] <base URL>";
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1).peekable();
+    let mut options = ParseOptions::default();
+    let mut after: Option<(i32, NaiveDate)> = None;
+    let mut every = None;
+    while let Some(flag) = args.peek().filter(|arg| arg.starts_with("--")).cloned() {
+        args.next();
+        if let Some(gaps) = flag.strip_prefix("--gaps=") {
+            options.gaps = gaps.parse()?;
+        } else if flag == "--lenient" {
+            options.lenient = true;
+        } else if let Some(race) = flag.strip_prefix("--after=") {
+            let (id, date) = race.split_at(race.find(',').context(USAGE)?);
+            after = Some((
+                id.parse().context(USAGE)?,
+                date[1..].parse().context(USAGE)?,
+            ));
+        } else if let Some(seconds) = flag.strip_prefix("--every=") {
+            every = Some(Duration::from_secs(seconds.parse().context(USAGE)?));
+        } else {
+            bail!(USAGE);
+        }
+    }
+    let base_url = args.next().context(USAGE)?;
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let mut database = Database::connect(&database_url)?;
+    database.set_parse_options(options);
+
+    let mut fetcher = Fetcher::new(&base_url, FetchOptions::default())?;
+    if let Some((id, date)) = after {
+        database.set_fetch_cursor(&fetcher, id, date)?;
+    }
+
+    loop {
+        match database.fetch_races(&mut fetcher) {
+            Ok(added) => {
+                for race in &added {
+                    println!("{}", race);
+                }
+                println!("{} races imported", added.len());
+            }
+            // Keep polling; the failed race is tried again next time.
+            Err(err) if every.is_some() => println!("failed: {:#}", err),
+            Err(err) => return Err(err),
+        }
+        match every {
+            Some(every) => thread::sleep(every),
+            None => return Ok(()),
+        }
+    }
+}
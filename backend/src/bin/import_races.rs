@@ -0,0 +1,78 @@
+use anyhow::{bail, Context};
+use dotenv::dotenv;
+use phr_backend::{for_each_result_file, AddMode, Database, ParseOptions, DEFAULT_EXTENSIONS};
+use std::env;
+use std::path::Path;
+
+const USAGE: &str =
+    "usage: import_races [--gaps=interval|leader] [--lenient] [--extensions=html,csv,json] <results dir or .tar.gz>";
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1).peekable();
+    let mut options = ParseOptions::default();
+    let mut extensions: Vec<String> = DEFAULT_EXTENSIONS
+        .iter()
+        .map(|ext| ext.to_string())
+        .collect();
+    while let Some(flag) = args.peek().filter(|arg| arg.starts_with("--")).cloned() {
+        args.next();
+        if flag.starts_with("--gaps=") {
+            options.gaps = flag["--gaps=".len()..].parse()?;
+        } else if flag == "--lenient" {
+            options.lenient = true;
+        } else if flag.starts_with("--extensions=") {
+            extensions = flag["--extensions=".len()..]
+                .split(',')
+                .map(str::to_string)
+                .collect();
+        } else {
+            bail!(USAGE);
+        }
+    }
+    let source = args.next().context(USAGE)?;
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let mut database = Database::connect(&database_url)?;
+    database.set_parse_options(options);
+
+    let mut imported = 0;
+    let mut skipped = 0;
+    let mut failed = 0;
+    for_each_result_file(Path::new(&source), &extensions, |path, file| {
+        let result = file.and_then(|file| {
+            if database.has_race(file.id)? {
+                return Ok(None);
+            }
+            database
+                .add_race(file.id, file.date, &file.results, AddMode::Insert)
+                .map(Some)
+        });
+        match result {
+            Ok(Some(added)) => {
+                imported += 1;
+                println!("imported {} ({})", path.display(), added.format);
+                for warning in &added.warnings {
+                    println!("  warning: {}", warning);
+                }
+            }
+            Ok(None) => {
+                skipped += 1;
+                println!("skipped  {} (already stored)", path.display());
+            }
+            Err(err) => {
+                failed += 1;
+                println!("failed   {}: {:#}", path.display(), err);
+            }
+        }
+    })?;
+
+    println!(
+        "{} imported, {} skipped, {} failed",
+        imported, skipped, failed
+    );
+    if failed > 0 {
+        bail!("{} files failed to import", failed);
+    }
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use anyhow::bail;
+use dotenv::dotenv;
+use phr_backend::Database;
+use std::env;
+
+const USAGE: &str = "usage: migrate [--check]";
+
+fn main() -> anyhow::Result<()> {
+    let check = match env::args().nth(1).as_deref() {
+        None => false,
+        Some("--check") => true,
+        Some(_) => bail!(USAGE),
+    };
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Database::connect_unmigrated(&database_url)?;
+
+    if check {
+        let pending = database.pending_migrations()?;
+        for name in &pending {
+            println!("pending {}", name);
+        }
+        if !pending.is_empty() {
+            bail!("{} migrations pending", pending.len());
+        }
+        println!("database is up to date");
+    } else {
+        let applied = database.migrate()?;
+        for name in &applied {
+            println!("applied {}", name);
+        }
+        if applied.is_empty() {
+            println!("database is up to date");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use dotenv::dotenv;
+use phr_backend::Database;
+use std::env;
+
+fn main() -> anyhow::Result<()> {
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Database::connect(&database_url)?;
+    database.rebuild_ratings()?;
+    println!("ratings rebuilt");
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use anyhow::bail;
+use dotenv::dotenv;
+use phr_backend::{Database, ParseOptions};
+use std::env;
+
+const USAGE: &str = "usage: reparse [--gaps=interval|leader] [--lenient] [--apply]";
+
+fn main() -> anyhow::Result<()> {
+    let mut options = ParseOptions::default();
+    let mut apply = false;
+    for flag in env::args().skip(1) {
+        if let Some(gaps) = flag.strip_prefix("--gaps=") {
+            options.gaps = gaps.parse()?;
+        } else if flag == "--lenient" {
+            options.lenient = true;
+        } else if flag == "--apply" {
+            apply = true;
+        } else {
+            bail!(USAGE);
+        }
+    }
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let mut database = Database::connect(&database_url)?;
+    database.set_parse_options(options);
+
+    let mut changed = 0;
+    let mut unchanged = 0;
+    let mut failed = 0;
+    database.reparse(apply, |id, result| match result {
+        Ok(reparsed) if reparsed.diff.is_unchanged() => unchanged += 1,
+        Ok(reparsed) => {
+            changed += 1;
+            println!("{}", reparsed);
+        }
+        Err(err) => {
+            failed += 1;
+            println!("race {}: failed: {:#}", id, err);
+        }
+    })?;
+
+    println!(
+        "{} {}, {} unchanged, {} failed",
+        changed,
+        if apply { "changed" } else { "would change" },
+        unchanged,
+        failed
+    );
+    if failed > 0 {
+        bail!("{} races failed to parse", failed);
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use anyhow::Context;
+use dotenv::dotenv;
+use phr_backend::Database;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+
+const USAGE: &str = "usage: restore <archive.tar.gz>";
+
+fn main() -> anyhow::Result<()> {
+    let path = env::args().nth(1).context(USAGE)?;
+    let file = File::open(&path).with_context(|| format!("cannot open {}", path))?;
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Database::connect(&database_url)?;
+
+    for (table, rows) in database.restore(BufReader::new(file))? {
+        println!("{:>8} {}", rows, table);
+    }
+    Ok(())
+}
@@ -1,16 +1,84 @@
+use anyhow::{bail, Context};
 use dotenv::dotenv;
-use phr_backend::Api;
-use std::env;
+use phr_backend::{Api, Database, FetchOptions, Fetcher, Watcher};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, thread};
 use warp::Filter;
 
+const USAGE: &str = "usage: serve [--watch=<dir>] [--fetch=<base URL>] [--every=<seconds>]";
+
 fn main() -> anyhow::Result<()> {
+    let mut watch = None;
+    let mut fetch = None;
+    let mut every = Duration::from_secs(60);
+    for flag in env::args().skip(1) {
+        if let Some(dir) = flag.strip_prefix("--watch=") {
+            watch = Some(dir.to_string());
+        } else if let Some(base_url) = flag.strip_prefix("--fetch=") {
+            fetch = Some(base_url.to_string());
+        } else if let Some(seconds) = flag.strip_prefix("--every=") {
+            every = Duration::from_secs(seconds.parse().context(USAGE)?);
+        } else {
+            bail!(USAGE);
+        }
+    }
+
     dotenv().ok();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Arc::new(Database::connect(&database_url)?);
+    let api = Api::new(&database);
+
+    // Races are imported in this process rather than by watch_races and
+    // fetch_races, so that subscribers hear about them.
+    if let Some(dir) = watch {
+        let mut watcher = Watcher::new(Path::new(&dir))?;
+        let database = database.clone();
+        thread::spawn(move || loop {
+            let imported =
+                database.import_watched(&mut watcher, |file, outcome, result| match result {
+                    Ok(added) => println!("{:<9} {}\n  {}", outcome, file.display(), added),
+                    Err(err) => println!("{:<9} {}: {:#}", outcome, file.display(), err),
+                });
+            if let Err(err) = imported {
+                println!("watching failed: {:#}", err);
+            }
+            thread::sleep(every);
+        });
+    }
+    if let Some(base_url) = fetch {
+        let database = database.clone();
+        thread::spawn(move || {
+            // The fetcher has its own runtime, which can't be moved between
+            // threads.
+            let mut fetcher = match Fetcher::new(&base_url, FetchOptions::default()) {
+                Ok(fetcher) => fetcher,
+                Err(err) => {
+                    println!("fetching failed: {:#}", err);
+                    return;
+                }
+            };
+            loop {
+                match database.fetch_races(&mut fetcher) {
+                    Ok(added) => {
+                        for race in &added {
+                            println!("{}", race);
+                        }
+                    }
+                    // The failed race is tried again next time.
+                    Err(err) => println!("fetching failed: {:#}", err),
+                }
+                thread::sleep(every);
+            }
+        });
+    }
 
-    let api = Api::new(&database_url)?;
+    let export = api.export_filter();
     warp::serve(
         (warp::path("graphql").and(api.to_filter()))
-            .or(warp::path("graphiql").and(juniper_warp::graphiql_filter("/graphql"))),
+            .or(warp::path("graphiql").and(juniper_warp::graphiql_filter("/graphql")))
+            .or(warp::path("export").and(export)),
     )
     .run(([127, 0, 0, 1], 8080));
 
@@ -0,0 +1,51 @@
+use anyhow::{bail, Context};
+use dotenv::dotenv;
+use phr_backend::{Database, Role};
+use std::env;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const USAGE: &str = "usage: tokens list
+       tokens create <name> <viewer|organiser|admin>
+       tokens revoke <id>";
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Database::connect(&database_url)?;
+
+    match args.as_slice() {
+        ["list"] => {
+            for token in database.tokens()? {
+                print!(
+                    "{}\t{}\t{}\tcreated {}",
+                    token.id,
+                    token.name,
+                    token.role,
+                    token.created_at.format(TIME_FORMAT)
+                );
+                match token.revoked_at {
+                    Some(revoked_at) => println!("\trevoked {}", revoked_at.format(TIME_FORMAT)),
+                    None => println!(),
+                }
+            }
+        }
+        ["create", name, role] => {
+            let role: Role = role.parse()?;
+            let (id, token) = database.create_token(name, role)?;
+            println!("created token {} for {} ({})", id, name, role);
+            println!("{}", token);
+        }
+        ["revoke", id] => {
+            let id: i32 = id.parse().context(USAGE)?;
+            database.revoke_token(id)?;
+            println!("revoked token {}", id);
+        }
+        _ => bail!(USAGE),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use anyhow::{bail, Context};
+use dotenv::dotenv;
+use phr_backend::{Database, ParseOptions, Watcher, DEFAULT_EXTENSIONS};
+use std::path::Path;
+use std::time::Duration;
+use std::{env, thread};
+
+const USAGE: &str =
+    "usage: watch_races [--gaps=interval|leader] [--lenient] [--extensions=html,csv,json] [--every=<seconds>] <dir>";
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1).peekable();
+    let mut options = ParseOptions::default();
+    let mut extensions: Vec<String> = DEFAULT_EXTENSIONS
+        .iter()
+        .map(|ext| ext.to_string())
+        .collect();
+    let mut every = Duration::from_secs(5);
+    while let Some(flag) = args.peek().filter(|arg| arg.starts_with("--")).cloned() {
+        args.next();
+        if let Some(gaps) = flag.strip_prefix("--gaps=") {
+            options.gaps = gaps.parse()?;
+        } else if flag == "--lenient" {
+            options.lenient = true;
+        } else if let Some(list) = flag.strip_prefix("--extensions=") {
+            extensions = list.split(',').map(str::to_string).collect();
+        } else if let Some(seconds) = flag.strip_prefix("--every=") {
+            every = Duration::from_secs(seconds.parse().context(USAGE)?);
+        } else {
+            bail!(USAGE);
+        }
+    }
+    let dir = args.next().context(USAGE)?;
+
+    dotenv().ok();
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let mut database = Database::connect(&database_url)?;
+    database.set_parse_options(options);
+
+    let mut watcher = Watcher::new(Path::new(&dir))?;
+    watcher.set_extensions(extensions);
+    loop {
+        database.import_watched(&mut watcher, |file, outcome, result| match result {
+            Ok(added) => println!("{:<9} {}\n  {}", outcome, file.display(), added),
+            Err(err) => println!("{:<9} {}: {:#}", outcome, file.display(), err),
+        })?;
+        thread::sleep(every);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::db::DbConnection;
+use crate::model::{
+    parse_points_table, Championship, ChampionshipRound, NewChampionship, NewSeason, Reason,
+};
+use anyhow::{bail, Context};
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+/// How points are awarded in a championship.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PointsSystem {
+    /// Points for each finishing position, starting with the winner.
+    pub(crate) points: Vec<i32>,
+    /// Points for the fastest lap of a round.
+    pub(crate) best_lap_points: i32,
+    /// How many of each driver's worst rounds don't count.
+    pub(crate) drop_rounds: usize,
+}
+
+/// The result of a driver in a single round.
+#[derive(Debug, Clone, Queryable)]
+pub(crate) struct RoundResult {
+    pub(crate) driver_id: i32,
+    pub(crate) position: Option<i32>,
+    pub(crate) reason: Option<Reason>,
+    pub(crate) best_lap: Option<i32>,
+}
+
+/// A driver's place in the standings of a championship.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Standing {
+    /// Drivers that are level on points and on countback share a position.
+    pub(crate) position: i32,
+    pub(crate) driver_id: i32,
+    pub(crate) points: i32,
+    /// The points scored in each round, or `None` for rounds the driver did
+    /// not enter.
+    pub(crate) round_points: Vec<Option<i32>>,
+    /// The points that don't count because they were scored in dropped
+    /// rounds.
+    pub(crate) dropped_points: i32,
+    /// How many times the driver finished in each position, starting with
+    /// wins.
+    pub(crate) finishes: Vec<i32>,
+}
+
+impl PointsSystem {
+    pub(crate) fn for_championship(championship: &Championship) -> anyhow::Result<PointsSystem> {
+        Ok(PointsSystem {
+            points: parse_points_table(&championship.points)?,
+            best_lap_points: championship.best_lap_points,
+            drop_rounds: championship.drop_rounds.max(0) as usize,
+        })
+    }
+
+    /// Only classified finishers score position points. The fastest lap
+    /// bonus goes to the fastest entrant that started and wasn't
+    /// disqualified, with the better placed one winning a tie.
+    fn round_points(&self, round: &[RoundResult]) -> HashMap<i32, i32> {
+        let mut points = HashMap::new();
+        for result in round {
+            let scored = match (result.reason, result.position) {
+                (None, Some(position)) if position >= 1 => {
+                    self.points.get(position as usize - 1).copied().unwrap_or(0)
+                }
+                _ => 0,
+            };
+            points.insert(result.driver_id, scored);
+        }
+
+        let fastest = round
+            .iter()
+            .filter(|result| {
+                result.reason != Some(Reason::Dns) && result.reason != Some(Reason::Dsq)
+            })
+            .filter_map(|result| {
+                let position = result.position.unwrap_or(i32::MAX);
+                Some((result.best_lap?, position, result.driver_id))
+            })
+            .min();
+        if let Some((_, _, driver_id)) = fastest {
+            *points.entry(driver_id).or_insert(0) += self.best_lap_points;
+        }
+        points
+    }
+
+    /// Computes the standings after `rounds`, which are in the order they
+    /// were run. Rounds a driver did not enter count as zero points when
+    /// dropping their worst rounds.
+    pub(crate) fn standings(&self, rounds: &[Vec<RoundResult>]) -> Vec<Standing> {
+        let mut by_driver: HashMap<i32, Standing> = HashMap::new();
+        for (i, round) in rounds.iter().enumerate() {
+            for (driver_id, points) in self.round_points(round) {
+                let standing = by_driver.entry(driver_id).or_insert_with(|| Standing {
+                    position: 0,
+                    driver_id,
+                    points: 0,
+                    round_points: vec![None; rounds.len()],
+                    dropped_points: 0,
+                    finishes: Vec::new(),
+                });
+                standing.round_points[i] = Some(points);
+            }
+            for result in round.iter().filter(|result| result.reason.is_none()) {
+                let position = match result.position {
+                    Some(position) if position >= 1 => position as usize,
+                    _ => continue,
+                };
+                let finishes = &mut by_driver.get_mut(&result.driver_id).unwrap().finishes;
+                if finishes.len() < position {
+                    finishes.resize(position, 0);
+                }
+                finishes[position - 1] += 1;
+            }
+        }
+
+        let mut standings: Vec<Standing> = by_driver.into_values().collect();
+        let width = standings
+            .iter()
+            .map(|s| s.finishes.len())
+            .max()
+            .unwrap_or(0);
+        for standing in &mut standings {
+            standing.finishes.resize(width, 0);
+            let mut scores: Vec<i32> = standing
+                .round_points
+                .iter()
+                .map(|points| points.unwrap_or(0))
+                .collect();
+            scores.sort();
+            let dropped = self.drop_rounds.min(scores.len());
+            standing.dropped_points = scores[..dropped].iter().sum();
+            standing.points = scores[dropped..].iter().sum();
+        }
+
+        // Ties on points are broken on countback: most wins, then most second
+        // places and so on.
+        standings.sort_by(|a, b| {
+            b.points
+                .cmp(&a.points)
+                .then_with(|| b.finishes.cmp(&a.finishes))
+                .then(a.driver_id.cmp(&b.driver_id))
+        });
+        for i in 0..standings.len() {
+            standings[i].position = match i.checked_sub(1).map(|j| &standings[j]) {
+                Some(prev)
+                    if prev.points == standings[i].points
+                        && prev.finishes == standings[i].finishes =>
+                {
+                    prev.position
+                }
+                _ => i as i32 + 1,
+            };
+        }
+        standings
+    }
+}
+
+/// Loads the standings of several championships at once.
+pub(crate) fn standings(
+    db: &DbConnection,
+    championship_ids: &[i32],
+) -> anyhow::Result<HashMap<i32, Vec<Standing>>> {
+    use crate::schema::{championship_rounds, championships, race_entrants, races};
+
+    let (all, rounds, results) = with_conn!(db, |conn| {
+        let all: Vec<Championship> = championships::table
+            .filter(championships::id.eq_any(championship_ids))
+            .load(conn)?;
+        let rounds: Vec<(i32, i32)> = championship_rounds::table
+            .inner_join(races::table)
+            .filter(championship_rounds::championship_id.eq_any(championship_ids))
+            .order((races::date, races::id))
+            .select((championship_rounds::championship_id, races::id))
+            .load(conn)?;
+        let race_ids: Vec<i32> = rounds.iter().map(|&(_, race_id)| race_id).collect();
+        let results: Vec<(i32, RoundResult)> = race_entrants::table
+            .filter(race_entrants::race_id.eq_any(race_ids))
+            .select((
+                race_entrants::race_id,
+                (
+                    race_entrants::driver_id,
+                    race_entrants::position,
+                    race_entrants::reason,
+                    race_entrants::best_lap,
+                ),
+            ))
+            .load(conn)?;
+        (all, rounds, results)
+    });
+
+    let mut by_race: HashMap<i32, Vec<RoundResult>> = HashMap::new();
+    for (race_id, result) in results {
+        by_race.entry(race_id).or_default().push(result);
+    }
+    let mut standings = HashMap::new();
+    for championship in all {
+        let system = PointsSystem::for_championship(&championship)?;
+        let championship_rounds: Vec<Vec<RoundResult>> = rounds
+            .iter()
+            .filter(|&&(id, _)| id == championship.id)
+            .map(|(_, race_id)| by_race.get(race_id).cloned().unwrap_or_default())
+            .collect();
+        standings.insert(championship.id, system.standings(&championship_rounds));
+    }
+    Ok(standings)
+}
+
+pub(crate) fn create_season(db: &DbConnection, season_name: &str) -> anyhow::Result<i32> {
+    use crate::schema::seasons::dsl::*;
+    with_conn!(db, |conn| conn.transaction(|| {
+        let existing: Option<i32> = seasons
+            .select(id)
+            .filter(name.eq(season_name))
+            .first(conn)
+            .optional()?;
+        if existing.is_some() {
+            bail!("season {:?} already exists", season_name);
+        }
+        NewSeason {
+            name: season_name.to_string(),
+        }
+        .insert_into(seasons)
+        .execute(conn)?;
+        Ok(seasons
+            .select(id)
+            .filter(name.eq(season_name))
+            .first(conn)?)
+    }))
+}
+
+pub(crate) fn create_championship(
+    db: &DbConnection,
+    new_championship: NewChampionship,
+) -> anyhow::Result<i32> {
+    use crate::schema::championships::dsl::*;
+    use crate::schema::seasons::dsl::seasons;
+
+    parse_points_table(&new_championship.points)?;
+    if new_championship.best_lap_points < 0 || new_championship.drop_rounds < 0 {
+        bail!("best lap points and dropped rounds can't be negative");
+    }
+    with_conn!(db, |conn| conn.transaction(|| {
+        let season_exists: bool = diesel::select(diesel::dsl::exists(
+            seasons.find(new_championship.season_id),
+        ))
+        .get_result(conn)?;
+        if !season_exists {
+            bail!("no season with id {}", new_championship.season_id);
+        }
+        let existing = championships
+            .select(id)
+            .filter(season_id.eq(new_championship.season_id))
+            .filter(name.eq(&new_championship.name));
+        if existing.first::<i32>(conn).optional()?.is_some() {
+            bail!(
+                "championship {:?} already exists in season {}",
+                new_championship.name,
+                new_championship.season_id
+            );
+        }
+        new_championship
+            .clone()
+            .insert_into(championships)
+            .execute(conn)?;
+        Ok(existing.first(conn)?)
+    }))
+}
+
+pub(crate) fn add_round(
+    db: &DbConnection,
+    championship_id: i32,
+    race_id: i32,
+) -> anyhow::Result<()> {
+    use crate::schema::championship_rounds::dsl::championship_rounds;
+    use crate::schema::championships::dsl::championships;
+    use crate::schema::races::dsl::races;
+
+    with_conn!(db, |conn| conn.transaction(|| {
+        championships
+            .find(championship_id)
+            .first::<Championship>(conn)
+            .optional()?
+            .with_context(|| format!("no championship with id {}", championship_id))?;
+        let race_exists: bool =
+            diesel::select(diesel::dsl::exists(races.find(race_id))).get_result(conn)?;
+        if !race_exists {
+            bail!("no race with id {}", race_id);
+        }
+        let round = ChampionshipRound {
+            championship_id,
+            race_id,
+        };
+        diesel::insert_or_ignore_into(championship_rounds)
+            .values(round)
+            .execute(conn)?;
+        Ok(())
+    }))
+}
+
+pub(crate) fn remove_round(
+    db: &DbConnection,
+    championship_id: i32,
+    race_id: i32,
+) -> anyhow::Result<()> {
+    use crate::schema::championship_rounds::dsl::championship_rounds;
+    let removed = with_conn!(db, |conn| diesel::delete(
+        championship_rounds.find((championship_id, race_id))
+    )
+    .execute(conn))?;
+    if removed == 0 {
+        bail!(
+            "race {} is not a round of championship {}",
+            race_id,
+            championship_id
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finish(driver_id: i32, position: i32, best_lap: i32) -> RoundResult {
+        RoundResult {
+            driver_id,
+            position: Some(position),
+            reason: None,
+            best_lap: Some(best_lap),
+        }
+    }
+
+    fn points_of(standings: &[Standing]) -> Vec<(i32, i32, i32)> {
+        standings
+            .iter()
+            .map(|s| (s.position, s.driver_id, s.points))
+            .collect()
+    }
+
+    #[test]
+    fn points_and_best_lap() {
+        let system = PointsSystem {
+            points: vec![25, 18, 15],
+            best_lap_points: 1,
+            drop_rounds: 0,
+        };
+        let mut retired = finish(4, 4, 900);
+        retired.reason = Some(Reason::Dsq);
+        let standings = system.standings(&[vec![
+            finish(1, 1, 1000),
+            finish(2, 2, 990),
+            finish(3, 3, 1010),
+            retired,
+        ]]);
+        assert_eq!(
+            points_of(&standings),
+            vec![(1, 1, 25), (2, 2, 19), (3, 3, 15), (4, 4, 0)]
+        );
+    }
+
+    #[test]
+    fn dropped_rounds_and_countback() {
+        let system = PointsSystem {
+            points: vec![10, 6, 4],
+            best_lap_points: 0,
+            drop_rounds: 1,
+        };
+        let standings = system.standings(&[
+            vec![finish(1, 1, 0), finish(2, 2, 0), finish(3, 3, 0)],
+            vec![finish(2, 1, 0), finish(1, 3, 0)],
+            vec![finish(3, 1, 0), finish(1, 2, 0)],
+        ]);
+        // 1 scores 10 + 4 + 6 and drops the 4, while 2 and 3 drop the round
+        // they missed. 1 and 2 both have a win and a second place, but 1 also
+        // has a third.
+        assert_eq!(
+            points_of(&standings),
+            vec![(1, 1, 16), (2, 2, 16), (3, 3, 14)]
+        );
+        assert_eq!(standings[0].dropped_points, 4);
+        assert_eq!(standings[2].round_points, vec![Some(4), None, Some(10)]);
+
+        let tied = system.standings(&[vec![finish(1, 1, 0)], vec![finish(2, 1, 0)]]);
+        assert_eq!(points_of(&tied), vec![(1, 1, 10), (1, 2, 10)]);
+    }
+}
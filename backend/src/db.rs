@@ -0,0 +1,131 @@
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+
+#[cfg(feature = "mysql")]
+use diesel::mysql::MysqlConnection;
+#[cfg(feature = "sqlite")]
+use diesel::sqlite::SqliteConnection;
+
+#[cfg(not(any(feature = "mysql", feature = "sqlite")))]
+compile_error!("at least one database backend feature (`mysql`, `sqlite`) must be enabled");
+
+/// Runs `$body` with `$conn` bound to the backend-specific connection wrapped
+/// by a `DbConnection`, so that the same query code can be used for every
+/// backend.
+macro_rules! with_conn {
+    ($db:expr, |$conn:ident| $body:expr) => {
+        match $db {
+            #[cfg(feature = "mysql")]
+            $crate::db::DbConnection::Mysql($conn) => $body,
+            #[cfg(feature = "sqlite")]
+            $crate::db::DbConnection::Sqlite($conn) => $body,
+        }
+    };
+}
+
+/// A connection pool for whichever backend `DATABASE_URL` refers to.
+///
+/// URLs starting with `mysql://` select MySQL, anything else is treated as
+/// the path to a SQLite database (optionally prefixed with `sqlite://`).
+#[derive(Clone)]
+pub(crate) enum DbPool {
+    #[cfg(feature = "mysql")]
+    Mysql(Pool<ConnectionManager<MysqlConnection>>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Pool<ConnectionManager<SqliteConnection>>),
+}
+
+pub(crate) enum DbConnection {
+    #[cfg(feature = "mysql")]
+    Mysql(PooledConnection<ConnectionManager<MysqlConnection>>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(PooledConnection<ConnectionManager<SqliteConnection>>),
+}
+
+impl DbPool {
+    pub(crate) fn new(db_url: &str) -> anyhow::Result<DbPool> {
+        if db_url.starts_with("mysql://") {
+            mysql_pool(db_url)
+        } else {
+            sqlite_pool(db_url.strip_prefix("sqlite://").unwrap_or(db_url))
+        }
+    }
+
+    pub(crate) fn get(&self) -> anyhow::Result<DbConnection> {
+        Ok(match self {
+            #[cfg(feature = "mysql")]
+            DbPool::Mysql(pool) => DbConnection::Mysql(pool.get()?),
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => DbConnection::Sqlite(pool.get()?),
+        })
+    }
+}
+
+#[cfg(feature = "mysql")]
+fn mysql_pool(db_url: &str) -> anyhow::Result<DbPool> {
+    Ok(DbPool::Mysql(Pool::new(ConnectionManager::new(db_url))?))
+}
+
+#[cfg(not(feature = "mysql"))]
+fn mysql_pool(_db_url: &str) -> anyhow::Result<DbPool> {
+    anyhow::bail!("MySQL support is not enabled in this build (feature `mysql`)")
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_pool(path: &str) -> anyhow::Result<DbPool> {
+    Ok(DbPool::Sqlite(
+        Pool::builder()
+            .connection_customizer(Box::new(SqlitePragmas))
+            .build(ConnectionManager::new(path))?,
+    ))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_pool(_path: &str) -> anyhow::Result<DbPool> {
+    anyhow::bail!("SQLite support is not enabled in this build (feature `sqlite`)")
+}
+
+/// SQLite leaves foreign key enforcement off unless it is enabled for every
+/// connection, and `ON DELETE CASCADE` depends on it.
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+struct SqlitePragmas;
+
+#[cfg(feature = "sqlite")]
+impl diesel::r2d2::CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for SqlitePragmas {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+        conn.batch_execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// A SQLite database in a new temporary file, which is deleted when this is
+/// dropped.
+#[cfg(all(test, feature = "sqlite"))]
+pub(crate) struct TestDb {
+    path: std::path::PathBuf,
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+impl TestDb {
+    /// `name` keeps the databases of tests that run at the same time apart.
+    pub(crate) fn new(name: &str) -> TestDb {
+        let file = format!("phr-{}-{}.db", name, std::process::id());
+        let path = std::env::temp_dir().join(file);
+        let _ = std::fs::remove_file(&path);
+        TestDb { path }
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        self.path
+            .to_str()
+            .expect("temporary directory is not UTF-8")
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,380 @@
+use crate::db::DbConnection;
+use crate::model::{
+    Driver, DriverAlias, Race, RaceEntrant, RaceMode, Reason, Track, Vehicle, VehicleName,
+    VEHICLE_COLUMNS,
+};
+use crate::parser;
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use std::fmt;
+
+/// A race as it is currently stored, with its track name and entrants.
+#[derive(Debug, Clone)]
+pub(crate) struct RaceSnapshot {
+    race: Race,
+    track: String,
+    entrants: Vec<EntrantSnapshot>,
+}
+
+/// An entrant together with the names it refers to by id.
+#[derive(Debug, Clone)]
+struct EntrantSnapshot {
+    driver: String,
+    vehicle: Option<String>,
+    entrant: RaceEntrant,
+}
+
+impl RaceSnapshot {
+    pub(crate) fn load(db: &DbConnection, id: i32) -> anyhow::Result<Option<RaceSnapshot>> {
+        use crate::schema::races::dsl::races;
+        use crate::schema::tracks::dsl::tracks;
+        use crate::schema::{drivers, race_entrants, vehicles};
+
+        let (race, track): (Race, Track) = match with_conn!(db, |conn| races
+            .find(id)
+            .inner_join(tracks)
+            .first(conn)
+            .optional())?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let entrants = with_conn!(db, |conn| race_entrants::table
+            .inner_join(drivers::table)
+            .left_join(vehicles::table)
+            .filter(race_entrants::race_id.eq(id))
+            .order(race_entrants::position.asc())
+            .select((
+                race_entrants::all_columns,
+                drivers::all_columns,
+                VEHICLE_COLUMNS.nullable(),
+            ))
+            .load::<(RaceEntrant, Driver, Option<Vehicle>)>(conn))?
+        .into_iter()
+        .map(|(entrant, driver, vehicle)| EntrantSnapshot {
+            driver: driver.name,
+            vehicle: vehicle.map(|vehicle| vehicle.name),
+            entrant,
+        })
+        .collect();
+        Ok(Some(RaceSnapshot {
+            race,
+            track: track.name,
+            entrants,
+        }))
+    }
+
+    /// How `race` would be stored as race `id`, without storing anything.
+    /// Drivers, vehicles and the track are looked up as `Race::store` does,
+    /// and the ones it would add are given id 0.
+    pub(crate) fn parsed(
+        db: &DbConnection,
+        race: &parser::Race,
+        id: i32,
+        date: NaiveDate,
+    ) -> anyhow::Result<RaceSnapshot> {
+        use crate::schema::{drivers, tracks, vehicles};
+
+        let track: Option<Track> = with_conn!(db, |conn| tracks::table
+            .filter(tracks::name.eq(&race.track))
+            .first(conn)
+            .optional())?;
+        let (track_id, track) =
+            track.map_or((0, race.track.clone()), |track| (track.id, track.name));
+
+        let mut entrants = Vec::new();
+        for entrant in &race.entrants {
+            let driver: Option<Driver> = match DriverAlias::resolve(db, &entrant.name)? {
+                Some(driver_id) => with_conn!(db, |conn| drivers::table
+                    .find(driver_id)
+                    .first(conn)
+                    .optional())?,
+                None => with_conn!(db, |conn| drivers::table
+                    .filter(drivers::name.eq(&entrant.name))
+                    .first(conn)
+                    .optional())?,
+            };
+            let (driver_id, driver) =
+                driver.map_or((0, entrant.name.clone()), |driver| (driver.id, driver.name));
+
+            let name = VehicleName::new(&entrant.vehicle);
+            let vehicle: Option<Vehicle> = with_conn!(db, |conn| vehicles::table
+                .select(VEHICLE_COLUMNS)
+                .filter(vehicles::normalized_name.eq(&name.normalized_name))
+                .first(conn)
+                .optional())?;
+            let (vehicle_id, vehicle) =
+                vehicle.map_or((0, name.name), |vehicle| (vehicle.id, vehicle.name));
+
+            entrants.push(EntrantSnapshot {
+                driver,
+                vehicle: Some(vehicle),
+                entrant: entrant.clone().into_row(id, driver_id, vehicle_id)?,
+            });
+        }
+
+        Ok(RaceSnapshot {
+            race: Race {
+                id,
+                date,
+                track_id,
+                laps: race.laps,
+                minutes: race.minutes,
+                mode: Some(race.mode.into()),
+            },
+            track,
+            entrants,
+        })
+    }
+
+    pub(crate) fn race(&self) -> &Race {
+        &self.race
+    }
+}
+
+/// The differences between the stored version of a race and a replacement.
+#[derive(Debug, Clone)]
+pub struct RaceDiff {
+    pub id: i32,
+    pub created: bool,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Change {
+    Race(FieldChange),
+    EntrantAdded(String),
+    EntrantRemoved(String),
+    Entrant(String, FieldChange),
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl RaceDiff {
+    pub(crate) fn new(old: Option<&RaceSnapshot>, new: &RaceSnapshot) -> RaceDiff {
+        let old = match old {
+            Some(old) => old,
+            None => {
+                return RaceDiff {
+                    id: new.race.id,
+                    created: true,
+                    changes: Vec::new(),
+                }
+            }
+        };
+
+        let mut changes = Vec::new();
+        compare_races(old, new, |change| changes.push(Change::Race(change)));
+        for old_entrant in &old.entrants {
+            let name = &old_entrant.driver;
+            match new.entrants.iter().find(|new| &new.driver == name) {
+                Some(new_entrant) => compare_entrants(old_entrant, new_entrant, |change| {
+                    changes.push(Change::Entrant(name.clone(), change))
+                }),
+                None => changes.push(Change::EntrantRemoved(name.clone())),
+            }
+        }
+        for new_entrant in &new.entrants {
+            if !old
+                .entrants
+                .iter()
+                .any(|old| old.driver == new_entrant.driver)
+            {
+                changes.push(Change::EntrantAdded(new_entrant.driver.clone()));
+            }
+        }
+
+        RaceDiff {
+            id: new.race.id,
+            created: false,
+            changes,
+        }
+    }
+
+    pub fn is_unchanged(&self) -> bool {
+        !self.created && self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for RaceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.created {
+            return write!(f, "race {}: created", self.id);
+        }
+        if self.changes.is_empty() {
+            return write!(f, "race {}: unchanged", self.id);
+        }
+        write!(f, "race {}:", self.id)?;
+        for change in &self.changes {
+            match change {
+                Change::Race(change) => write!(f, "\n  {}", change)?,
+                Change::EntrantAdded(name) => write!(f, "\n  + {}", name)?,
+                Change::EntrantRemoved(name) => write!(f, "\n  - {}", name)?,
+                Change::Entrant(name, change) => write!(f, "\n  {}: {}", name, change)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+fn compare_races(old: &RaceSnapshot, new: &RaceSnapshot, mut push: impl FnMut(FieldChange)) {
+    let (old_race, new_race) = (&old.race, &new.race);
+    compare("date", &old_race.date, &new_race.date, &mut push);
+    compare("track", &old.track, &new.track, &mut push);
+    compare("laps", &old_race.laps, &new_race.laps, &mut push);
+    compare("minutes", &old_race.minutes, &new_race.minutes, &mut push);
+    compare("mode", &old_race.mode, &new_race.mode, &mut push);
+}
+
+fn compare_entrants(
+    old: &EntrantSnapshot,
+    new: &EntrantSnapshot,
+    mut push: impl FnMut(FieldChange),
+) {
+    let (old_entrant, new_entrant) = (&old.entrant, &new.entrant);
+    compare(
+        "position",
+        &old_entrant.position,
+        &new_entrant.position,
+        &mut push,
+    );
+    compare("vehicle", &old.vehicle, &new.vehicle, &mut push);
+    let (old, new) = (old_entrant, new_entrant);
+    compare("time", &old.time, &new.time, &mut push);
+    compare("gap", &old.gap, &new.gap, &mut push);
+    compare("best_lap", &old.best_lap, &new.best_lap, &mut push);
+    compare("lap", &old.lap, &new.lap, &mut push);
+    compare("reason", &old.reason, &new.reason, &mut push);
+    compare("ping", &old.ping, &new.ping, &mut push);
+    compare("fps", &old.fps, &new.fps, &mut push);
+    compare("fps_locked", &old.fps_locked, &new.fps_locked, &mut push);
+    compare("row_class", &old.row_class, &new.row_class, &mut push);
+    compare("name_class", &old.name_class, &new.name_class, &mut push);
+    compare("extras", &old.extras, &new.extras, &mut push);
+}
+
+fn compare<T: PartialEq + Value>(
+    field: &'static str,
+    old: &T,
+    new: &T,
+    push: &mut impl FnMut(FieldChange),
+) {
+    if old != new {
+        push(FieldChange {
+            field,
+            old: old.show(),
+            new: new.show(),
+        });
+    }
+}
+
+trait Value {
+    fn show(&self) -> String;
+}
+
+impl<T: Value> Value for Option<T> {
+    fn show(&self) -> String {
+        match self {
+            Some(value) => value.show(),
+            None => "-".to_string(),
+        }
+    }
+}
+
+macro_rules! display_value {
+    ($($ty:ty),*) => {
+        $(
+            impl Value for $ty {
+                fn show(&self) -> String {
+                    self.to_string()
+                }
+            }
+        )*
+    };
+}
+
+display_value!(i32, bool, String, NaiveDate);
+
+impl Value for Reason {
+    fn show(&self) -> String {
+        format!("{:?}", self).to_uppercase()
+    }
+}
+
+impl Value for RaceMode {
+    fn show(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entrant(name: &str, position: i32, time: i32) -> EntrantSnapshot {
+        let entrant = RaceEntrant {
+            race_id: 1,
+            driver_id: 0,
+            position: Some(position),
+            vehicle_id: Some(1),
+            time: Some(time),
+            gap: None,
+            best_lap: None,
+            lap: Some(1),
+            reason: None,
+            ping: None,
+            fps: None,
+            fps_locked: false,
+            entered_as: None,
+            row_class: None,
+            name_class: None,
+            extras: None,
+        };
+        EntrantSnapshot {
+            driver: name.to_string(),
+            vehicle: Some("Nero".to_string()),
+            entrant,
+        }
+    }
+
+    #[test]
+    fn entrant_changes() {
+        let race = Race {
+            id: 1,
+            date: "2020-01-25".parse().unwrap(),
+            track_id: 1,
+            laps: Some(1),
+            minutes: None,
+            mode: Some(RaceMode::Laps),
+        };
+        let old = RaceSnapshot {
+            race: race.clone(),
+            track: "VIG Circuit Alta 5M".to_string(),
+            entrants: vec![entrant("a", 1, 1000), entrant("b", 2, 2000)],
+        };
+        let new = RaceSnapshot {
+            race,
+            track: "VIG Circuit Alta 5M".to_string(),
+            entrants: vec![entrant("a", 1, 1500), entrant("c", 2, 2000)],
+        };
+
+        let diff = RaceDiff::new(Some(&old), &new);
+        assert_eq!(
+            diff.to_string(),
+            "race 1:\n  a: time 1000 -> 1500\n  - b\n  + c"
+        );
+        assert!(RaceDiff::new(None, &new).created);
+        assert!(RaceDiff::new(Some(&new), &new).is_unchanged());
+    }
+}
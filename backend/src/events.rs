@@ -0,0 +1,78 @@
+use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use std::sync::Mutex;
+
+/// Something that happened to the stored results, for live subscribers.
+#[derive(Debug, Clone)]
+pub(crate) enum Event {
+    RaceAdded { race_id: i32, replaced: bool },
+    RatingChanged(RatingUpdate),
+    TrackRecord(TrackRecord),
+}
+
+/// A change in a driver's current rating, which is the rating after the
+/// latest race they were rated in.
+#[derive(Debug, Clone)]
+pub(crate) struct RatingUpdate {
+    pub(crate) driver_id: i32,
+    pub(crate) race_id: i32,
+    pub(crate) rating: f64,
+    pub(crate) previous_rating: Option<f64>,
+}
+
+/// A new fastest lap on a track.
+#[derive(Debug, Clone)]
+pub(crate) struct TrackRecord {
+    pub(crate) track_id: i32,
+    pub(crate) race_id: i32,
+    pub(crate) driver_id: i32,
+    pub(crate) best_lap: i32,
+    /// The record that was broken, or `None` if this is the first lap set
+    /// on the track.
+    pub(crate) previous_best_lap: Option<i32>,
+}
+
+/// Sends events to every subscriber in this process.
+#[derive(Default)]
+pub(crate) struct Broadcaster {
+    subscribers: Mutex<Vec<UnboundedSender<Event>>>,
+}
+
+impl Broadcaster {
+    pub(crate) fn subscribe(&self) -> UnboundedReceiver<Event> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Sends `event` to all subscribers, forgetting the ones that have gone
+    /// away.
+    pub(crate) fn publish(&self, event: Event) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{Async, Stream};
+
+    #[test]
+    fn drops_closed_subscribers() {
+        let broadcaster = Broadcaster::default();
+        let mut open = broadcaster.subscribe();
+        drop(broadcaster.subscribe());
+
+        broadcaster.publish(Event::RaceAdded {
+            race_id: 1,
+            replaced: false,
+        });
+        assert_eq!(broadcaster.subscribers.lock().unwrap().len(), 1);
+        match open.poll() {
+            Ok(Async::Ready(Some(Event::RaceAdded { race_id: 1, .. }))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,559 @@
+//! Exports of races, entrants and drivers as CSV, JSON or NDJSON.
+//!
+//! Races are read in batches by id and written out as they are read, so
+//! exports don't hold the whole history in memory.
+
+use crate::db::{DbConnection, DbPool};
+use crate::model::{Driver, Race, RaceEntrant, RaceMode, Reason, Vehicle, VEHICLE_COLUMNS};
+use anyhow::{bail, Context};
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use futures::sync::mpsc;
+use futures::{Sink, Stream};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+use std::thread;
+use warp::filters::BoxedFilter;
+use warp::reply::Reply;
+use warp::Filter;
+
+/// How many races or drivers are read from the database at a time.
+const BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma-separated values with a header row.
+    Csv,
+    /// A JSON array of objects.
+    Json,
+    /// A JSON object on each line.
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<ExportFormat> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            _ => bail!("unrecognized export format {:?}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportTable {
+    Races,
+    /// Every entry of every race, with the names of the driver and vehicle.
+    Entrants,
+    /// The drivers who entered any of the exported races, with their stats
+    /// over those races.
+    Drivers,
+}
+
+impl FromStr for ExportTable {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<ExportTable> {
+        match s {
+            "races" => Ok(ExportTable::Races),
+            "entrants" => Ok(ExportTable::Entrants),
+            "drivers" => Ok(ExportTable::Drivers),
+            _ => bail!("unrecognized export table {:?}", s),
+        }
+    }
+}
+
+/// Which races to export, or to export the entrants and drivers of.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Only include races on or after this date.
+    pub from: Option<NaiveDate>,
+    /// Only include races on or before this date.
+    pub to: Option<NaiveDate>,
+    /// Only include races on the track with this name.
+    pub track: Option<String>,
+}
+
+impl ExportFilter {
+    /// Reads the `from`, `to` and `track` query parameters.
+    fn from_query(query: &HashMap<String, String>) -> anyhow::Result<ExportFilter> {
+        let date = |key: &str| {
+            query
+                .get(key)
+                .map(|date| {
+                    date.parse::<NaiveDate>()
+                        .with_context(|| format!("invalid {} date", key))
+                })
+                .transpose()
+        };
+        Ok(ExportFilter {
+            from: date("from")?,
+            to: date("to")?,
+            track: query.get("track").cloned(),
+        })
+    }
+}
+
+const RACE_COLUMNS: &[&str] = &["id", "date", "track", "mode", "laps", "minutes"];
+
+const ENTRANT_COLUMNS: &[&str] = &[
+    "race_id",
+    "date",
+    "track",
+    "driver_id",
+    "driver",
+    "entered_as",
+    "position",
+    "vehicle",
+    "time",
+    "gap",
+    "best_lap",
+    "lap",
+    "reason",
+    "ping",
+    "fps",
+    "fps_locked",
+];
+
+const DRIVER_COLUMNS: &[&str] = &["id", "name", "races", "wins", "first_race", "last_race"];
+
+/// Writes the rows of `table` that match `filter` to `out`. Races and
+/// entrants are ordered by race id, and drivers by id.
+pub(crate) fn export<W: Write>(
+    db: &DbConnection,
+    table: ExportTable,
+    format: ExportFormat,
+    filter: &ExportFilter,
+    out: W,
+) -> anyhow::Result<()> {
+    match table {
+        ExportTable::Races => {
+            let mut writer = RecordWriter::new(out, format, RACE_COLUMNS)?;
+            for_each_race_batch(db, filter, |batch| {
+                for (race, track) in batch {
+                    writer.write(&[
+                        race.id.into(),
+                        race.date.to_string().into(),
+                        track.into(),
+                        race.mode.map(mode_name).into(),
+                        race.laps.into(),
+                        race.minutes.into(),
+                    ])?;
+                }
+                Ok(())
+            })?;
+            writer.finish()?;
+        }
+        ExportTable::Entrants => {
+            let mut writer = RecordWriter::new(out, format, ENTRANT_COLUMNS)?;
+            for_each_race_batch(db, filter, |batch| {
+                let ids: Vec<i32> = batch.iter().map(|(race, _)| race.id).collect();
+                let mut entrants = batch_entrants(db, &ids)?.into_iter().peekable();
+                for (race, track) in batch {
+                    while let Some((entrant, driver, vehicle)) =
+                        entrants.next_if(|(entrant, _, _)| entrant.race_id == race.id)
+                    {
+                        writer.write(&[
+                            race.id.into(),
+                            race.date.to_string().into(),
+                            track.clone().into(),
+                            driver.id.into(),
+                            driver.name.into(),
+                            entrant.entered_as.into(),
+                            entrant.position.into(),
+                            vehicle.map(|vehicle| vehicle.name).into(),
+                            entrant.time.into(),
+                            entrant.gap.into(),
+                            entrant.best_lap.into(),
+                            entrant.lap.into(),
+                            entrant.reason.map(reason_name).into(),
+                            entrant.ping.into(),
+                            entrant.fps.into(),
+                            entrant.fps_locked.into(),
+                        ])?;
+                    }
+                }
+                Ok(())
+            })?;
+            writer.finish()?;
+        }
+        ExportTable::Drivers => {
+            let mut writer = RecordWriter::new(out, format, DRIVER_COLUMNS)?;
+            export_drivers(db, filter, &mut writer)?;
+            writer.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` with the races that match `filter` and their track names, a
+/// batch at a time, in order of id.
+fn for_each_race_batch<F>(db: &DbConnection, filter: &ExportFilter, mut f: F) -> anyhow::Result<()>
+where
+    F: FnMut(Vec<(Race, String)>) -> anyhow::Result<()>,
+{
+    use crate::schema::{races, tracks};
+
+    let mut last_id = None;
+    loop {
+        let batch: Vec<(Race, String)> = with_conn!(db, |conn| {
+            let mut query = races::table
+                .inner_join(tracks::table)
+                .select((races::all_columns, tracks::name))
+                .into_boxed();
+            if let Some(from) = filter.from {
+                query = query.filter(races::date.ge(from));
+            }
+            if let Some(to) = filter.to {
+                query = query.filter(races::date.le(to));
+            }
+            if let Some(track) = &filter.track {
+                query = query.filter(tracks::name.eq(track.clone()));
+            }
+            if let Some(last_id) = last_id {
+                query = query.filter(races::id.gt(last_id));
+            }
+            query.order(races::id.asc()).limit(BATCH_SIZE).load(conn)
+        })?;
+        let done = (batch.len() as i64) < BATCH_SIZE;
+        last_id = batch.last().map(|(race, _)| race.id).or(last_id);
+        if !batch.is_empty() {
+            f(batch)?;
+        }
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// The entrants of the races with `ids`, ordered by race id and position.
+fn batch_entrants(
+    db: &DbConnection,
+    ids: &[i32],
+) -> anyhow::Result<Vec<(RaceEntrant, Driver, Option<Vehicle>)>> {
+    use crate::schema::{drivers, race_entrants, vehicles};
+    Ok(with_conn!(db, |conn| race_entrants::table
+        .inner_join(drivers::table)
+        .left_join(vehicles::table)
+        .filter(race_entrants::race_id.eq_any(ids))
+        .order((race_entrants::race_id.asc(), race_entrants::position.asc()))
+        .select((
+            race_entrants::all_columns,
+            drivers::all_columns,
+            VEHICLE_COLUMNS.nullable(),
+        ))
+        .load(conn))?)
+}
+
+fn export_drivers<W: Write>(
+    db: &DbConnection,
+    filter: &ExportFilter,
+    writer: &mut RecordWriter<W>,
+) -> anyhow::Result<()> {
+    use crate::schema::{drivers, race_entrants, races, tracks};
+
+    let mut last_id = None;
+    loop {
+        let batch: Vec<Driver> = with_conn!(db, |conn| {
+            let mut query = drivers::table.into_boxed();
+            if let Some(last_id) = last_id {
+                query = query.filter(drivers::id.gt(last_id));
+            }
+            query.order(drivers::id.asc()).limit(BATCH_SIZE).load(conn)
+        })?;
+        let ids: Vec<i32> = batch.iter().map(|driver| driver.id).collect();
+
+        let entries: Vec<(i32, Option<i32>, NaiveDate)> = with_conn!(db, |conn| {
+            let mut query = race_entrants::table
+                .inner_join(races::table.inner_join(tracks::table))
+                .filter(race_entrants::driver_id.eq_any(&ids))
+                .select((
+                    race_entrants::driver_id,
+                    race_entrants::position,
+                    races::date,
+                ))
+                .into_boxed();
+            if let Some(from) = filter.from {
+                query = query.filter(races::date.ge(from));
+            }
+            if let Some(to) = filter.to {
+                query = query.filter(races::date.le(to));
+            }
+            if let Some(track) = &filter.track {
+                query = query.filter(tracks::name.eq(track.clone()));
+            }
+            query.load(conn)
+        })?;
+        let mut stats: HashMap<i32, DriverTotals> = HashMap::new();
+        for (driver_id, position, date) in entries {
+            stats.entry(driver_id).or_default().add(position, date);
+        }
+
+        let done = (batch.len() as i64) < BATCH_SIZE;
+        last_id = batch.last().map(|driver| driver.id).or(last_id);
+        for driver in batch {
+            let totals = match stats.get(&driver.id) {
+                Some(totals) => totals,
+                None => continue,
+            };
+            writer.write(&[
+                driver.id.into(),
+                driver.name.into(),
+                totals.races.into(),
+                totals.wins.into(),
+                totals.first_race.map(|date| date.to_string()).into(),
+                totals.last_race.map(|date| date.to_string()).into(),
+            ])?;
+        }
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DriverTotals {
+    races: i32,
+    wins: i32,
+    first_race: Option<NaiveDate>,
+    last_race: Option<NaiveDate>,
+}
+
+impl DriverTotals {
+    fn add(&mut self, position: Option<i32>, date: NaiveDate) {
+        self.races += 1;
+        if position == Some(1) {
+            self.wins += 1;
+        }
+        self.first_race = Some(self.first_race.map_or(date, |first| first.min(date)));
+        self.last_race = Some(self.last_race.map_or(date, |last| last.max(date)));
+    }
+}
+
+fn mode_name(mode: RaceMode) -> &'static str {
+    match mode {
+        RaceMode::Laps => "laps",
+        RaceMode::Minutes => "minutes",
+        RaceMode::Rally => "rally",
+        RaceMode::PointToPoint => "point-to-point",
+    }
+}
+
+fn reason_name(reason: Reason) -> &'static str {
+    match reason {
+        Reason::Dns => "dns",
+        Reason::Dnf => "dnf",
+        Reason::Dsq => "dsq",
+    }
+}
+
+/// Writes records with the same columns in an export format, one at a time.
+struct RecordWriter<W> {
+    out: W,
+    format: ExportFormat,
+    columns: &'static [&'static str],
+    count: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    fn new(
+        mut out: W,
+        format: ExportFormat,
+        columns: &'static [&'static str],
+    ) -> io::Result<RecordWriter<W>> {
+        match format {
+            ExportFormat::Csv => {
+                let header: Vec<Value> = columns.iter().map(|&column| column.into()).collect();
+                write_csv_record(&mut out, &header)?;
+            }
+            ExportFormat::Json => out.write_all(b"[")?,
+            ExportFormat::Ndjson => {}
+        }
+        Ok(RecordWriter {
+            out,
+            format,
+            columns,
+            count: 0,
+        })
+    }
+
+    /// Writes a record with a value for each column.
+    fn write(&mut self, record: &[Value]) -> io::Result<()> {
+        match self.format {
+            ExportFormat::Csv => write_csv_record(&mut self.out, record)?,
+            ExportFormat::Json | ExportFormat::Ndjson => {
+                if self.format == ExportFormat::Json {
+                    self.out
+                        .write_all(if self.count == 0 { b"\n" } else { b",\n" })?;
+                }
+                let object: Map<String, Value> = self
+                    .columns
+                    .iter()
+                    .map(|column| column.to_string())
+                    .zip(record.iter().cloned())
+                    .collect();
+                serde_json::to_writer(&mut self.out, &object)?;
+                if self.format == ExportFormat::Ndjson {
+                    self.out.write_all(b"\n")?;
+                }
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        if self.format == ExportFormat::Json {
+            self.out
+                .write_all(if self.count == 0 { b"]\n" } else { b"\n]\n" })?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn write_csv_record<W: Write>(out: &mut W, record: &[Value]) -> io::Result<()> {
+    for (i, value) in record.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        match value {
+            Value::Null => {}
+            Value::String(s) if s.contains(&[',', '"', '\n', '\r'][..]) => {
+                write!(out, "\"{}\"", s.replace('"', "\"\""))?;
+            }
+            Value::String(s) => out.write_all(s.as_bytes())?,
+            value => write!(out, "{}", value)?,
+        }
+    }
+    out.write_all(b"\r\n")
+}
+
+/// Sends everything written to it as chunks of a response body.
+struct ChunkWriter<'a>(&'a mut futures::sink::Wait<mpsc::Sender<io::Result<Vec<u8>>>>);
+
+impl Write for ChunkWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "export response closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn parse_request(
+    name: &str,
+    query: &HashMap<String, String>,
+) -> anyhow::Result<(ExportTable, ExportFormat, ExportFilter)> {
+    let (table, format) = name
+        .split_once('.')
+        .context("expected a path such as /races.csv")?;
+    Ok((
+        table.parse()?,
+        format.parse()?,
+        ExportFilter::from_query(query)?,
+    ))
+}
+
+/// Serves `GET /<table>.<format>`, such as `/entrants.csv`, with the `from`,
+/// `to` and `track` query parameters of `ExportFilter`.
+///
+/// The export runs on its own thread, and is streamed to the client as it is
+/// written. If it fails partway, the response is cut off.
+pub(crate) fn export_filter(pool: Option<DbPool>) -> BoxedFilter<(impl Reply,)> {
+    warp::get2()
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .map(move |name: String, query: HashMap<String, String>| {
+            let request = parse_request(&name, &query).and_then(|request| {
+                let pool = pool.clone().context("not connected to a database")?;
+                Ok((request, pool))
+            });
+            let ((table, format, filter), pool) = match request {
+                Ok(request) => request,
+                Err(err) => {
+                    let mut response = Response::new(Body::from(format!("{:#}\n", err)));
+                    *response.status_mut() = StatusCode::BAD_REQUEST;
+                    return response;
+                }
+            };
+
+            let (sender, receiver) = mpsc::channel(4);
+            thread::spawn(move || {
+                let mut sender = sender.wait();
+                let result = pool.get().and_then(|db| {
+                    let out = BufWriter::with_capacity(1 << 16, ChunkWriter(&mut sender));
+                    export(&db, table, format, &filter, out)
+                });
+                if let Err(err) = result {
+                    let err = io::Error::new(io::ErrorKind::Other, format!("{:#}", err));
+                    let _ = sender.send(Err(err));
+                }
+            });
+            let body = receiver.then(|chunk| match chunk {
+                Ok(chunk) => chunk,
+                Err(()) => Err(io::Error::new(io::ErrorKind::Other, "export stopped")),
+            });
+
+            let mut response = Response::new(Body::wrap_stream(body));
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(format.content_type()),
+            );
+            response
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(format: ExportFormat, records: &[&[Value]]) -> String {
+        let mut writer = RecordWriter::new(Vec::new(), format, &["id", "name"]).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn record_formats() {
+        let records: &[&[Value]] = &[&[1.into(), "Some, Driver".into()], &[2.into(), Value::Null]];
+        assert_eq!(
+            written(ExportFormat::Csv, records),
+            "id,name\r\n1,\"Some, Driver\"\r\n2,\r\n"
+        );
+        assert_eq!(
+            written(ExportFormat::Json, records),
+            "[\n{\"id\":1,\"name\":\"Some, Driver\"},\n{\"id\":2,\"name\":null}\n]\n"
+        );
+        assert_eq!(
+            written(ExportFormat::Ndjson, records),
+            "{\"id\":1,\"name\":\"Some, Driver\"}\n{\"id\":2,\"name\":null}\n"
+        );
+        assert_eq!(written(ExportFormat::Json, &[]), "[]\n");
+    }
+}
@@ -0,0 +1,311 @@
+use crate::db::DbConnection;
+use crate::model::FetchCursor;
+use anyhow::{anyhow, bail, Context};
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use futures::{Future, Stream};
+use hyper::client::HttpConnector;
+use hyper::{Client, StatusCode, Uri};
+use select::document::Document;
+use select::predicate::Name;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::current_thread::Runtime;
+use tokio::timer::Timeout;
+
+/// How the fetcher talks to the results website.
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    /// How many times a request that failed with a network error, a timeout
+    /// or a server error is retried before giving up.
+    pub retries: u32,
+    /// How long to wait before the first retry. The wait doubles with every
+    /// retry after that.
+    pub backoff: Duration,
+    /// How long to wait for a response.
+    pub timeout: Duration,
+}
+
+impl Default for FetchOptions {
+    fn default() -> FetchOptions {
+        FetchOptions {
+            retries: 5,
+            backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Downloads results from a website that publishes them as
+/// `<base URL>/EventResult/YYYY/MM/DD/<id>.html`, with an index page linking
+/// the results of each day at `<base URL>/EventResult/YYYY/MM/DD/`.
+pub struct Fetcher {
+    base_url: String,
+    options: FetchOptions,
+    client: Client<HttpConnector>,
+    runtime: Runtime,
+}
+
+/// Why a request failed, and whether trying it again might help.
+struct Failure {
+    error: anyhow::Error,
+    transient: bool,
+}
+
+impl Fetcher {
+    /// Only plain `http://` URLs are supported.
+    pub fn new(base_url: &str, options: FetchOptions) -> anyhow::Result<Fetcher> {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let uri: Uri = base_url.parse().context("invalid base URL")?;
+        if uri.scheme_str() != Some("http") {
+            bail!("only http:// URLs are supported");
+        }
+        Ok(Fetcher {
+            base_url,
+            options,
+            client: Client::new(),
+            runtime: Runtime::new()?,
+        })
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Lists the races published from `after_date` up to and including
+    /// `until`, ordered by date and id, leaving out those on `after_date` up
+    /// to race `after_id`.
+    pub(crate) fn new_races(
+        &mut self,
+        after_id: i32,
+        after_date: NaiveDate,
+        until: NaiveDate,
+    ) -> anyhow::Result<Vec<(i32, NaiveDate)>> {
+        let mut races = Vec::new();
+        let mut date = after_date;
+        while date <= until {
+            let index = self.get(&format!("EventResult/{}/", date.format("%Y/%m/%d")))?;
+            let mut ids = listed_race_ids(index.as_deref().unwrap_or(""));
+            if date == after_date {
+                ids.retain(|&id| id > after_id);
+            }
+            ids.sort_unstable();
+            ids.dedup();
+            races.extend(ids.into_iter().map(|id| (id, date)));
+            date += chrono::Duration::days(1);
+        }
+        Ok(races)
+    }
+
+    /// Downloads the results document of a race.
+    pub(crate) fn results(&mut self, id: i32, date: NaiveDate) -> anyhow::Result<String> {
+        let path = format!("EventResult/{}/{}.html", date.format("%Y/%m/%d"), id);
+        self.get(&path)?
+            .with_context(|| format!("the results of race {} are missing", id))
+    }
+
+    /// Downloads `path` below the base URL, retrying transient failures.
+    /// Returns `None` if there is no such page.
+    fn get(&mut self, path: &str) -> anyhow::Result<Option<String>> {
+        let url = format!("{}/{}", self.base_url, path);
+        let uri: Uri = url
+            .parse()
+            .with_context(|| format!("invalid URL {}", url))?;
+        let mut backoff = self.options.backoff;
+        let mut retries = 0;
+        loop {
+            match self.try_get(uri.clone()) {
+                Ok(page) => return Ok(page),
+                Err(failure) if failure.transient && retries < self.options.retries => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    retries += 1;
+                }
+                Err(failure) => {
+                    return Err(failure.error.context(format!(
+                        "failed to fetch {} (retried {} times)",
+                        url, retries
+                    )))
+                }
+            }
+        }
+    }
+
+    fn try_get(&mut self, uri: Uri) -> Result<Option<String>, Failure> {
+        let request = self.client.get(uri).and_then(|response| {
+            let status = response.status();
+            response
+                .into_body()
+                .concat2()
+                .map(move |body| (status, body))
+        });
+        let (status, body) = match self
+            .runtime
+            .block_on(Timeout::new(request, self.options.timeout))
+        {
+            Ok(response) => response,
+            Err(e) if e.is_elapsed() => return Err(Failure::transient(anyhow!("timed out"))),
+            Err(e) => {
+                return Err(match e.into_inner() {
+                    Some(e) => Failure::transient(e.into()),
+                    None => Failure::permanent(anyhow!("timer failed")),
+                })
+            }
+        };
+        if status == StatusCode::NOT_FOUND {
+            Ok(None)
+        } else if status.is_success() {
+            let page = String::from_utf8(body.to_vec()).context("page is not valid UTF-8");
+            page.map(Some).map_err(Failure::permanent)
+        } else if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            Err(Failure::transient(anyhow!("server responded {}", status)))
+        } else {
+            Err(Failure::permanent(anyhow!("server responded {}", status)))
+        }
+    }
+}
+
+impl Failure {
+    fn transient(error: anyhow::Error) -> Failure {
+        Failure {
+            error,
+            transient: true,
+        }
+    }
+
+    fn permanent(error: anyhow::Error) -> Failure {
+        Failure {
+            error,
+            transient: false,
+        }
+    }
+}
+
+/// The ids of the `<id>.html` pages linked from an index page.
+fn listed_race_ids(index: &str) -> Vec<i32> {
+    Document::from(index)
+        .find(Name("a"))
+        .filter_map(|link| link.attr("href"))
+        .filter_map(|href| href.rsplit('/').next()?.strip_suffix(".html")?.parse().ok())
+        .collect()
+}
+
+pub(crate) fn load_cursor(db: &DbConnection, url: &str) -> anyhow::Result<Option<FetchCursor>> {
+    use crate::schema::fetch_cursors::dsl::fetch_cursors;
+    Ok(with_conn!(db, |conn| fetch_cursors
+        .find(url)
+        .first(conn)
+        .optional())?)
+}
+
+pub(crate) fn save_cursor(db: &DbConnection, cursor: &FetchCursor) -> anyhow::Result<()> {
+    use crate::schema::fetch_cursors::dsl::fetch_cursors;
+    with_conn!(db, |conn| diesel::replace_into(fetch_cursors)
+        .values(cursor)
+        .execute(conn))?;
+    Ok(())
+}
+
+/// The latest stored race, where fetching from a website starts the first
+/// time.
+pub(crate) fn latest_race(db: &DbConnection) -> anyhow::Result<Option<(i32, NaiveDate)>> {
+    use crate::schema::races::dsl::*;
+    Ok(with_conn!(db, |conn| races
+        .select((id, date))
+        .order((date.desc(), id.desc()))
+        .first(conn)
+        .optional())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Serves the sample archive over HTTP with an index page for each day,
+    /// failing the first request for every path. Returns the base URL.
+    fn serve_samples() -> String {
+        let mut pages: HashMap<String, String> = HashMap::new();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(
+            &include_bytes!("samples/EventResult.tar.gz")[..],
+        ));
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_str().unwrap().to_string();
+            if !path.ends_with(".html") {
+                continue;
+            }
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            let (dir, name) = path.split_at(path.rfind('/').unwrap() + 1);
+            let link = format!("<a href=\"{}\">{}</a>\n", name, name);
+            pages
+                .entry(format!("/{}", dir))
+                .or_default()
+                .push_str(&link);
+            pages.insert(format!("/{}", path), contents);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut requested = HashSet::new();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut lines = BufReader::new(&stream).lines().map(Result::unwrap);
+                let request = lines.next().unwrap();
+                lines.take_while(|line| !line.is_empty()).for_each(drop);
+                let path = request.split(' ').nth(1).unwrap().to_string();
+                let (status, body) = match pages.get(&path) {
+                    _ if requested.insert(path) => ("503 Service Unavailable", ""),
+                    Some(page) => ("200 OK", page.as_str()),
+                    None => ("404 Not Found", ""),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn fetches_new_races() {
+        let options = FetchOptions {
+            retries: 1,
+            backoff: Duration::from_millis(1),
+            ..FetchOptions::default()
+        };
+        let mut fetcher = Fetcher::new(&serve_samples(), options).unwrap();
+        let date = |s: &str| s.parse::<NaiveDate>().unwrap();
+
+        let races = fetcher
+            .new_races(20630, date("2020-01-25"), date("2020-02-05"))
+            .unwrap();
+        let expected = [
+            (20632, date("2020-01-25")),
+            (20638, date("2020-01-25")),
+            (21101, date("2020-02-01")),
+            (21104, date("2020-02-01")),
+            (21109, date("2020-02-01")),
+        ];
+        assert_eq!(races, expected);
+
+        // Only the races of the first day are compared with `after_id`.
+        let races = fetcher
+            .new_races(21105, date("2020-01-25"), date("2020-02-01"))
+            .unwrap();
+        assert_eq!(races, expected[2..]);
+
+        let results = fetcher.results(21101, date("2020-02-01")).unwrap();
+        assert!(crate::parser::parse_race(&results, &Default::default()).is_ok());
+        assert!(fetcher.results(21102, date("2020-02-01")).is_err());
+    }
+}
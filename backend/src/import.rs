@@ -0,0 +1,138 @@
+use anyhow::{bail, Context};
+use chrono::naive::NaiveDate;
+use flate2::read::GzDecoder;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// A results document found in an `EventResult/YYYY/MM/DD/<id>.<ext>` tree.
+#[derive(Debug, Clone)]
+pub struct ResultFile {
+    pub id: i32,
+    pub date: NaiveDate,
+    pub results: String,
+}
+
+/// Extracts the race id and date from a path shaped like
+/// `.../YYYY/MM/DD/<id>.<ext>`.
+pub fn parse_result_path(path: &Path) -> anyhow::Result<(i32, NaiveDate)> {
+    let id = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .context("missing file name")?
+        .parse()
+        .context("file name is not a race id")?;
+
+    let mut parents = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .map(|dir| dir.file_name().and_then(|name| name.to_str()));
+    let mut next = |what| -> anyhow::Result<u32> {
+        parents
+            .next()
+            .flatten()
+            .with_context(|| format!("missing {} directory", what))?
+            .parse()
+            .with_context(|| format!("{} directory is not a number", what))
+    };
+    let day = next("day")?;
+    let month = next("month")?;
+    let year = next("year")?;
+
+    let date = match NaiveDate::from_ymd_opt(year as i32, month, day) {
+        Some(date) => date,
+        None => bail!("invalid date {:04}-{:02}-{:02}", year, month, day),
+    };
+    Ok((id, date))
+}
+
+/// The extensions of the files that are read as results unless others are
+/// asked for. Chat logs are saved as `.txt` or `.log` files, which are too
+/// common to pick up without being asked to.
+pub const DEFAULT_EXTENSIONS: &[&str] = &["html", "csv", "json"];
+
+pub(crate) fn is_result_path<S: AsRef<str>>(path: &Path, extensions: &[S]) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| extensions.iter().any(|allowed| allowed.as_ref() == ext))
+}
+
+/// Calls `f` with every results document below `source`, which may be
+/// either a directory or a `.tar.gz` archive, that has one of `extensions`.
+///
+/// Errors that only affect a single file are passed to `f` instead of
+/// aborting the walk.
+pub fn for_each_result_file<S, F>(source: &Path, extensions: &[S], mut f: F) -> anyhow::Result<()>
+where
+    S: AsRef<str>,
+    F: FnMut(&Path, anyhow::Result<ResultFile>),
+{
+    if source.is_dir() {
+        for entry in WalkDir::new(source).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+            let entry = entry?;
+            let path = entry.path();
+            if !entry.file_type().is_file() || !is_result_path(path, extensions) {
+                continue;
+            }
+            f(path, read_result(path, fs::read_to_string(path)));
+        }
+    } else {
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(source)?));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            if !entry.header().entry_type().is_file() || !is_result_path(&path, extensions) {
+                continue;
+            }
+            let mut contents = String::new();
+            let read = entry.read_to_string(&mut contents).map(|_| contents);
+            f(&path, read_result(&path, read));
+        }
+    }
+    Ok(())
+}
+
+fn read_result(path: &Path, contents: std::io::Result<String>) -> anyhow::Result<ResultFile> {
+    let (id, date) = parse_result_path(path)?;
+    Ok(ResultFile {
+        id,
+        date,
+        results: contents?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn result_paths() {
+        let (id, date) = parse_result_path(Path::new("EventResult/2020/01/25/20638.html")).unwrap();
+        assert_eq!(id, 20638);
+        assert_eq!(date, "2020-01-25".parse::<NaiveDate>().unwrap());
+
+        assert!(parse_result_path(Path::new("2020/01/25/index.html")).is_err());
+        assert!(parse_result_path(Path::new("01/25/20638.html")).is_err());
+        assert!(parse_result_path(Path::new("2020/02/30/20638.html")).is_err());
+
+        assert!(is_result_path(
+            Path::new("2020/01/25/20638.json"),
+            DEFAULT_EXTENSIONS
+        ));
+        assert!(!is_result_path(
+            Path::new("2020/01/25/20638.png"),
+            DEFAULT_EXTENSIONS
+        ));
+        assert!(!is_result_path(
+            Path::new("2020/01/25/README.txt"),
+            DEFAULT_EXTENSIONS
+        ));
+        assert!(is_result_path(
+            Path::new("2020/01/25/20638.txt"),
+            &["html", "txt"]
+        ));
+    }
+}
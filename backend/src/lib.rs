@@ -3,55 +3,362 @@
 #[macro_use]
 extern crate diesel;
 
+#[macro_use]
+mod db;
+
+mod aliases;
 mod api;
+mod auth;
+mod backup;
+mod championships;
+mod diff;
+mod events;
+mod export;
+mod fetch;
+mod import;
+mod migrations;
 mod model;
 mod parser;
+mod ratings;
+mod reparse;
 mod schema;
+mod watch;
+
+pub use self::backup::TableCounts;
+pub use self::diff::{Change, FieldChange, RaceDiff};
+pub use self::export::{ExportFilter, ExportFormat, ExportTable};
+pub use self::fetch::{FetchOptions, Fetcher};
+pub use self::import::{for_each_result_file, parse_result_path, ResultFile, DEFAULT_EXTENSIONS};
+pub use self::model::{ApiToken, ImportOutcome, Role};
+pub use self::parser::{Field, GapMode, ParseError, ParseOptions, Warning};
+pub use self::watch::Watcher;
 
-use self::api::{Context, Mutation, Query, Schema};
-use self::parser::parse_race;
-use anyhow::anyhow;
+use self::api::{Context, Mutation, Query, Schema, Subscription, SubscriptionSchema};
+use self::db::DbPool;
+use self::events::Broadcaster;
+use self::model::{FetchCursor, NewImportLogEntry};
+use self::parser::{detect_format, Format};
+use anyhow::{anyhow, Context as _};
 use chrono::naive::NaiveDate;
+use chrono::{Duration, Utc};
 use diesel::prelude::*;
 use juniper::IntrospectionFormat;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
 use warp::filters::BoxedFilter;
 use warp::reply::Reply;
 use warp::Filter;
 
+/// How `Database::add_race` treats a race id that is already stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddMode {
+    /// Fail without changing anything.
+    Insert,
+    /// Replace the stored race and all of its entrants.
+    Replace,
+}
+
+/// The outcome of `Database::add_race`.
+#[derive(Debug, Clone)]
+pub struct AddedRace {
+    /// The name of the format the results were detected to be in, such as
+    /// `html`.
+    pub format: &'static str,
+    pub diff: RaceDiff,
+    pub warnings: Vec<Warning>,
+}
+
+impl fmt::Display for AddedRace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diff)?;
+        for warning in &self.warnings {
+            write!(f, "\n  warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Database {
-    conn: MysqlConnection,
+    pool: DbPool,
+    parse_options: ParseOptions,
+    /// Where changes are published. An `Api` made from the database shares
+    /// it, so that the API's subscribers hear about races imported through
+    /// the database in the same process.
+    events: Arc<Broadcaster>,
 }
 
 impl Database {
+    /// Connects to the database and applies any pending migrations.
     pub fn connect(db_url: &str) -> anyhow::Result<Database> {
+        let database = Database::connect_unmigrated(db_url)?;
+        database.migrate()?;
+        Ok(database)
+    }
+
+    /// Connects to the database without checking or migrating its schema.
+    pub fn connect_unmigrated(db_url: &str) -> anyhow::Result<Database> {
         Ok(Database {
-            conn: Connection::establish(db_url)?,
+            pool: DbPool::new(db_url)?,
+            parse_options: ParseOptions::default(),
+            events: Arc::default(),
         })
     }
 
-    pub fn add_race(&self, id: i32, date: NaiveDate, results: &str) -> anyhow::Result<()> {
-        let race = parse_race(results)?;
-        race.insert_into(&self.conn, id, date)
+    /// Returns the names of the migrations that have not been applied yet.
+    pub fn pending_migrations(&self) -> anyhow::Result<Vec<&'static str>> {
+        let pending = migrations::pending(&self.pool.get()?)?;
+        Ok(pending.iter().map(|m| m.name).collect())
+    }
+
+    /// Applies all pending migrations, returning the names of the ones that
+    /// were run.
+    pub fn migrate(&self) -> anyhow::Result<Vec<&'static str>> {
+        let applied = migrations::run_pending(&self.pool.get()?)?;
+        Ok(applied.iter().map(|m| m.name).collect())
+    }
+
+    pub fn set_parse_options(&mut self, options: ParseOptions) {
+        self.parse_options = options;
+    }
+
+    pub fn has_race(&self, id: i32) -> anyhow::Result<bool> {
+        use self::schema::races::dsl::races;
+        let db = self.pool.get()?;
+        Ok(with_conn!(&db, |conn| diesel::select(diesel::dsl::exists(
+            races.find(id)
+        ))
+        .get_result(conn))?)
+    }
+
+    pub fn add_race(
+        &self,
+        id: i32,
+        date: NaiveDate,
+        results: &str,
+        mode: AddMode,
+    ) -> anyhow::Result<AddedRace> {
+        let parsed = detect_format(results)
+            .ok_or(ParseError::UnknownFormat)
+            .and_then(|format| Ok((format, format.parse(results, &self.parse_options)?)));
+        let (format, mut race) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                // The document is kept, so that it can be imported by
+                // `reparse` once the parser has been fixed.
+                let err = anyhow::Error::from(err);
+                reparse::store_failed(&self.pool.get()?, id, date, results, &err)?;
+                return Err(err);
+            }
+        };
+        let warnings = std::mem::take(&mut race.warnings);
+        let diff = race.insert_into(
+            &self.pool.get()?,
+            id,
+            date,
+            mode,
+            Some(results),
+            &self.events,
+        )?;
+        Ok(AddedRace {
+            format: format.name(),
+            diff,
+            warnings,
+        })
+    }
+
+    /// Imports the races published on the fetcher's website since the last
+    /// one imported from there, skipping races that are already stored. The
+    /// first time, fetching starts after the latest stored race.
+    ///
+    /// Stops at the first race that fails to import, so that the next call
+    /// tries it again.
+    pub fn fetch_races(&self, fetcher: &mut Fetcher) -> anyhow::Result<Vec<AddedRace>> {
+        let db = self.pool.get()?;
+        let (mut race_id, mut date) = match fetch::load_cursor(&db, fetcher.base_url())? {
+            Some(cursor) => (cursor.race_id, cursor.date),
+            None => fetch::latest_race(&db)?
+                .context("no races are stored yet; set where to start fetching from first")?,
+        };
+        let today = Utc::now().naive_utc().date();
+        // The website may already be on the next day.
+        let until = today + Duration::days(1);
+
+        let mut added = Vec::new();
+        for (id, race_date) in fetcher.new_races(race_id, date, until)? {
+            if !self.has_race(id)? {
+                let results = fetcher.results(id, race_date)?;
+                let race = self
+                    .add_race(id, race_date, &results, AddMode::Insert)
+                    .with_context(|| format!("failed to import race {}", id))?;
+                added.push(race);
+            }
+            // The next day is scanned again until it is today, as races may
+            // still be published today with lower ids.
+            if race_date <= today {
+                race_id = id;
+                date = race_date;
+                self.set_fetch_cursor(fetcher, race_id, date)?;
+            }
+        }
+        // Every day up to today has been scanned, including those without
+        // races, which later calls don't need to scan again.
+        if date < today {
+            self.set_fetch_cursor(fetcher, 0, today)?;
+        }
+        Ok(added)
+    }
+
+    /// Makes the next `fetch_races` from the fetcher's website start after
+    /// race `id` on `date`.
+    pub fn set_fetch_cursor(
+        &self,
+        fetcher: &Fetcher,
+        id: i32,
+        date: NaiveDate,
+    ) -> anyhow::Result<()> {
+        let cursor = FetchCursor {
+            base_url: fetcher.base_url().to_string(),
+            race_id: id,
+            date,
+        };
+        fetch::save_cursor(&self.pool.get()?, &cursor)
+    }
+
+    /// Imports the results files that have settled in the watcher's
+    /// directory, replacing stored races with the same id, and files them
+    /// away. The outcome for each file is recorded in the import log and
+    /// passed to `report`.
+    pub fn import_watched<F>(&self, watcher: &mut Watcher, mut report: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&Path, ImportOutcome, &anyhow::Result<AddedRace>),
+    {
+        for file in watcher.settled_files()? {
+            let mut race_id = None;
+            let result = watcher.race_of(&file).and_then(|(id, date)| {
+                race_id = Some(id);
+                let results = watcher.read(&file)?;
+                self.add_race(id, date, &results, AddMode::Replace)
+            });
+            let outcome = match &result {
+                Ok(added) if added.diff.created => ImportOutcome::Imported,
+                Ok(added) if added.diff.is_unchanged() => ImportOutcome::Unchanged,
+                Ok(_) => ImportOutcome::Replaced,
+                Err(_) => ImportOutcome::Failed,
+            };
+            let entry = NewImportLogEntry {
+                file: file.to_string_lossy().into_owned(),
+                race_id,
+                outcome,
+                error: result.as_ref().err().map(|err| format!("{:#}", err)),
+                logged_at: Utc::now().naive_utc(),
+            };
+            watch::log_import(&self.pool.get()?, &entry)?;
+            watcher.file_away(&file, result.is_err())?;
+            report(&file, outcome, &result);
+        }
+        Ok(())
+    }
+
+    /// Merges driver `source` into `target`, keeping the name of `source` as
+    /// an alias.
+    pub fn merge_drivers(&self, source: i32, target: i32) -> anyhow::Result<()> {
+        aliases::merge_drivers(&self.pool.get()?, source, target)
+    }
+
+    /// Turns an alias back into a separate driver, returning its id.
+    pub fn split_alias(&self, alias: &str) -> anyhow::Result<i32> {
+        aliases::split_alias(&self.pool.get()?, alias)
+    }
+
+    pub fn rename_driver(&self, id: i32, name: &str) -> anyhow::Result<()> {
+        aliases::rename_driver(&self.pool.get()?, id, name)
+    }
+
+    /// Writes the rows of `table` that match `filter` to `out`, a batch of
+    /// races at a time.
+    pub fn export<W: Write>(
+        &self,
+        table: ExportTable,
+        format: ExportFormat,
+        filter: &ExportFilter,
+        out: W,
+    ) -> anyhow::Result<()> {
+        export::export(&self.pool.get()?, table, format, filter, out)
+    }
+
+    /// Writes every table to `out` as a gzipped archive, returning the number
+    /// of rows of each.
+    pub fn backup<W: Write>(&self, out: W) -> anyhow::Result<TableCounts> {
+        backup::backup(&self.pool.get()?, out)
+    }
+
+    /// Restores an archive written by `backup` into this database, which
+    /// must be empty.
+    pub fn restore<R: Read>(&self, archive: R) -> anyhow::Result<TableCounts> {
+        backup::restore(&self.pool.get()?, archive)
+    }
+
+    /// Parses every race that has a stored results document, and every
+    /// document that failed to import, again with the current parse options,
+    /// calling `f` with how each race would change. With `apply`, the changes
+    /// are stored.
+    pub fn reparse<F>(&self, apply: bool, f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(i32, anyhow::Result<AddedRace>),
+    {
+        reparse::reparse(
+            &self.pool.get()?,
+            &self.parse_options,
+            &self.events,
+            apply,
+            f,
+        )
+    }
+
+    /// Recomputes the ratings of all drivers from scratch.
+    pub fn rebuild_ratings(&self) -> anyhow::Result<()> {
+        ratings::rebuild(&self.pool.get()?)
+    }
+
+    /// Creates an API token with the given role, returning its id and the
+    /// token itself, which is not stored and can't be shown again.
+    pub fn create_token(&self, name: &str, role: Role) -> anyhow::Result<(i32, String)> {
+        auth::create_token(&self.pool.get()?, name, role)
+    }
+
+    pub fn revoke_token(&self, id: i32) -> anyhow::Result<()> {
+        auth::revoke_token(&self.pool.get()?, id)
+    }
+
+    pub fn tokens(&self) -> anyhow::Result<Vec<ApiToken>> {
+        auth::list_tokens(&self.pool.get()?)
     }
 }
 
 pub struct Api {
     context: Context,
     schema: Schema,
+    subscriptions: SubscriptionSchema,
 }
 
 impl Api {
-    pub fn new(db_url: &str) -> anyhow::Result<Api> {
-        Ok(Api {
-            context: Context::new(db_url)?,
+    /// Serves `database`, publishing the changes made through it to
+    /// subscribers.
+    pub fn new(database: &Database) -> Api {
+        Api {
+            context: Context::with_pool(database.pool.clone(), database.events.clone()),
             schema: Schema::new(Query, Mutation),
-        })
+            subscriptions: SubscriptionSchema::new(Subscription, juniper::EmptyMutation::new()),
+        }
     }
 
     pub fn without_database() -> Api {
         Api {
             context: Context::without_database(),
             schema: Schema::new(Query, Mutation),
+            subscriptions: SubscriptionSchema::new(Subscription, juniper::EmptyMutation::new()),
         }
     }
 
@@ -62,12 +369,83 @@ impl Api {
         Ok(serde_json::to_string(&value)?)
     }
 
+    /// Serves exports at `GET /<table>.<format>`, such as `/entrants.csv`,
+    /// filtered by the `from`, `to` and `track` query parameters.
+    pub fn export_filter(&self) -> BoxedFilter<(impl Reply,)> {
+        export::export_filter(self.context.db.clone())
+    }
+
+    /// Serves the API, authenticating requests with the API token in their
+    /// `Authorization: Bearer <token>` header, if any. WebSocket upgrade
+    /// requests to the same path are served subscriptions.
     pub fn to_filter(self) -> BoxedFilter<(impl Reply,)> {
-        let context = self.context;
-        juniper_warp::make_graphql_filter(
+        let context = Arc::new(self.context);
+        let subscriptions = api::subscription_filter(context.clone(), self.subscriptions);
+        let graphql = juniper_warp::make_graphql_filter(
             self.schema,
-            warp::any().map(move || context.clone()).boxed(),
+            warp::header::optional("authorization")
+                .map(move |authorization: Option<String>| context.for_request(authorization))
+                .boxed(),
+        );
+        subscriptions.or(graphql).boxed()
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::TestDb;
+    use crate::events::Event;
+    use futures::Stream;
+    use serde_json::json;
+
+    #[test]
+    fn api_hears_imports() {
+        let test_db = TestDb::new("api_events");
+        let database = Database::connect(test_db.url()).unwrap();
+        let api = Api::new(&database);
+        let mut events = api.context.events.subscribe().wait();
+
+        let results = r#"{
+            "track": "Sandpit",
+            "mode": "laps",
+            "laps": 5,
+            "results": [
+                {"position": 1, "name": "Some Driver", "vehicle": "Formula", "time": 312345},
+                {"position": 2, "name": "Other", "vehicle": "Formula", "time": 313345}
+            ]
+        }"#;
+        let date = "2020-06-10".parse().unwrap();
+        database
+            .add_race(7, date, results, AddMode::Insert)
+            .unwrap();
+
+        let event = events.next().unwrap().unwrap();
+        assert!(matches!(
+            event,
+            Event::RaceAdded {
+                race_id: 7,
+                replaced: false
+            }
+        ));
+        // Resolved as the subscription server does for each event.
+        let (data, errors) = juniper::execute(
+            "{ raceAdded { replaced race { id track { name } } } }",
+            None,
+            &api.subscriptions,
+            &juniper::Variables::new(),
+            &api.context.for_event(Some(event)),
         )
-        .boxed()
+        .unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            serde_json::to_value(&data).unwrap(),
+            json!({
+                "raceAdded": {
+                    "replaced": false,
+                    "race": { "id": 7, "track": { "name": "Sandpit" } },
+                },
+            })
+        );
     }
 }
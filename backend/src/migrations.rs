@@ -0,0 +1,255 @@
+use crate::db::DbConnection;
+use crate::model::VehicleName;
+use crate::ratings;
+use anyhow::bail;
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel_migrations::{Migration, MigrationConnection, MigrationError, RunMigrationsError};
+use std::collections::HashMap;
+use std::io;
+
+/// A migration from `backend/migrations/<backend>`, compiled into the binary
+/// by `build.rs`.
+#[derive(Debug)]
+pub(crate) struct EmbeddedMigration {
+    version: &'static str,
+    pub(crate) name: &'static str,
+    up_sql: &'static str,
+}
+
+impl Migration for EmbeddedMigration {
+    fn version(&self) -> &str {
+        self.version
+    }
+
+    fn run(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        conn.batch_execute(self.up_sql).map_err(Into::into)
+    }
+
+    /// Only the `up.sql` of each migration is embedded, so reverting fails;
+    /// use the diesel CLI with `backend/migrations/<backend>` instead.
+    fn revert(&self, _conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        Err(RunMigrationsError::MigrationError(MigrationError::IoError(
+            io::Error::new(
+                io::ErrorKind::Other,
+                "embedded migrations cannot be reverted; use the diesel CLI",
+            ),
+        )))
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
+fn embedded(db: &DbConnection) -> &'static [EmbeddedMigration] {
+    match db {
+        #[cfg(feature = "mysql")]
+        DbConnection::Mysql(_) => MYSQL,
+        #[cfg(feature = "sqlite")]
+        DbConnection::Sqlite(_) => SQLITE,
+    }
+}
+
+/// Returns the version of the newest migration known to this build, which
+/// is the same for every backend.
+pub(crate) fn latest_version(db: &DbConnection) -> &'static str {
+    embedded(db).iter().map(|m| m.version).max().unwrap_or("")
+}
+
+/// Returns the migrations that have not been applied to the database yet.
+///
+/// Fails if the database has applied migrations that this build does not
+/// know about, which means that it was migrated by a newer version.
+pub(crate) fn pending(db: &DbConnection) -> anyhow::Result<Vec<&'static EmbeddedMigration>> {
+    let applied = with_conn!(db, |conn| {
+        diesel_migrations::setup_database(conn)?;
+        conn.previously_run_migration_versions()
+    })?;
+    let known = embedded(db);
+
+    let mut unknown: Vec<&str> = applied
+        .iter()
+        .map(String::as_str)
+        .filter(|version| !known.iter().any(|m| m.version == *version))
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        bail!(
+            "database schema is newer than this build (unknown migrations: {})",
+            unknown.join(", ")
+        );
+    }
+
+    Ok(known
+        .iter()
+        .filter(|m| !applied.contains(m.version))
+        .collect())
+}
+
+/// Applies all pending migrations, returning the ones that were run, then
+/// fills in the data that the migrations can't compute in SQL.
+pub(crate) fn run_pending(db: &DbConnection) -> anyhow::Result<Vec<&'static EmbeddedMigration>> {
+    let pending = pending(db)?;
+    let migrations = || pending.iter().map(|m| *m as &dyn Migration);
+    match db {
+        #[cfg(feature = "mysql")]
+        DbConnection::Mysql(conn) => {
+            diesel_migrations::run_migrations(conn, migrations(), &mut io::sink())?
+        }
+        #[cfg(feature = "sqlite")]
+        DbConnection::Sqlite(conn) => {
+            // Foreign keys are off while the migrations run, as they are in
+            // the diesel CLI, so that a table can be rebuilt without its
+            // deletion cascading into the tables that reference it.
+            conn.batch_execute("PRAGMA foreign_keys = OFF;")?;
+            let result = diesel_migrations::run_migrations(conn, migrations(), &mut io::sink());
+            conn.batch_execute("PRAGMA foreign_keys = ON;")?;
+            result?;
+            check_foreign_keys(conn)?;
+        }
+    }
+    merge_vehicles(db)?;
+    ratings::backfill(db)?;
+    Ok(pending)
+}
+
+/// Merges the vehicles whose names `VehicleName::new` considers the same and
+/// normalizes their names, as the vehicles migration leaves them as they
+/// were entered. Does nothing once they are normalized.
+fn merge_vehicles(db: &DbConnection) -> anyhow::Result<()> {
+    use crate::schema::{race_entrants, vehicles};
+
+    with_conn!(db, |conn| conn.transaction(|| {
+        let all: Vec<(i32, String, String)> = vehicles::table
+            .select((vehicles::id, vehicles::name, vehicles::normalized_name))
+            .order(vehicles::id)
+            .load(conn)?;
+
+        // The first vehicle of each normalized name is kept, and the entrants
+        // of the others are moved to it before they are deleted, so that the
+        // kept vehicles can be renamed without clashing with them.
+        let mut kept: HashMap<String, i32> = HashMap::new();
+        let mut renamed = Vec::new();
+        for (id, name, normalized_name) in all {
+            let normal = VehicleName::new(&name);
+            if let Some(&kept_id) = kept.get(&normal.normalized_name) {
+                diesel::update(race_entrants::table.filter(race_entrants::vehicle_id.eq(id)))
+                    .set(race_entrants::vehicle_id.eq(kept_id))
+                    .execute(conn)?;
+                diesel::delete(vehicles::table.find(id)).execute(conn)?;
+                continue;
+            }
+            kept.insert(normal.normalized_name.clone(), id);
+            if name != normal.name || normalized_name != normal.normalized_name {
+                renamed.push((id, normal));
+            }
+        }
+        for (id, normal) in renamed {
+            diesel::update(vehicles::table.find(id))
+                .set((
+                    vehicles::name.eq(normal.name),
+                    vehicles::normalized_name.eq(normal.normalized_name),
+                ))
+                .execute(conn)?;
+        }
+        Ok(())
+    }))
+}
+
+/// Fails if a row references a row that doesn't exist, which SQLite doesn't
+/// check while foreign keys are off.
+#[cfg(feature = "sqlite")]
+fn check_foreign_keys(conn: &diesel::SqliteConnection) -> anyhow::Result<()> {
+    use diesel::dsl::sql;
+    use diesel::sql_types::Text;
+
+    let broken: Vec<String> =
+        sql::<Text>("SELECT DISTINCT \"table\" FROM pragma_foreign_key_check").load(conn)?;
+    if !broken.is_empty() {
+        bail!(
+            "migrations left rows with broken references in {}",
+            broken.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::{DbPool, TestDb};
+
+    /// Applies the migrations older than `version`, as an older build would
+    /// have.
+    fn migrate_before(db: &DbConnection, version: &str) {
+        let older = embedded(db)
+            .iter()
+            .filter(|m| m.version < version)
+            .map(|m| m as &dyn Migration);
+        with_conn!(db, |conn| diesel_migrations::run_migrations(
+            conn,
+            older,
+            &mut io::sink()
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn vehicles_migration() {
+        use crate::schema::{race_entrants, vehicles};
+
+        let test_db = TestDb::new("vehicles_migration");
+        let db = DbPool::new(test_db.url()).unwrap().get().unwrap();
+        migrate_before(&db, "20200529201130");
+        with_conn!(&db, |conn| conn.batch_execute(
+            "INSERT INTO tracks (id, name) VALUES (1, 'Sandpit');
+             INSERT INTO drivers (id, name) VALUES
+                 (1, 'A'), (2, 'B'), (3, 'C'), (4, 'D'), (5, 'E');
+             INSERT INTO races (id, date, track_id, laps, mode) VALUES
+                 (1, '2020-05-01', 1, 5, 'laps');
+             INSERT INTO race_entrants
+                 (race_id, driver_id, position, vehicle, fps_locked) VALUES
+                 (1, 1, 1, 'formula  one', 0),
+                 (1, 2, 2, ' Formula' || char(9) || 'One ', 0),
+                 (1, 3, 3, 'ÄRGER 2', 0),
+                 (1, 4, 4, 'ärger 2', 0),
+                 (1, 5, 5, NULL, 0);"
+        ))
+        .unwrap();
+        run_pending(&db).unwrap();
+
+        let all: Vec<(String, String)> = with_conn!(&db, |conn| vehicles::table
+            .select((vehicles::name, vehicles::normalized_name))
+            .order(vehicles::id)
+            .load(conn))
+        .unwrap();
+        assert_eq!(
+            all,
+            [
+                ("Formula One".to_string(), "formula one".to_string()),
+                ("ÄRGER 2".to_string(), "ärger 2".to_string()),
+            ]
+        );
+        let entrants: Vec<Option<String>> = with_conn!(&db, |conn| race_entrants::table
+            .left_join(vehicles::table)
+            .select(vehicles::normalized_name.nullable())
+            .order(race_entrants::driver_id)
+            .load(conn))
+        .unwrap();
+        assert_eq!(
+            entrants,
+            [
+                Some("formula one".to_string()),
+                Some("formula one".to_string()),
+                Some("ärger 2".to_string()),
+                Some("ärger 2".to_string()),
+                None,
+            ]
+        );
+
+        // Vehicles added later are found by their normalized names.
+        assert_eq!(
+            VehicleName::new("FORMULA ONE").get_or_insert(&db).unwrap(),
+            VehicleName::new("formula one").get_or_insert(&db).unwrap()
+        );
+    }
+}
@@ -1,8 +1,20 @@
-use crate::schema::{drivers, race_entrants, races};
-use chrono::naive::NaiveDate;
+use crate::db::DbConnection;
+use crate::schema::{
+    api_tokens, championship_rounds, championships, driver_aliases, driver_ratings, drivers,
+    failed_sources, fetch_cursors, import_log, race_entrants, race_sources, races, seasons, tracks,
+    vehicles,
+};
+use anyhow::bail;
+use chrono::naive::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
 use diesel_derive_enum::DbEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use juniper::GraphQLEnum;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Identifiable, Queryable)]
 pub(crate) struct Driver {
@@ -17,23 +29,119 @@ pub(crate) struct DriverName {
 }
 
 impl DriverName {
-    pub(crate) fn get_or_insert(self, conn: &MysqlConnection) -> anyhow::Result<i32> {
+    /// Returns the id of the driver with this name or alias, adding a new
+    /// driver if there is none.
+    pub(crate) fn get_or_insert(self, db: &DbConnection) -> anyhow::Result<i32> {
         use self::drivers::dsl::*;
-        diesel::insert_or_ignore_into(drivers)
-            .values(self.clone())
-            .execute(conn)?;
+        if let Some(driver_id) = DriverAlias::resolve(db, &self.name)? {
+            return Ok(driver_id);
+        }
+        with_conn!(db, |conn| {
+            diesel::insert_or_ignore_into(drivers)
+                .values(self.clone())
+                .execute(conn)?;
 
-        Ok(drivers.select(id).filter(name.eq(self.name)).first(conn)?)
+            Ok(drivers.select(id).filter(name.eq(self.name)).first(conn)?)
+        })
     }
 }
 
-#[derive(Debug, Clone, Identifiable, Insertable, Queryable)]
+#[derive(Debug, Clone, Identifiable, Queryable)]
+pub(crate) struct Track {
+    pub(crate) id: i32,
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "tracks"]
+pub(crate) struct TrackName {
+    pub(crate) name: String,
+}
+
+impl TrackName {
+    pub(crate) fn get_or_insert(self, db: &DbConnection) -> anyhow::Result<i32> {
+        use self::tracks::dsl::*;
+        with_conn!(db, |conn| {
+            diesel::insert_or_ignore_into(tracks)
+                .values(self.clone())
+                .execute(conn)?;
+
+            Ok(tracks.select(id).filter(name.eq(self.name)).first(conn)?)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Identifiable, Queryable)]
+pub(crate) struct Vehicle {
+    pub(crate) id: i32,
+    pub(crate) name: String,
+}
+
+/// The columns to select for a `Vehicle`, which leaves out the normalized
+/// name.
+pub(crate) const VEHICLE_COLUMNS: (vehicles::id, vehicles::name) = (vehicles::id, vehicles::name);
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "vehicles"]
+pub(crate) struct VehicleName {
+    pub(crate) name: String,
+    pub(crate) normalized_name: String,
+}
+
+impl VehicleName {
+    /// Vehicles are told apart ignoring case and runs of whitespace, as the
+    /// same car is often typed slightly differently.
+    pub(crate) fn new(name: &str) -> VehicleName {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        VehicleName {
+            normalized_name: name.to_lowercase(),
+            name,
+        }
+    }
+
+    pub(crate) fn get_or_insert(self, db: &DbConnection) -> anyhow::Result<i32> {
+        use self::vehicles::dsl::*;
+        with_conn!(db, |conn| {
+            diesel::insert_or_ignore_into(vehicles)
+                .values(self.clone())
+                .execute(conn)?;
+
+            Ok(vehicles
+                .select(id)
+                .filter(normalized_name.eq(self.normalized_name))
+                .first(conn)?)
+        })
+    }
+}
+
+/// Another name that a driver has entered races under.
+#[derive(Debug, Clone, Insertable, Queryable)]
+#[table_name = "driver_aliases"]
+pub(crate) struct DriverAlias {
+    pub(crate) name: String,
+    pub(crate) driver_id: i32,
+}
+
+impl DriverAlias {
+    pub(crate) fn resolve(db: &DbConnection, alias: &str) -> anyhow::Result<Option<i32>> {
+        use self::driver_aliases::dsl::*;
+        Ok(with_conn!(db, |conn| driver_aliases
+            .select(driver_id)
+            .filter(name.eq(alias))
+            .first(conn)
+            .optional())?)
+    }
+}
+
+#[derive(Debug, Clone, Identifiable, Insertable, AsChangeset, Queryable)]
+#[changeset_options(treat_none_as_null = "true")]
 pub(crate) struct Race {
     pub(crate) id: i32,
     pub(crate) date: NaiveDate,
-    pub(crate) track: String,
+    pub(crate) track_id: i32,
     pub(crate) laps: Option<i32>,
     pub(crate) minutes: Option<i32>,
+    pub(crate) mode: Option<RaceMode>,
 }
 
 #[derive(Debug, Clone, Identifiable, Insertable, Queryable)]
@@ -42,14 +150,191 @@ pub(crate) struct RaceEntrant {
     pub(crate) race_id: i32,
     pub(crate) driver_id: i32,
     pub(crate) position: Option<i32>,
-    pub(crate) vehicle: Option<String>,
+    pub(crate) vehicle_id: Option<i32>,
     pub(crate) time: Option<i32>,
+    pub(crate) gap: Option<i32>,
     pub(crate) best_lap: Option<i32>,
     pub(crate) lap: Option<i32>,
     pub(crate) reason: Option<Reason>,
     pub(crate) ping: Option<i32>,
     pub(crate) fps: Option<i32>,
     pub(crate) fps_locked: bool,
+    /// The name the driver was listed under in the results.
+    pub(crate) entered_as: Option<String>,
+    pub(crate) row_class: Option<String>,
+    pub(crate) name_class: Option<String>,
+    /// See `encode_extras`.
+    pub(crate) extras: Option<String>,
+}
+
+/// Stores the values of unknown results table columns, keyed by header, as a
+/// JSON object, or `None` if there are none.
+pub(crate) fn encode_extras(extras: &[(String, String)]) -> Option<String> {
+    if extras.is_empty() {
+        return None;
+    }
+    let object: serde_json::Map<_, _> = extras
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone().into()))
+        .collect();
+    Some(serde_json::Value::Object(object).to_string())
+}
+
+/// The inverse of `encode_extras`, ordered by header.
+pub(crate) fn decode_extras(extras: Option<&str>) -> anyhow::Result<Vec<(String, String)>> {
+    let object: serde_json::Map<_, _> = match extras {
+        Some(extras) => serde_json::from_str(extras)?,
+        None => return Ok(Vec::new()),
+    };
+    object
+        .into_iter()
+        .map(|(name, value)| match value {
+            serde_json::Value::String(value) => Ok((name, value)),
+            _ => bail!("extra {:?} is not a string", name),
+        })
+        .collect()
+}
+
+/// The results document a race was parsed from, gzipped.
+#[derive(Debug, Clone, Insertable, Queryable)]
+#[table_name = "race_sources"]
+pub(crate) struct RaceSource {
+    pub(crate) race_id: i32,
+    pub(crate) document: Vec<u8>,
+}
+
+impl RaceSource {
+    pub(crate) fn new(race_id: i32, document: &str) -> io::Result<RaceSource> {
+        Ok(RaceSource {
+            race_id,
+            document: compress(document)?,
+        })
+    }
+
+    pub(crate) fn document(&self) -> io::Result<String> {
+        decompress(&self.document)
+    }
+}
+
+/// A results document that failed to import, gzipped, with why it failed.
+#[derive(Debug, Clone, Insertable, Queryable)]
+#[table_name = "failed_sources"]
+pub(crate) struct FailedSource {
+    pub(crate) race_id: i32,
+    pub(crate) date: NaiveDate,
+    pub(crate) document: Vec<u8>,
+    pub(crate) error: String,
+    pub(crate) failed_at: NaiveDateTime,
+}
+
+impl FailedSource {
+    pub(crate) fn new(
+        race_id: i32,
+        date: NaiveDate,
+        document: &str,
+        error: String,
+        failed_at: NaiveDateTime,
+    ) -> io::Result<FailedSource> {
+        Ok(FailedSource {
+            race_id,
+            date,
+            document: compress(document)?,
+            error,
+            failed_at,
+        })
+    }
+
+    pub(crate) fn document(&self) -> io::Result<String> {
+        decompress(&self.document)
+    }
+}
+
+fn compress(document: &str) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(document.as_bytes())?;
+    encoder.finish()
+}
+
+fn decompress(document: &[u8]) -> io::Result<String> {
+    let mut decompressed = String::new();
+    GzDecoder::new(document).read_to_string(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// The rating of a driver after a race.
+#[derive(Debug, Clone, Insertable, Queryable)]
+pub(crate) struct DriverRating {
+    pub(crate) race_id: i32,
+    pub(crate) driver_id: i32,
+    pub(crate) rating: f64,
+    /// How much the rating changed in this race.
+    pub(crate) delta: f64,
+}
+
+#[derive(Debug, Clone, Identifiable, Queryable)]
+pub(crate) struct Season {
+    pub(crate) id: i32,
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "seasons"]
+pub(crate) struct NewSeason {
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Clone, Identifiable, Queryable)]
+pub(crate) struct Championship {
+    pub(crate) id: i32,
+    pub(crate) season_id: i32,
+    pub(crate) name: String,
+    /// The points for each finishing position, separated by commas.
+    pub(crate) points: String,
+    /// Points for the fastest lap of a round.
+    pub(crate) best_lap_points: i32,
+    /// How many of each driver's worst rounds don't count.
+    pub(crate) drop_rounds: i32,
+}
+
+pub(crate) fn parse_points_table(points: &str) -> anyhow::Result<Vec<i32>> {
+    if points.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    points
+        .split(',')
+        .map(|points| match points.trim().parse() {
+            Ok(points) if points >= 0 => Ok(points),
+            _ => bail!("invalid points {:?}", points),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "championships"]
+pub(crate) struct NewChampionship {
+    pub(crate) season_id: i32,
+    pub(crate) name: String,
+    pub(crate) points: String,
+    pub(crate) best_lap_points: i32,
+    pub(crate) drop_rounds: i32,
+}
+
+#[derive(Debug, Clone, Insertable, Queryable)]
+#[table_name = "championship_rounds"]
+pub(crate) struct ChampionshipRound {
+    pub(crate) championship_id: i32,
+    pub(crate) race_id: i32,
+}
+
+/// How far the results fetcher has got on a website: every race published
+/// before `date`, and those up to `race_id` on `date`, which is 0 if there
+/// were none yet.
+#[derive(Debug, Clone, Insertable, Queryable)]
+#[table_name = "fetch_cursors"]
+pub(crate) struct FetchCursor {
+    pub(crate) base_url: String,
+    pub(crate) race_id: i32,
+    pub(crate) date: NaiveDate,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, DbEnum, GraphQLEnum)]
@@ -58,3 +343,133 @@ pub(crate) enum Reason {
     Dnf,
     Dsq,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DbEnum, GraphQLEnum)]
+pub(crate) enum RaceMode {
+    Laps,
+    Minutes,
+    Rally,
+    PointToPoint,
+}
+
+/// What the holder of an API token is allowed to do. Each role includes the
+/// permissions of the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, DbEnum, GraphQLEnum)]
+pub enum Role {
+    /// May check results without storing them.
+    Viewer,
+    /// May add races.
+    Organiser,
+    /// May replace races that are already stored.
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Role> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "organiser" => Ok(Role::Organiser),
+            "admin" => Ok(Role::Admin),
+            _ => bail!("unrecognized role {:?}", s),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Role::Viewer => "viewer",
+            Role::Organiser => "organiser",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+/// What the watch command did with a results file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DbEnum)]
+pub enum ImportOutcome {
+    Imported,
+    /// The file replaced a stored race with the same id.
+    Replaced,
+    /// The file has the same results as the stored race.
+    Unchanged,
+    Failed,
+}
+
+impl fmt::Display for ImportOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            ImportOutcome::Imported => "imported",
+            ImportOutcome::Replaced => "replaced",
+            ImportOutcome::Unchanged => "unchanged",
+            ImportOutcome::Failed => "failed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "import_log"]
+pub(crate) struct NewImportLogEntry {
+    pub(crate) file: String,
+    pub(crate) race_id: Option<i32>,
+    pub(crate) outcome: ImportOutcome,
+    pub(crate) error: Option<String>,
+    pub(crate) logged_at: NaiveDateTime,
+}
+
+/// A stored API token. Only a hash of the token itself is kept.
+#[derive(Debug, Clone, Queryable)]
+pub struct ApiToken {
+    pub id: i32,
+    pub name: String,
+    pub role: Role,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "api_tokens"]
+pub(crate) struct NewApiToken {
+    pub(crate) name: String,
+    pub(crate) token_hash: String,
+    pub(crate) role: Role,
+    pub(crate) created_at: NaiveDateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extras() {
+        let extras = vec![
+            ("Team".to_string(), "Red".to_string()),
+            ("Car #".to_string(), "7".to_string()),
+        ];
+        let decoded = decode_extras(encode_extras(&extras).as_deref()).unwrap();
+        assert_eq!(decoded, [extras[1].clone(), extras[0].clone()]);
+        assert_eq!(encode_extras(&[]), None);
+        assert!(decode_extras(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn race_source_compression() {
+        let document = "<table id=\"scoreTable\"></table>".repeat(100);
+        let source = RaceSource::new(1, &document).unwrap();
+        assert!(source.document.len() < document.len() / 10);
+        assert_eq!(source.document().unwrap(), document);
+    }
+
+    #[test]
+    fn vehicle_names() {
+        let name = VehicleName::new("  Audi  R8\tLMS ");
+        assert_eq!(name.name, "Audi R8 LMS");
+        assert_eq!(name.normalized_name, "audi r8 lms");
+        assert_eq!(
+            VehicleName::new("AUDI R8 LMS").normalized_name,
+            name.normalized_name
+        );
+    }
+}
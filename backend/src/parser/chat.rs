@@ -0,0 +1,211 @@
+//! Plain-text chat logs, in which the server announces the results once a
+//! race is over. Lines may start with a `[HH:MM:SS]` timestamp, and other chat
+//! is ignored:
+//!
+//! ```text
+//! [20:14:03] *** Race finished: Sandpit, Laps: 5
+//! [20:14:03] *** 1. Some Driver [Formula] 05:12.345 (best 01:01.234)
+//! [20:14:03] *** 2. Other [Formula] +00:01.000 (best 01:01.500)
+//! [20:14:04] *** 3. Third [Formula] DNF (lap 3)
+//! [20:14:09] Other: gg
+//! ```
+
+use super::{
+    parse_time, Entrant, Field, Format, ParseError, ParseOptions, Race, Reason, Times, TIME,
+};
+
+const SERVER: &str = "*** ";
+const FINISHED: &str = "Race finished: ";
+const EXAMPLE: &str = "2. Name [Vehicle] 01:23.456 (best 01:01.234)";
+
+pub(super) struct ChatLog;
+
+impl Format for ChatLog {
+    fn name(&self) -> &'static str {
+        "chat"
+    }
+
+    fn detect(&self, text: &str) -> bool {
+        text.lines()
+            .filter_map(server_message)
+            .any(|message| message.starts_with(FINISHED))
+    }
+
+    /// Parses the results announced after the first `Race finished` line.
+    fn parse(&self, text: &str, options: &ParseOptions) -> Result<Race, ParseError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| Some((index + 1, server_message(line)?)))
+            .skip_while(|(_, message)| !message.starts_with(FINISHED));
+
+        let (_, heading) = lines.next().ok_or(ParseError::MissingTable)?;
+        let heading = &heading[FINISHED.len()..];
+        // Track names may contain commas, but modes don't.
+        let (track, mode_str) = match heading.rfind(", ") {
+            Some(split) => (&heading[..split], &heading[split + 2..]),
+            None => return Err(ParseError::MissingHeading("mode")),
+        };
+        let mut race = Race::new(track.trim().to_string(), mode_str.trim())?;
+
+        let mut times = Times::default();
+        // The results end at the next message that isn't numbered.
+        let results = lines.take_while(|(_, message)| {
+            message
+                .split_once(". ")
+                .is_some_and(|(position, _)| position.chars().all(|c| c.is_ascii_digit()))
+        });
+        for (line, message) in results {
+            let entrant = parse_result(line, message, &mut times, options);
+            race.push_entrant(entrant, options)?;
+        }
+
+        race.check_time_order();
+        Ok(race)
+    }
+}
+
+/// The text of a server message, without its timestamp.
+fn server_message(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let line = match line.strip_prefix('[') {
+        Some(rest) => rest.split_once("] ")?.1,
+        None => line,
+    };
+    line.strip_prefix(SERVER)
+}
+
+/// Parses a result such as `2. Other [Formula] +00:01.000 (best 01:01.500)`.
+fn parse_result(
+    line: usize,
+    message: &str,
+    times: &mut Times,
+    options: &ParseOptions,
+) -> Result<Entrant, ParseError> {
+    let malformed = || ParseError::Malformed {
+        line,
+        message: format!(
+            "expected a result such as {:?}, found {:?}",
+            EXAMPLE, message
+        ),
+    };
+    let invalid =
+        |column: &str, text: &str, field: Field, expected: &'static str| ParseError::InvalidCell {
+            row: line,
+            column: column.to_string(),
+            text: text.to_string(),
+            field,
+            expected,
+        };
+
+    let (position, rest) = message.split_once(". ").ok_or_else(malformed)?;
+    // Names may contain brackets, but vehicles don't.
+    let vehicle_start = rest.rfind(" [").ok_or_else(malformed)?;
+    let name = &rest[..vehicle_start];
+    let (vehicle, rest) = rest[vehicle_start + 2..]
+        .split_once(']')
+        .ok_or_else(malformed)?;
+    let mut notes = rest.split('(');
+    let time_str = notes.next().unwrap_or("").trim();
+
+    let mut reason = None;
+    let mut time = None;
+    match time_str {
+        "DNS" => reason = Some(Reason::Dns),
+        "DNF" => reason = Some(Reason::Dnf),
+        "DSQ" => reason = Some(Reason::Dsq),
+        _ => {
+            time = Some(
+                times
+                    .parse(time_str, options.gaps)
+                    .map_err(|expected| invalid("time", time_str, Field::Time, expected))?,
+            );
+        }
+    }
+    // Counted even if the rest of the result is invalid, as the `+` times
+    // after it are relative to it.
+    times.finished(time);
+
+    let mut best_lap = None;
+    let mut lap = None;
+    for note in notes {
+        let note = note.trim().trim_end_matches(')');
+        if let Some(best) = note.strip_prefix("best ") {
+            best_lap =
+                Some(parse_time(best).ok_or_else(|| invalid("best", best, Field::BestLap, TIME))?);
+        } else if let Some(number) = note.strip_prefix("lap ") {
+            lap = Some(
+                number
+                    .parse()
+                    .map_err(|_| invalid("lap", number, Field::Lap, "a number"))?,
+            );
+        } else {
+            return Err(malformed());
+        }
+    }
+
+    Ok(Entrant {
+        position: position
+            .parse()
+            .map_err(|_| invalid("position", position, Field::Position, "a number"))?,
+        name: name.trim().to_string(),
+        vehicle: vehicle.trim().to_string(),
+        time,
+        gap: times.gap(time),
+        best_lap,
+        lap,
+        reason,
+        ping: None,
+        fps: None,
+        fps_locked: false,
+        row_class: None,
+        name_class: None,
+        extras: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::detect_format;
+    use std::time::Duration;
+
+    const LOG: &str = "\
+[20:12:55] Some Driver: good luck
+[20:14:03] *** Race finished: Sandpit, Fields, Laps: 5
+[20:14:03] *** 1. Some Driver [Formula] 05:12.345 (best 01:01.234)
+[20:14:03] *** 2. [XYZ] Other [Formula] +00:01.000 (best 01:01.500)
+[20:14:04] *** 3. Third [Formula] DNF (lap 3)
+[20:14:05] *** Next race starts in 60 seconds
+[20:14:09] Other: gg
+";
+
+    #[test]
+    fn parse_log() {
+        assert_eq!(detect_format(LOG).map(|format| format.name()), Some("chat"));
+
+        let race = ChatLog.parse(LOG, &ParseOptions::default()).unwrap();
+        assert_eq!(race.track, "Sandpit, Fields");
+        assert_eq!(race.laps, Some(5));
+        let names: Vec<_> = race.entrants.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Some Driver", "[XYZ] Other", "Third"]);
+        assert_eq!(race.entrants[1].gap, Some(Duration::from_secs(1)));
+        assert_eq!(race.entrants[2].lap, Some(3));
+    }
+
+    #[test]
+    fn located_errors() {
+        let broken = LOG.replace("(lap 3)", "(lap three)");
+        let error = ChatLog
+            .parse(&broken, &ParseOptions::default())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::InvalidCell {
+                row: 5,
+                field: Field::Lap,
+                ..
+            }
+        ));
+    }
+}
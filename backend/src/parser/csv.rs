@@ -0,0 +1,171 @@
+//! The score table exported as CSV, with a record for each of its rows:
+//!
+//! ```text
+//! Sandpit
+//! Laps: 5
+//! ,Pos,Name,Vehicle,Time,BestLap,Lap,PING,FPS
+//! ,1,Some Driver,Formula,05:12.345,01:01.234,5,40,60
+//! ```
+
+use super::table::{Cell, Table};
+use super::{Format, ParseError, ParseOptions, Race, Times};
+
+pub(super) struct Csv;
+
+impl Format for Csv {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn detect(&self, text: &str) -> bool {
+        match records(text) {
+            Ok(records) => records.get(2).is_some_and(|headers| {
+                Table::new(headers.iter().map(|header| header.trim().to_string()))
+                    .has_required_columns()
+            }),
+            Err(_) => false,
+        }
+    }
+
+    fn parse(&self, text: &str, options: &ParseOptions) -> Result<Race, ParseError> {
+        let mut records = records(text)?.into_iter();
+
+        let mut heading = |what| {
+            records
+                .next()
+                .and_then(|record| record.into_iter().next())
+                .map(|field| field.trim().to_string())
+                .filter(|field| !field.is_empty())
+                .ok_or(ParseError::MissingHeading(what))
+        };
+
+        let track = heading("track")?;
+        let mut race = Race::new(track, &heading("mode")?)?;
+
+        let headers = records.next().ok_or(ParseError::MissingHeading("column"))?;
+        let table = Table::new(headers.into_iter().map(|header| header.trim().to_string()));
+
+        let mut times = Times::default();
+        // The three heading records come first.
+        for (index, record) in records.enumerate() {
+            let cells = record.iter().map(|field| Cell {
+                text: field,
+                class: None,
+            });
+            let entrant = table.parse_row(index + 3, cells, None, &mut times, options);
+            race.push_entrant(entrant, options)?;
+        }
+
+        race.check_time_order();
+        Ok(race)
+    }
+}
+
+/// Splits CSV into records of fields. Blank lines are left out.
+fn records(text: &str) -> Result<Vec<Vec<String>>, ParseError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    // The line that the current field started on.
+    let mut field_line = 1;
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => {
+                quoted = true;
+            }
+            '\n' if quoted => {
+                line += 1;
+                field.push('\n');
+            }
+            ',' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                field_line = line;
+            }
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                field_line = line;
+                record.push(std::mem::take(&mut field));
+                if record.len() == 1 && record[0].is_empty() {
+                    record.clear();
+                } else {
+                    records.push(std::mem::take(&mut record));
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(ParseError::Malformed {
+            line: field_line,
+            message: "unterminated quoted field".to_string(),
+        });
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{detect_format, Field};
+
+    const RESULTS: &str = "Sandpit\r\n\
+                           Laps: 5\r\n\
+                           ,Pos,Name,Vehicle,Time,BestLap,Lap,PING,FPS\r\n\
+                           *,1,\"Driver, The\",Formula,05:12.345,01:01.234,5,40,60\r\n\
+                           ,2,Other,Formula,+00:01.000,01:01.500,5,80,30\r\n\
+                           ,3,Third,Formula,DNF,,-,120,60\r\n";
+
+    #[test]
+    fn parse_table() {
+        assert_eq!(
+            detect_format(RESULTS).map(|format| format.name()),
+            Some("csv")
+        );
+
+        let race = Csv.parse(RESULTS, &ParseOptions::default()).unwrap();
+        assert_eq!(race.track, "Sandpit");
+        assert_eq!(race.laps, Some(5));
+        let names: Vec<_> = race.entrants.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Driver, The", "Other", "Third"]);
+        assert!(race.entrants[0].fps_locked);
+        assert_eq!(
+            race.entrants[1].gap,
+            Some(std::time::Duration::from_secs(1))
+        );
+        assert_eq!(race.entrants[2].lap, None);
+    }
+
+    #[test]
+    fn located_errors() {
+        let broken = RESULTS.replace("80,30", "80,thirty");
+        match Csv.parse(&broken, &ParseOptions::default()) {
+            Err(ParseError::InvalidCell { row, field, .. }) => {
+                assert_eq!((row, field), (4, Field::Fps));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let unterminated = RESULTS.replace("Other", "\"Other");
+        match records(&unterminated) {
+            Err(ParseError::Malformed { line, .. }) => assert_eq!(line, 5),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Results pages with a `table#scoreTable`, as published on the results
+//! website.
+
+use super::table::{Cell, Table};
+use super::{Format, ParseError, ParseOptions, Race, Times};
+use select::document::Document;
+use select::predicate::{Attr, Name, Predicate};
+
+pub(super) struct ScoreTable;
+
+impl Format for ScoreTable {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn detect(&self, text: &str) -> bool {
+        text.trim_start().starts_with('<')
+    }
+
+    fn parse(&self, text: &str, options: &ParseOptions) -> Result<Race, ParseError> {
+        let document = Document::from(text);
+
+        let score_table = document
+            .find(Name("table").and(Attr("id", "scoreTable")))
+            .next()
+            .ok_or(ParseError::MissingTable)?;
+
+        let mut rows = score_table.find(Name("tr"));
+
+        let mut heading = |what| {
+            rows.next()
+                .and_then(|tr| tr.find(Name("th")).next())
+                .and_then(|th| th.first_child())
+                .and_then(|text| text.as_text())
+                .map(|s| s.trim())
+                .ok_or(ParseError::MissingHeading(what))
+        };
+
+        let track = heading("track")?.to_string();
+        let mut race = Race::new(track, heading("mode")?)?;
+
+        let heading = rows.next().ok_or(ParseError::MissingHeading("column"))?;
+        let table = Table::new(
+            heading
+                .find(Name("th"))
+                .map(|th| th.text().trim().to_string()),
+        );
+
+        let mut times = Times::default();
+        // The three heading rows come first.
+        for (index, row) in rows.enumerate() {
+            let cells = row.find(Name("td")).map(|td| Cell {
+                text: td
+                    .first_child()
+                    .and_then(|text| text.as_text())
+                    .unwrap_or(""),
+                class: td.attr("class"),
+            });
+            let entrant = table.parse_row(index + 3, cells, row.attr("class"), &mut times, options);
+            race.push_entrant(entrant, options)?;
+        }
+
+        race.check_time_order();
+        Ok(race)
+    }
+}
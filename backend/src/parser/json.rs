@@ -0,0 +1,278 @@
+//! Results written by the game server plugin. Times are in milliseconds, and
+//! keys of a result that aren't known are kept as extras:
+//!
+//! ```text
+//! {
+//!   "track": "Sandpit",
+//!   "mode": "laps",
+//!   "laps": 5,
+//!   "results": [
+//!     {"position": 1, "name": "Some Driver", "vehicle": "Formula", "time": 312345,
+//!      "bestLap": 61234, "lap": 5, "ping": 40, "fps": 60, "fpsLocked": false},
+//!     {"position": 2, "name": "Other", "vehicle": "Formula", "status": "dnf", "lap": 3}
+//!   ]
+//! }
+//! ```
+
+use super::{Entrant, Field, Format, Mode, ParseError, ParseOptions, Race, Reason, Times};
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+pub(super) struct Json;
+
+impl Format for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn detect(&self, text: &str) -> bool {
+        text.trim_start().starts_with('{')
+    }
+
+    fn parse(&self, text: &str, options: &ParseOptions) -> Result<Race, ParseError> {
+        let document: Value = serde_json::from_str(text).map_err(|err| ParseError::Malformed {
+            line: err.line(),
+            message: err.to_string(),
+        })?;
+
+        let string = |key: &'static str| {
+            document
+                .get(key)
+                .and_then(Value::as_str)
+                .ok_or(ParseError::MissingHeading(key))
+        };
+        let number = |key: &str| {
+            document
+                .get(key)
+                .and_then(Value::as_i64)
+                .and_then(|n| i32::try_from(n).ok())
+        };
+
+        let track = string("track")?.to_string();
+        let mode_str = string("mode")?;
+        let mode = match mode_str {
+            "laps" => Mode::Laps,
+            "minutes" => Mode::Minutes,
+            "rally" => Mode::Rally,
+            "point-to-point" => Mode::PointToPoint,
+            _ => return Err(ParseError::UnrecognizedMode(mode_str.to_string())),
+        };
+        let mut race = Race {
+            track,
+            mode,
+            laps: number("laps"),
+            minutes: number("minutes"),
+            entrants: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        let results = document
+            .get("results")
+            .and_then(Value::as_array)
+            .ok_or(ParseError::MissingTable)?;
+        let mut times = Times::default();
+        let empty = Map::new();
+        for (index, result) in results.iter().enumerate() {
+            let result = result.as_object().unwrap_or(&empty);
+            let entrant = parse_result(index, result, &mut times);
+            race.push_entrant(entrant, options)?;
+        }
+
+        race.check_time_order();
+        Ok(race)
+    }
+}
+
+fn parse_result(
+    index: usize,
+    result: &Map<String, Value>,
+    times: &mut Times,
+) -> Result<Entrant, ParseError> {
+    let invalid =
+        |key: &str, value: &Value, field: Field, expected: &'static str| ParseError::InvalidCell {
+            row: index,
+            column: key.to_string(),
+            text: value.to_string(),
+            field,
+            expected,
+        };
+    let number = |key: &str, field: Field| -> Result<Option<i32>, ParseError> {
+        match result.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => match value.as_i64().map(i32::try_from) {
+                Some(Ok(n)) => Ok(Some(n)),
+                _ => Err(invalid(key, value, field, "a number")),
+            },
+        }
+    };
+    let millis = |key: &str, field: Field| -> Result<Option<Duration>, ParseError> {
+        match result.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => match value.as_u64().filter(|&ms| ms <= i32::MAX as u64) {
+                Some(ms) => Ok(Some(Duration::from_millis(ms))),
+                None => Err(invalid(key, value, field, "a time in milliseconds")),
+            },
+        }
+    };
+    let string = |key: &str, field: Field| -> Result<String, ParseError> {
+        match result.get(key) {
+            None | Some(Value::Null) => Err(ParseError::MissingField { row: index, field }),
+            Some(Value::String(s)) => Ok(s.clone()),
+            Some(value) => Err(invalid(key, value, field, "a string")),
+        }
+    };
+
+    // The time is counted before anything else can fail, as the gaps of the
+    // finishers after a skipped leader are still relative to their time.
+    let time = millis("time", Field::Time)?;
+    times.finished(time);
+
+    let reason = match result.get("status") {
+        None | Some(Value::Null) => None,
+        Some(value) => match value.as_str() {
+            Some("finished") => None,
+            Some("dns") => Some(Reason::Dns),
+            Some("dnf") => Some(Reason::Dnf),
+            Some("dsq") => Some(Reason::Dsq),
+            _ => {
+                return Err(invalid(
+                    "status",
+                    value,
+                    Field::Time,
+                    "one of finished, dns, dnf or dsq",
+                ))
+            }
+        },
+    };
+    let fps_locked = match result.get("fpsLocked") {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(locked)) => *locked,
+        Some(value) => return Err(invalid("fpsLocked", value, Field::FpsLocked, "a boolean")),
+    };
+
+    Ok(Entrant {
+        position: number("position", Field::Position)?.ok_or(ParseError::MissingField {
+            row: index,
+            field: Field::Position,
+        })?,
+        name: string("name", Field::Name)?,
+        vehicle: string("vehicle", Field::Vehicle)?,
+        time,
+        gap: times.gap(time),
+        best_lap: millis("bestLap", Field::BestLap)?,
+        lap: number("lap", Field::Lap)?,
+        reason,
+        ping: number("ping", Field::Ping)?,
+        fps: number("fps", Field::Fps)?,
+        fps_locked,
+        row_class: None,
+        name_class: None,
+        extras: result
+            .iter()
+            .filter(|(key, _)| !KNOWN_KEYS.contains(&key.as_str()))
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+    })
+}
+
+const KNOWN_KEYS: &[&str] = &[
+    "position",
+    "name",
+    "vehicle",
+    "time",
+    "bestLap",
+    "lap",
+    "status",
+    "ping",
+    "fps",
+    "fpsLocked",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::detect_format;
+
+    const RESULTS: &str = r#"{
+        "track": "Sandpit",
+        "mode": "minutes",
+        "minutes": 10,
+        "results": [
+            {"position": 1, "name": "Some Driver", "vehicle": "Formula", "time": 612345,
+             "bestLap": 61234, "lap": 9, "ping": 40, "fps": 60, "fpsLocked": true,
+             "team": "Red"},
+            {"position": 2, "name": "Other", "vehicle": "Formula", "time": 613345, "lap": 9},
+            {"position": 3, "name": "Third", "vehicle": "Formula", "status": "dsq", "lap": 2}
+        ]
+    }"#;
+
+    #[test]
+    fn parse_results() {
+        assert_eq!(
+            detect_format(RESULTS).map(|format| format.name()),
+            Some("json")
+        );
+
+        let race = Json.parse(RESULTS, &ParseOptions::default()).unwrap();
+        assert_eq!(race.minutes, Some(10));
+        assert_eq!(race.entrants.len(), 3);
+        assert!(race.entrants[0].fps_locked);
+        assert_eq!(
+            race.entrants[0].extras,
+            [("team".to_string(), "Red".to_string())]
+        );
+        assert_eq!(race.entrants[1].gap, Some(Duration::from_secs(1)));
+        assert!(matches!(race.entrants[2].reason, Some(Reason::Dsq)));
+    }
+
+    #[test]
+    fn located_errors() {
+        let broken = RESULTS.replace("\"lap\": 2", "\"lap\": \"two\"");
+        let error = Json.parse(&broken, &ParseOptions::default()).unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::InvalidCell {
+                row: 2,
+                field: Field::Lap,
+                ..
+            }
+        ));
+
+        let overflowing = RESULTS.replace("\"ping\": 40", "\"ping\": 4294967336");
+        let error = Json
+            .parse(&overflowing, &ParseOptions::default())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::InvalidCell {
+                row: 0,
+                field: Field::Ping,
+                ..
+            }
+        ));
+
+        let too_long = RESULTS.replace("\"time\": 613345", "\"time\": 2147483648");
+        let error = Json.parse(&too_long, &ParseOptions::default()).unwrap_err();
+        assert!(matches!(
+            error,
+            ParseError::InvalidCell {
+                row: 1,
+                field: Field::Time,
+                ..
+            }
+        ));
+
+        let malformed = RESULTS.replace("\"minutes\": 10,", "\"minutes\": 10,,");
+        let error = Json
+            .parse(&malformed, &ParseOptions::default())
+            .unwrap_err();
+        assert!(matches!(error, ParseError::Malformed { line: 4, .. }));
+    }
+}
@@ -0,0 +1,759 @@
+mod chat;
+mod csv;
+mod html;
+mod json;
+mod table;
+
+use crate::db::DbConnection;
+use crate::diff::{RaceDiff, RaceSnapshot};
+use crate::events::{Broadcaster, Event, TrackRecord};
+use crate::model;
+use crate::ratings;
+use crate::AddMode;
+use anyhow::{bail, Context};
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use scan_fmt::scan_fmt;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub gaps: GapMode,
+    /// Skip rows that can't be parsed, with a warning for each, instead of
+    /// rejecting the whole document.
+    pub lenient: bool,
+}
+
+/// What a `+` time in the results table is relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, juniper::GraphQLEnum)]
+pub enum GapMode {
+    /// The time of the previous finisher.
+    Interval,
+    /// The time of the winner.
+    Leader,
+}
+
+impl Default for GapMode {
+    fn default() -> GapMode {
+        GapMode::Interval
+    }
+}
+
+impl FromStr for GapMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<GapMode> {
+        match s {
+            "interval" => Ok(GapMode::Interval),
+            "leader" => Ok(GapMode::Leader),
+            _ => bail!("unrecognized gap mode {:?}", s),
+        }
+    }
+}
+
+/// A column of the results table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The unnamed column marking entrants with a locked frame rate.
+    FpsLocked,
+    Position,
+    Name,
+    Vehicle,
+    Time,
+    BestLap,
+    Lap,
+    Ping,
+    Fps,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Field::FpsLocked => "fps lock",
+            Field::Position => "position",
+            Field::Name => "name",
+            Field::Vehicle => "vehicle",
+            Field::Time => "time",
+            Field::BestLap => "best lap",
+            Field::Lap => "lap",
+            Field::Ping => "ping",
+            Field::Fps => "fps",
+        })
+    }
+}
+
+/// Why a results document could not be parsed.
+///
+/// Rows are counted from 0 among all rows of the score table in HTML and all
+/// records in CSV, so the first entrant is in row 3. In chat logs, the row is
+/// the line, counted from 1. In JSON, it is the index of the entrant's result,
+/// and the column its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The document doesn't look like any of the supported formats.
+    UnknownFormat,
+    /// The document is not well-formed in its format, such as JSON with a
+    /// syntax error.
+    Malformed {
+        /// The line of the document, counted from 1.
+        line: usize,
+        message: String,
+    },
+    MissingTable,
+    /// The track, mode or column heading row is missing.
+    MissingHeading(&'static str),
+    UnrecognizedMode(String),
+    InvalidCell {
+        row: usize,
+        /// The header of the cell's column.
+        column: String,
+        text: String,
+        field: Field,
+        /// What the cell should have contained.
+        expected: &'static str,
+    },
+    /// A row has no cell for a field that every entrant needs.
+    MissingField {
+        row: usize,
+        field: Field,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownFormat => write!(f, "unknown results format"),
+            ParseError::Malformed { line, message } => write!(f, "line {}: {}", line, message),
+            ParseError::MissingTable => write!(f, "results table not found in race result"),
+            ParseError::MissingHeading(what) => write!(f, "{} heading not found", what),
+            ParseError::UnrecognizedMode(mode) => write!(f, "unrecognized mode string {:?}", mode),
+            ParseError::InvalidCell {
+                row,
+                column,
+                text,
+                field,
+                expected,
+            } => write!(
+                f,
+                "row {}, column {:?}: expected {} as the {}, found {:?}",
+                row, column, expected, field, text
+            ),
+            ParseError::MissingField { row, field } => write!(f, "row {}: {} required", row, field),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A problem found in a results document that does not prevent it from being
+/// imported.
+#[derive(Debug, Clone)]
+pub enum Warning {
+    /// The total time of the entrant in this position is lower than that of
+    /// the finisher ahead of them.
+    TimeOrder { position: i32 },
+    /// A row that could not be parsed was left out, in lenient mode.
+    SkippedRow(ParseError),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::TimeOrder { position } => write!(
+                f,
+                "position {} has a lower total time than the finisher ahead",
+                position
+            ),
+            Warning::SkippedRow(error) => write!(f, "skipped {}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Race {
+    pub(crate) track: String,
+    pub(crate) mode: Mode,
+    pub(crate) laps: Option<i32>,
+    pub(crate) minutes: Option<i32>,
+    pub(crate) entrants: Vec<Entrant>,
+    pub(crate) warnings: Vec<Warning>,
+}
+
+impl Race {
+    /// A race without entrants yet, with the mode and its length read from a
+    /// mode heading such as `Laps: 5`.
+    pub(crate) fn new(track: String, mode_str: &str) -> Result<Race, ParseError> {
+        let mut race = Race {
+            track,
+            mode: Mode::Rally,
+            laps: None,
+            minutes: None,
+            entrants: Vec::new(),
+            warnings: Vec::new(),
+        };
+        if let Ok(laps) = scan_fmt!(mode_str, "Laps: {}", i32) {
+            race.mode = Mode::Laps;
+            race.laps = Some(laps);
+        } else if let Ok(minutes) = scan_fmt!(mode_str, "Minutes: {}", i32) {
+            race.mode = Mode::Minutes;
+            race.minutes = Some(minutes);
+        } else if mode_str == "Rally" {
+            race.mode = Mode::Rally;
+        } else if mode_str == "Point-to-Point" {
+            race.mode = Mode::PointToPoint;
+        } else {
+            return Err(ParseError::UnrecognizedMode(mode_str.to_string()));
+        }
+        Ok(race)
+    }
+
+    /// Adds the next entrant, or skips a row that could not be parsed with a
+    /// warning in lenient mode.
+    pub(crate) fn push_entrant(
+        &mut self,
+        entrant: Result<Entrant, ParseError>,
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        match entrant {
+            Ok(entrant) => self.entrants.push(entrant),
+            Err(error) if options.lenient => self.warnings.push(Warning::SkippedRow(error)),
+            Err(error) => return Err(error),
+        }
+        Ok(())
+    }
+
+    /// Warns about finishers whose total time is lower than that of the
+    /// finisher ahead of them.
+    pub(crate) fn check_time_order(&mut self) {
+        let mut previous_time = None;
+        for entrant in &self.entrants {
+            if let Some(time) = entrant.time {
+                if previous_time.map_or(false, |previous| time < previous) {
+                    self.warnings.push(Warning::TimeOrder {
+                        position: entrant.position,
+                    });
+                }
+                previous_time = Some(time);
+            }
+        }
+    }
+
+    /// Stores the race as `race_id`, and once it is committed, publishes the
+    /// race and any rating changes and track records that result from it.
+    ///
+    /// `source` is the results document the race was parsed from, which is
+    /// kept so that the race can be parsed again by `reparse`. Without one,
+    /// as for races entered as a list of entrants, any stored source of a
+    /// replaced race is removed.
+    pub(crate) fn insert_into(
+        self,
+        db: &DbConnection,
+        race_id: i32,
+        date: NaiveDate,
+        mode: AddMode,
+        source: Option<&str>,
+        events: &Broadcaster,
+    ) -> anyhow::Result<RaceDiff> {
+        let (diff, new_events) = with_conn!(db, |conn| conn.transaction(|| {
+            let mut stored = self.store(db, race_id, date, mode, source)?;
+            let (from_date, from_id) = stored.rated_from;
+            let rating_updates = ratings::update_from(db, from_date, from_id)?;
+            stored
+                .events
+                .extend(rating_updates.into_iter().map(Event::RatingChanged));
+            Ok::<_, anyhow::Error>((stored.diff, stored.events))
+        }))?;
+
+        for event in new_events {
+            events.publish(event);
+        }
+        Ok(diff)
+    }
+
+    /// Stores the race like `insert_into`, but leaves updating the ratings
+    /// and publishing the events to the caller, so that several races can
+    /// be stored before the ratings are updated once.
+    pub(crate) fn store(
+        self,
+        db: &DbConnection,
+        race_id: i32,
+        date: NaiveDate,
+        mode: AddMode,
+        source: Option<&str>,
+    ) -> anyhow::Result<StoredRace> {
+        with_conn!(db, |conn| conn.transaction(|| {
+            let old = RaceSnapshot::load(db, race_id)?;
+            if old.is_some() {
+                match mode {
+                    AddMode::Insert => bail!("race {} is already stored", race_id),
+                    AddMode::Replace => {
+                        use crate::schema::race_entrants::dsl::{self, race_entrants};
+                        diesel::delete(race_entrants.filter(dsl::race_id.eq(race_id)))
+                            .execute(conn)?;
+                    }
+                }
+            }
+
+            let new_race = model::Race {
+                id: race_id,
+                date,
+                track_id: model::TrackName { name: self.track }.get_or_insert(db)?,
+                laps: self.laps,
+                minutes: self.minutes,
+                mode: Some(self.mode.into()),
+            };
+            let track_id = new_race.track_id;
+            // The race row itself is updated rather than deleted, so that
+            // rows referring to it, such as championship rounds, are kept.
+            use crate::schema::races::dsl::races;
+            if old.is_some() {
+                diesel::update(races.find(race_id))
+                    .set(&new_race)
+                    .execute(conn)?;
+            } else {
+                new_race.insert_into(races).execute(conn)?;
+            }
+
+            use crate::schema::failed_sources::dsl::failed_sources;
+            use crate::schema::race_sources::dsl::race_sources;
+            diesel::delete(race_sources.find(race_id)).execute(conn)?;
+            diesel::delete(failed_sources.find(race_id)).execute(conn)?;
+            if let Some(source) = source {
+                model::RaceSource::new(race_id, source)?
+                    .insert_into(race_sources)
+                    .execute(conn)?;
+            }
+
+            let record = lap_record(db, track_id, race_id)?;
+            let mut fastest: Option<(i32, i32)> = None;
+            for entrant in self.entrants {
+                let new_driver = model::DriverName {
+                    name: entrant.name.clone(),
+                };
+                let driver_id = new_driver.get_or_insert(db)?;
+                let vehicle_id = model::VehicleName::new(&entrant.vehicle).get_or_insert(db)?;
+                let new_entrant = entrant.into_row(race_id, driver_id, vehicle_id)?;
+                if let Some(best_lap) = new_entrant.best_lap {
+                    let faster = match fastest {
+                        Some((fastest, _)) => best_lap < fastest,
+                        None => true,
+                    };
+                    if new_entrant.reason != Some(model::Reason::Dsq) && faster {
+                        fastest = Some((best_lap, driver_id));
+                    }
+                }
+                use crate::schema::race_entrants::dsl::race_entrants;
+                new_entrant.insert_into(race_entrants).execute(conn)?;
+            }
+
+            // A replaced race may have moved to a different date, so the ratings
+            // are updated from whichever of the two dates is earlier.
+            let old_start = old.as_ref().map(|old| (old.race().date, race_id));
+            let rated_from = old_start.map_or((date, race_id), |old| old.min((date, race_id)));
+
+            let mut new_events = vec![Event::RaceAdded {
+                race_id,
+                replaced: old.is_some(),
+            }];
+            if let Some((best_lap, driver_id)) = fastest {
+                let broken = match record {
+                    Some(record) => best_lap < record,
+                    None => true,
+                };
+                if broken {
+                    new_events.push(Event::TrackRecord(TrackRecord {
+                        track_id,
+                        race_id,
+                        driver_id,
+                        best_lap,
+                        previous_best_lap: record,
+                    }));
+                }
+            }
+
+            let new = RaceSnapshot::load(db, race_id)?.context("inserted race not found")?;
+            Ok(StoredRace {
+                diff: RaceDiff::new(old.as_ref(), &new),
+                rated_from,
+                events: new_events,
+            })
+        }))
+    }
+}
+
+/// A race written by `Race::store`.
+pub(crate) struct StoredRace {
+    pub(crate) diff: RaceDiff,
+    /// The date and id of the race that ratings have to be updated from.
+    pub(crate) rated_from: (NaiveDate, i32),
+    /// The events to publish once the race is committed, other than rating
+    /// changes.
+    pub(crate) events: Vec<Event>,
+}
+
+/// The fastest lap set on a track in any race but `except_race_id`. Laps from
+/// disqualified entrants don't count.
+fn lap_record(
+    db: &DbConnection,
+    track_id: i32,
+    except_race_id: i32,
+) -> anyhow::Result<Option<i32>> {
+    use crate::schema::{race_entrants, races};
+    let record = with_conn!(db, |conn| race_entrants::table
+        .inner_join(races::table)
+        .filter(races::track_id.eq(track_id))
+        .filter(races::id.ne(except_race_id))
+        .filter(
+            race_entrants::reason
+                .is_null()
+                .or(race_entrants::reason.ne(model::Reason::Dsq)),
+        )
+        .select(diesel::dsl::min(race_entrants::best_lap))
+        .first(conn))?;
+    Ok(record)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Entrant {
+    pub(crate) position: i32,
+    pub(crate) name: String,
+    pub(crate) vehicle: String,
+    pub(crate) time: Option<Duration>,
+    pub(crate) gap: Option<Duration>,
+    pub(crate) best_lap: Option<Duration>,
+    pub(crate) lap: Option<i32>,
+    pub(crate) reason: Option<Reason>,
+    pub(crate) ping: Option<i32>,
+    pub(crate) fps: Option<i32>,
+    pub(crate) fps_locked: bool,
+    /// The class of the entrant's row, such as `dnf`.
+    pub(crate) row_class: Option<String>,
+    /// The class of the cell with the entrant's name, such as `gt0`.
+    pub(crate) name_class: Option<String>,
+    /// The cells of columns that aren't parsed, by column header.
+    pub(crate) extras: Vec<(String, String)>,
+}
+
+impl Entrant {
+    /// The row that stores the entrant in race `race_id`, failing if one of
+    /// its times doesn't fit in a column.
+    pub(crate) fn into_row(
+        self,
+        race_id: i32,
+        driver_id: i32,
+        vehicle_id: i32,
+    ) -> anyhow::Result<model::RaceEntrant> {
+        let millis = |duration: Option<Duration>, what: &str| {
+            duration
+                .map(|duration| i32::try_from(duration.as_millis()))
+                .transpose()
+                .with_context(|| format!("{} of {} is too long", what, self.name))
+        };
+        Ok(model::RaceEntrant {
+            race_id,
+            driver_id,
+            position: Some(self.position),
+            vehicle_id: Some(vehicle_id),
+            time: millis(self.time, "time")?,
+            gap: millis(self.gap, "gap")?,
+            best_lap: millis(self.best_lap, "best lap")?,
+            lap: self.lap,
+            reason: self.reason.map(Into::into),
+            ping: self.ping,
+            fps: self.fps,
+            fps_locked: self.fps_locked,
+            entered_as: Some(self.name),
+            row_class: self.row_class,
+            name_class: self.name_class,
+            extras: model::encode_extras(&self.extras),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Mode {
+    Laps,
+    Minutes,
+    Rally,
+    PointToPoint,
+}
+
+impl From<Mode> for model::RaceMode {
+    fn from(mode: Mode) -> model::RaceMode {
+        match mode {
+            Mode::Laps => model::RaceMode::Laps,
+            Mode::Minutes => model::RaceMode::Minutes,
+            Mode::Rally => model::RaceMode::Rally,
+            Mode::PointToPoint => model::RaceMode::PointToPoint,
+        }
+    }
+}
+
+impl From<model::RaceMode> for Mode {
+    fn from(mode: model::RaceMode) -> Mode {
+        match mode {
+            model::RaceMode::Laps => Mode::Laps,
+            model::RaceMode::Minutes => Mode::Minutes,
+            model::RaceMode::Rally => Mode::Rally,
+            model::RaceMode::PointToPoint => Mode::PointToPoint,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Reason {
+    Dns,
+    Dnf,
+    Dsq,
+}
+
+impl From<Reason> for model::Reason {
+    fn from(reason: Reason) -> model::Reason {
+        match reason {
+            Reason::Dns => model::Reason::Dns,
+            Reason::Dnf => model::Reason::Dnf,
+            Reason::Dsq => model::Reason::Dsq,
+        }
+    }
+}
+
+impl From<model::Reason> for Reason {
+    fn from(reason: model::Reason) -> Reason {
+        match reason {
+            model::Reason::Dns => Reason::Dns,
+            model::Reason::Dnf => Reason::Dnf,
+            model::Reason::Dsq => Reason::Dsq,
+        }
+    }
+}
+
+/// A layout of results documents that can be parsed into a `Race`.
+pub(crate) trait Format: Sync {
+    /// A short name for the format, such as `html`.
+    fn name(&self) -> &'static str;
+
+    /// Whether `text` looks like a document in this format. Only the overall
+    /// shape is checked, so parsing can still fail.
+    fn detect(&self, text: &str) -> bool;
+
+    fn parse(&self, text: &str, options: &ParseOptions) -> Result<Race, ParseError>;
+}
+
+/// The supported formats, in the order they are tried by `detect_format`.
+pub(crate) static FORMATS: &[&dyn Format] =
+    &[&json::Json, &html::ScoreTable, &chat::ChatLog, &csv::Csv];
+
+/// The first format that `text` looks like.
+pub(crate) fn detect_format(text: &str) -> Option<&'static dyn Format> {
+    FORMATS.iter().copied().find(|format| format.detect(text))
+}
+
+/// Parses a results document in any of the supported formats.
+pub(crate) fn parse_race(text: &str, options: &ParseOptions) -> Result<Race, ParseError> {
+    detect_format(text)
+        .ok_or(ParseError::UnknownFormat)?
+        .parse(text, options)
+}
+
+/// The times of the finishers so far, which `+` times are relative to.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Times {
+    leader: Option<Duration>,
+    previous: Option<Duration>,
+}
+
+impl Times {
+    /// Parses a total time, or a `+` time after the leader or the previous
+    /// finisher. Returns what was expected instead if it can't be parsed.
+    pub(crate) fn parse(&self, time_str: &str, gaps: GapMode) -> Result<Duration, &'static str> {
+        match time_str.strip_prefix('+') {
+            Some(offset) => {
+                let offset = parse_time(offset).ok_or(TIME)?;
+                let base = match gaps {
+                    GapMode::Interval => self.previous,
+                    GapMode::Leader => self.leader,
+                };
+                Ok(base.ok_or(GAP_WITHOUT_TIME)? + offset)
+            }
+            None => parse_time(time_str).ok_or(TIME),
+        }
+    }
+
+    /// The gap from the leader to `time`. The first finisher is the leader.
+    pub(crate) fn gap(&self, time: Option<Duration>) -> Option<Duration> {
+        time.and_then(|time| time.checked_sub(self.leader.unwrap_or(time)))
+    }
+
+    /// Counts the time of the next row. Rows count their time as soon as it
+    /// is parsed, even if they are skipped for another invalid cell later.
+    fn finished(&mut self, time: Option<Duration>) {
+        if let Some(time) = time {
+            self.leader = self.leader.or(Some(time));
+            self.previous = Some(time);
+        }
+    }
+}
+
+const TIME: &str = "a time such as 1:23.456";
+const GAP_WITHOUT_TIME: &str = "a time before the first gap";
+
+fn parse_time(time_str: &str) -> Option<Duration> {
+    if let Ok((minutes, seconds, millis)) = scan_fmt!(time_str, "{}:{}.{}", u64, u64, u64) {
+        Some(Duration::from_millis(
+            millis + 1000 * (seconds + 60 * minutes),
+        ))
+    } else if let Ok((hours, minutes, seconds, millis)) =
+        scan_fmt!(time_str, "{}:{}:{}.{}", u64, u64, u64, u64)
+    {
+        Some(Duration::from_millis(
+            millis + 1000 * (seconds + 60 * (minutes + 60 * hours)),
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_events() {
+        use std::io::Read;
+
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(
+            &include_bytes!("../samples/EventResult.tar.gz")[..],
+        ));
+
+        let mut contents = String::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            eprintln!("{:?}", entry.path().unwrap());
+            contents.clear();
+            entry.read_to_string(&mut contents).unwrap();
+            let _parsed = parse_race(&contents, &ParseOptions::default()).unwrap();
+        }
+    }
+
+    #[test]
+    fn unknown_format() {
+        let error = parse_race("no results here", &ParseOptions::default()).unwrap_err();
+        assert_eq!(error, ParseError::UnknownFormat);
+    }
+
+    fn sample(path: &str) -> String {
+        use std::io::Read;
+
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(
+            &include_bytes!("../samples/EventResult.tar.gz")[..],
+        ));
+        let mut entry = archive
+            .entries()
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|entry| entry.path().unwrap().to_str() == Some(path))
+            .unwrap();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn gap_modes() {
+        let contents = sample("EventResult/2020/03/07/23343.html");
+
+        let interval = parse_race(&contents, &ParseOptions::default()).unwrap();
+        assert!(interval.warnings.is_empty());
+        let times: Vec<_> = interval.entrants.iter().map(|e| e.time).collect();
+        let gaps: Vec<_> = interval.entrants.iter().map(|e| e.gap).collect();
+        assert_eq!(times[1], Some(parse_time("01:31:10.816").unwrap()));
+        assert_eq!(gaps[0], Some(Duration::from_millis(0)));
+        assert_eq!(gaps[2], Some(parse_time("01:12.633").unwrap()));
+
+        let leader = parse_race(
+            &contents,
+            &ParseOptions {
+                gaps: GapMode::Leader,
+                ..ParseOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(!leader.warnings.is_empty());
+    }
+
+    #[test]
+    fn located_errors() {
+        let contents = sample("EventResult/2020/03/07/23343.html");
+        let broken = contents.replace("+00:56.256", "+soon");
+
+        let error = parse_race(&broken, &ParseOptions::default()).unwrap_err();
+        let expected = ParseError::InvalidCell {
+            row: 4,
+            column: "Time".to_string(),
+            text: "+soon".to_string(),
+            field: Field::Time,
+            expected: TIME,
+        };
+        assert_eq!(error, expected);
+
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+        let lenient = parse_race(&broken, &options).unwrap();
+        let strict = parse_race(&contents, &ParseOptions::default()).unwrap();
+        assert_eq!(lenient.entrants.len(), strict.entrants.len() - 1);
+        assert!(matches!(&lenient.warnings[0], Warning::SkippedRow(error) if *error == expected));
+    }
+
+    #[test]
+    fn skipped_rows_keep_times() {
+        let contents = sample("EventResult/2020/03/07/23343.html");
+        // The PING of the second finisher, whose time the next `+` time is
+        // relative to.
+        let broken = contents.replacen(
+            "<td class=\"mono\">153</td>",
+            "<td class=\"mono\">slow</td>",
+            1,
+        );
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+
+        let lenient = parse_race(&broken, &options).unwrap();
+        let strict = parse_race(&contents, &ParseOptions::default()).unwrap();
+        assert!(matches!(
+            &lenient.warnings[0],
+            Warning::SkippedRow(ParseError::InvalidCell {
+                row: 4,
+                field: Field::Ping,
+                ..
+            })
+        ));
+        assert_eq!(lenient.entrants[1].name, "StoryVS");
+        assert_eq!(lenient.entrants[1].time, strict.entrants[2].time);
+        assert_eq!(lenient.entrants[1].gap, strict.entrants[2].gap);
+    }
+
+    #[test]
+    fn classes_and_extras() {
+        let contents = sample("EventResult/2020/01/25/20632.html")
+            .replace("<th>FPS</th>", "<th>FPS</th><th>Team</th>")
+            .replace("</tr>", "<td>Red</td></tr>");
+
+        let race = parse_race(&contents, &ParseOptions::default()).unwrap();
+        let entrant = race.entrants.iter().find(|e| e.name == "Ctski").unwrap();
+        assert_eq!(entrant.row_class.as_deref(), Some("dnf"));
+        assert_eq!(entrant.name_class.as_deref(), Some("gt0"));
+        assert_eq!(entrant.extras, [("Team".to_string(), "Red".to_string())]);
+        assert_eq!(race.entrants[0].row_class, None);
+    }
+}
@@ -0,0 +1,189 @@
+//! The layout shared by the formats that lay out a race as a table: a track
+//! heading, a mode heading and a row of column headers, followed by a row per
+//! entrant.
+
+use super::{parse_time, Entrant, Field, ParseError, ParseOptions, Reason, Times, TIME};
+
+/// A cell of an entrant's row.
+pub(super) struct Cell<'a> {
+    pub(super) text: &'a str,
+    pub(super) class: Option<&'a str>,
+}
+
+/// The columns of a table.
+pub(super) struct Table {
+    columns: Vec<(Option<Field>, String)>,
+}
+
+impl Table {
+    pub(super) fn new<I>(headers: I) -> Table
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let columns = headers
+            .into_iter()
+            .map(|header| {
+                let field = match header.as_str() {
+                    "" => Some(Field::FpsLocked),
+                    "Pos" => Some(Field::Position),
+                    "Name" => Some(Field::Name),
+                    "Vehicle" => Some(Field::Vehicle),
+                    "Time" => Some(Field::Time),
+                    "BestLap" => Some(Field::BestLap),
+                    "Lap" => Some(Field::Lap),
+                    "PING" => Some(Field::Ping),
+                    "FPS" => Some(Field::Fps),
+                    _ => None,
+                };
+                (field, header)
+            })
+            .collect();
+        Table { columns }
+    }
+
+    /// Whether the table has the columns that every entrant needs, for
+    /// telling a table apart from other documents.
+    pub(super) fn has_required_columns(&self) -> bool {
+        [Field::Position, Field::Name, Field::Vehicle]
+            .iter()
+            .all(|required| {
+                self.columns
+                    .iter()
+                    .any(|(field, _)| field == &Some(*required))
+            })
+    }
+
+    /// Parses the cells of row `row_index`, whose `+` times are relative to
+    /// `times`, and counts its time.
+    pub(super) fn parse_row<'a, I>(
+        &self,
+        row_index: usize,
+        cells: I,
+        row_class: Option<&str>,
+        times: &mut Times,
+        options: &ParseOptions,
+    ) -> Result<Entrant, ParseError>
+    where
+        I: IntoIterator<Item = Cell<'a>>,
+    {
+        let cells: Vec<_> = cells
+            .into_iter()
+            .zip(&self.columns)
+            .map(|(cell, (field, header))| (cell.text.trim(), cell.class, *field, header))
+            .collect();
+        let invalid_cell = |text: &str, field, header: &String, expected| ParseError::InvalidCell {
+            row: row_index,
+            column: header.clone(),
+            text: text.to_string(),
+            field,
+            expected,
+        };
+
+        // The time is parsed and counted before the other cells, so that the
+        // `+` times of the next rows stay right if one of them is invalid.
+        let mut reason = None;
+        let mut time = None;
+        for &(text, _, field, header) in &cells {
+            if field != Some(Field::Time) {
+                continue;
+            }
+            match text {
+                "DNS" => {
+                    reason = Some(Reason::Dns);
+                }
+                "DNF" => {
+                    reason = Some(Reason::Dnf);
+                }
+                "DSQ" => {
+                    reason = Some(Reason::Dsq);
+                }
+                "" => {}
+                time_str => {
+                    time =
+                        Some(times.parse(time_str, options.gaps).map_err(|expected| {
+                            invalid_cell(text, Field::Time, header, expected)
+                        })?);
+                }
+            }
+        }
+        let gap = times.gap(time);
+        times.finished(time);
+
+        let mut fps_locked = false;
+        let mut position = None;
+        let mut name = None;
+        let mut vehicle = None;
+        let mut best_lap = None;
+        let mut lap = None;
+        let mut fps = None;
+        let mut ping = None;
+        let mut name_class = None;
+        let mut extras = Vec::new();
+
+        for (text, class, field, header) in cells {
+            let field = match field {
+                Some(field) => field,
+                None => {
+                    extras.push((header.clone(), text.to_string()));
+                    continue;
+                }
+            };
+            let invalid = |expected| invalid_cell(text, field, header, expected);
+            let number = || text.parse::<i32>().map_err(|_| invalid("a number"));
+
+            match field {
+                Field::FpsLocked => {
+                    fps_locked = text == "*";
+                }
+                Field::Position => {
+                    position = Some(number()?);
+                }
+                Field::Name => {
+                    name = Some(text.to_string());
+                    name_class = class.map(str::to_string);
+                }
+                Field::Vehicle => {
+                    vehicle = Some(text.to_string());
+                }
+                Field::Time => {}
+                Field::BestLap => {
+                    if !text.is_empty() {
+                        best_lap = Some(parse_time(text).ok_or_else(|| invalid(TIME))?);
+                    }
+                }
+                Field::Lap => {
+                    if text != "-" {
+                        lap = Some(number()?);
+                    }
+                }
+                Field::Ping => {
+                    ping = Some(number()?);
+                }
+                Field::Fps => {
+                    fps = Some(number()?);
+                }
+            }
+        }
+
+        let missing = |field| ParseError::MissingField {
+            row: row_index,
+            field,
+        };
+        Ok(Entrant {
+            position: position.ok_or_else(|| missing(Field::Position))?,
+            name: name.ok_or_else(|| missing(Field::Name))?,
+            vehicle: vehicle.ok_or_else(|| missing(Field::Vehicle))?,
+            time,
+            gap,
+            best_lap,
+            lap,
+            reason,
+            ping,
+            fps,
+            fps_locked,
+            row_class: row_class.map(str::to_string),
+            name_class,
+            extras,
+        })
+    }
+}
@@ -0,0 +1,251 @@
+use crate::db::DbConnection;
+use crate::events::RatingUpdate;
+use crate::model::{DriverRating, Reason};
+use crate::schema::{driver_ratings, race_entrants, races};
+use chrono::naive::NaiveDate;
+use diesel::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// The rating of a driver before their first race.
+pub(crate) const INITIAL_RATING: f64 = 1500.0;
+
+/// How much a rating can change in a single race.
+const K_FACTOR: f64 = 32.0;
+
+/// Where an entrant ended up, for the purpose of rating and comparing drivers.
+/// Lower is better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Placing {
+    Finished(i32),
+    /// Did not finish or was disqualified. Ranked below every finisher and
+    /// level with each other.
+    Retired,
+}
+
+impl Placing {
+    /// Entrants that did not start are not rated at all.
+    pub(crate) fn new(position: Option<i32>, reason: Option<Reason>) -> Option<Placing> {
+        match reason {
+            Some(Reason::Dns) => None,
+            Some(Reason::Dnf) | Some(Reason::Dsq) => Some(Placing::Retired),
+            None => Some(Placing::Finished(position.unwrap_or(i32::MAX))),
+        }
+    }
+}
+
+/// Computes the rating changes of a race with a multiplayer Elo model: every
+/// pair of entrants is scored as a game between the two, and the sum is scaled
+/// so that a whole race moves a rating about as much as a single game.
+fn rate(field: &[(Placing, f64)]) -> Vec<f64> {
+    if field.len() < 2 {
+        return vec![0.0; field.len()];
+    }
+    let scale = K_FACTOR / (field.len() - 1) as f64;
+    field
+        .iter()
+        .map(|&(placing, rating)| {
+            let score: f64 = field
+                .iter()
+                .map(|&(other_placing, other_rating)| {
+                    let expected = 1.0 / (1.0 + 10f64.powf((other_rating - rating) / 400.0));
+                    let actual = match placing.cmp(&other_placing) {
+                        Ordering::Less => 1.0,
+                        Ordering::Equal => 0.5,
+                        Ordering::Greater => 0.0,
+                    };
+                    actual - expected
+                })
+                .sum();
+            scale * score
+        })
+        .collect()
+}
+
+/// Recomputes all ratings from scratch.
+pub(crate) fn rebuild(db: &DbConnection) -> anyhow::Result<()> {
+    recompute(db, None).map(drop)
+}
+
+/// Computes ratings if there are races but no ratings yet, as after the
+/// migration that added them to a database with races. Returns whether
+/// ratings were computed.
+pub(crate) fn backfill(db: &DbConnection) -> anyhow::Result<bool> {
+    use diesel::dsl::exists;
+
+    let rated: bool = with_conn!(db, |conn| diesel::select(exists(
+        driver_ratings::table.select(driver_ratings::race_id)
+    ))
+    .get_result(conn))?;
+    let raced: bool = with_conn!(db, |conn| diesel::select(exists(
+        race_entrants::table.select(race_entrants::race_id)
+    ))
+    .get_result(conn))?;
+    if rated || !raced {
+        return Ok(false);
+    }
+    rebuild(db)?;
+    Ok(true)
+}
+
+/// Recomputes the ratings of the race at `(date, id)` and every race after
+/// it, which is needed whenever a race is added, replaced or removed.
+/// Returns the drivers whose current rating changed.
+pub(crate) fn update_from(
+    db: &DbConnection,
+    date: NaiveDate,
+    id: i32,
+) -> anyhow::Result<Vec<RatingUpdate>> {
+    recompute(db, Some((date, id)))
+}
+
+fn recompute(
+    db: &DbConnection,
+    from: Option<(NaiveDate, i32)>,
+) -> anyhow::Result<Vec<RatingUpdate>> {
+    with_conn!(db, |conn| conn.transaction(|| {
+        // Races are rated in date order, with the id breaking ties.
+        let later = || {
+            let mut query = races::table.select(races::id).into_boxed();
+            if let Some((date, id)) = from {
+                query = query.filter(
+                    races::date
+                        .gt(date)
+                        .or(races::date.eq(date).and(races::id.ge(id))),
+                );
+            }
+            query
+        };
+
+        // The latest rating of each driver who entered one of the races.
+        let latest = || -> QueryResult<HashMap<i32, f64>> {
+            Ok(driver_ratings::table
+                .inner_join(races::table)
+                .filter(
+                    driver_ratings::driver_id.eq_any(
+                        race_entrants::table
+                            .select(race_entrants::driver_id)
+                            .filter(race_entrants::race_id.eq_any(later())),
+                    ),
+                )
+                .order((races::date, races::id))
+                .select((driver_ratings::driver_id, driver_ratings::rating))
+                .load::<(i32, f64)>(conn)?
+                .into_iter()
+                .collect())
+        };
+
+        let previous = latest()?;
+        diesel::delete(driver_ratings::table.filter(driver_ratings::race_id.eq_any(later())))
+            .execute(conn)?;
+        // With the later ratings gone, the latest remaining rating of each
+        // driver is the one they enter the first recomputed race with.
+        let mut current = latest()?;
+        let mut latest_race: HashMap<i32, i32> = HashMap::new();
+
+        let race_ids: Vec<i32> = later().order((races::date, races::id)).load(conn)?;
+        let mut fields: HashMap<i32, Vec<(i32, Placing)>> = HashMap::new();
+        let entrants: Vec<(i32, i32, Option<i32>, Option<Reason>)> = race_entrants::table
+            .select((
+                race_entrants::race_id,
+                race_entrants::driver_id,
+                race_entrants::position,
+                race_entrants::reason,
+            ))
+            .filter(race_entrants::race_id.eq_any(later()))
+            .load(conn)?;
+        for (race_id, driver_id, position, reason) in entrants {
+            if let Some(placing) = Placing::new(position, reason) {
+                fields
+                    .entry(race_id)
+                    .or_default()
+                    .push((driver_id, placing));
+            }
+        }
+
+        for race_id in race_ids {
+            let field = match fields.remove(&race_id) {
+                Some(field) if field.len() >= 2 => field,
+                _ => continue,
+            };
+            let ratings: Vec<(Placing, f64)> = field
+                .iter()
+                .map(|&(driver_id, placing)| {
+                    let rating = current.get(&driver_id).copied();
+                    (placing, rating.unwrap_or(INITIAL_RATING))
+                })
+                .collect();
+            let rows = field.iter().zip(&ratings).zip(rate(&ratings)).map(
+                |((&(driver_id, _), &(_, rating)), delta)| DriverRating {
+                    race_id,
+                    driver_id,
+                    rating: rating + delta,
+                    delta,
+                },
+            );
+            // SQLite doesn't support inserting several rows at once.
+            for row in rows {
+                current.insert(row.driver_id, row.rating);
+                latest_race.insert(row.driver_id, row.race_id);
+                row.insert_into(driver_ratings::table).execute(conn)?;
+            }
+        }
+
+        let mut updates: Vec<RatingUpdate> = latest_race
+            .into_iter()
+            .map(|(driver_id, race_id)| RatingUpdate {
+                driver_id,
+                race_id,
+                rating: current[&driver_id],
+                previous_rating: previous.get(&driver_id).copied(),
+            })
+            .filter(|update| match update.previous_rating {
+                Some(previous) => (update.rating - previous).abs() > 1e-9,
+                None => true,
+            })
+            .collect();
+        updates.sort_by_key(|update| update.driver_id);
+        Ok(updates)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placings() {
+        let deltas = rate(&[
+            (Placing::Finished(1), 1500.0),
+            (Placing::Finished(2), 1500.0),
+            (Placing::Retired, 1500.0),
+            (Placing::Retired, 1500.0),
+        ]);
+        assert!(deltas[0] > deltas[1]);
+        assert!(deltas[1] > 0.0);
+        assert!(deltas[2] < 0.0);
+        assert!((deltas[2] - deltas[3]).abs() < 1e-9);
+        assert!(deltas.iter().sum::<f64>().abs() < 1e-9);
+
+        assert_eq!(Placing::new(Some(3), Some(Reason::Dns)), None);
+        assert_eq!(
+            Placing::new(Some(3), Some(Reason::Dnf)),
+            Some(Placing::Retired)
+        );
+    }
+
+    #[test]
+    fn upsets() {
+        // Beating a much stronger driver gains more than beating an equal one.
+        let upset = rate(&[
+            (Placing::Finished(1), 1300.0),
+            (Placing::Finished(2), 1700.0),
+        ]);
+        let even = rate(&[
+            (Placing::Finished(1), 1500.0),
+            (Placing::Finished(2), 1500.0),
+        ]);
+        assert!(upset[0] > even[0]);
+        assert!((even[0] - K_FACTOR / 2.0).abs() < 1e-9);
+    }
+}
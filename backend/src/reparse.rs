@@ -0,0 +1,260 @@
+//! Parsing stored races again from the results documents they were imported
+//! from, so that parser fixes reach races imported before them, and trying
+//! the documents that failed to import again.
+
+use crate::db::DbConnection;
+use crate::diff::{RaceDiff, RaceSnapshot};
+use crate::events::{Broadcaster, Event};
+use crate::model::{FailedSource, RaceSource};
+use crate::parser::{detect_format, ParseError, ParseOptions, StoredRace};
+use crate::ratings;
+use crate::{AddMode, AddedRace};
+use anyhow::Context;
+use chrono::naive::NaiveDate;
+use chrono::Utc;
+use diesel::prelude::*;
+
+/// Parses the stored source of every race, and every document that failed to
+/// import, again, calling `f` with how each race would change, or why it
+/// couldn't be parsed. With `apply`, the races that change are replaced by
+/// the new results, and the ratings are updated once from the earliest of
+/// them.
+pub(crate) fn reparse<F>(
+    db: &DbConnection,
+    options: &ParseOptions,
+    events: &Broadcaster,
+    apply: bool,
+    mut f: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(i32, anyhow::Result<AddedRace>),
+{
+    use crate::schema::{failed_sources, race_sources, races};
+
+    let stored: Vec<(i32, NaiveDate)> = with_conn!(db, |conn| race_sources::table
+        .inner_join(races::table)
+        .select((race_sources::race_id, races::date))
+        .order(race_sources::race_id.asc())
+        .load(conn))?;
+    let failed: Vec<(i32, NaiveDate)> = with_conn!(db, |conn| failed_sources::table
+        .select((failed_sources::race_id, failed_sources::date))
+        .order(failed_sources::race_id.asc())
+        .load(conn))?;
+
+    let new_events = with_conn!(db, |conn| conn.transaction(|| {
+        let mut new_events = Vec::new();
+        let mut rated_from: Option<(NaiveDate, i32)> = None;
+        let mut reparse_one = |id, date, document: anyhow::Result<String>| {
+            let result = document.and_then(|document| {
+                let (added, stored) = reparse_race(db, id, date, &document, options, apply)?;
+                if let Some(stored) = stored {
+                    rated_from = Some(match rated_from {
+                        Some(from) => from.min(stored.rated_from),
+                        None => stored.rated_from,
+                    });
+                    new_events.extend(stored.events);
+                }
+                Ok(added)
+            });
+            f(id, result);
+        };
+
+        for (id, date) in stored {
+            let source: RaceSource =
+                with_conn!(db, |conn| race_sources::table.find(id).first(conn))?;
+            let document = source.document().context("stored source is corrupt");
+            reparse_one(id, date, document);
+        }
+        for (id, date) in failed {
+            let source: FailedSource =
+                with_conn!(db, |conn| failed_sources::table.find(id).first(conn))?;
+            let document = source.document().context("stored source is corrupt");
+            reparse_one(id, date, document);
+        }
+
+        if let Some((date, id)) = rated_from {
+            let rating_updates = ratings::update_from(db, date, id)?;
+            new_events.extend(rating_updates.into_iter().map(Event::RatingChanged));
+        }
+        Ok::<_, anyhow::Error>(new_events)
+    }))?;
+
+    for event in new_events {
+        events.publish(event);
+    }
+    Ok(())
+}
+
+/// Parses `document` as race `id`, and compares it to the stored race without
+/// writing anything. With `apply`, a race that changes is stored, leaving its
+/// ratings to be updated by the caller.
+fn reparse_race(
+    db: &DbConnection,
+    id: i32,
+    date: NaiveDate,
+    document: &str,
+    options: &ParseOptions,
+    apply: bool,
+) -> anyhow::Result<(AddedRace, Option<StoredRace>)> {
+    let format = detect_format(document).ok_or(ParseError::UnknownFormat)?;
+    let mut race = format.parse(document, options)?;
+    let warnings = std::mem::take(&mut race.warnings);
+
+    let old = RaceSnapshot::load(db, id)?;
+    let diff = RaceDiff::new(old.as_ref(), &RaceSnapshot::parsed(db, &race, id, date)?);
+    let stored = if apply && !diff.is_unchanged() {
+        let mode = if old.is_some() {
+            AddMode::Replace
+        } else {
+            AddMode::Insert
+        };
+        Some(race.store(db, id, date, mode, Some(document))?)
+    } else {
+        None
+    };
+    Ok((
+        AddedRace {
+            format: format.name(),
+            diff,
+            warnings,
+        },
+        stored,
+    ))
+}
+
+/// Keeps a results document for race `id` that failed to import, replacing
+/// any that failed before, so that `reparse` can try it again.
+pub(crate) fn store_failed(
+    db: &DbConnection,
+    id: i32,
+    date: NaiveDate,
+    document: &str,
+    error: &anyhow::Error,
+) -> anyhow::Result<()> {
+    use crate::schema::failed_sources::dsl::failed_sources;
+
+    let source = FailedSource::new(
+        id,
+        date,
+        document,
+        format!("{:#}", error),
+        Utc::now().naive_utc(),
+    )?;
+    with_conn!(db, |conn| conn.transaction(|| {
+        diesel::delete(failed_sources.find(id)).execute(conn)?;
+        source.insert_into(failed_sources).execute(conn)
+    }))?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::{DbPool, TestDb};
+    use crate::migrations;
+    use crate::schema::{driver_ratings, failed_sources, race_entrants, races};
+
+    const RESULTS: &str = r#"{
+        "track": "Sandpit",
+        "mode": "laps",
+        "laps": 5,
+        "results": [
+            {"position": 1, "name": "Some Driver", "vehicle": "Formula", "time": 312345},
+            {"position": 2, "name": "Other", "vehicle": "Formula", "time": 313345}
+        ]
+    }"#;
+
+    fn reparse_all(db: &DbConnection, apply: bool) -> Vec<(i32, Result<RaceDiff, String>)> {
+        let mut results = Vec::new();
+        let options = ParseOptions::default();
+        reparse(
+            db,
+            &options,
+            &Broadcaster::default(),
+            apply,
+            |id, result| {
+                let result = result.map(|added| added.diff);
+                results.push((id, result.map_err(|err| format!("{:#}", err))));
+            },
+        )
+        .unwrap();
+        results
+    }
+
+    #[test]
+    fn reparses_stored_and_failed_documents() {
+        let test_db = TestDb::new("reparse");
+        let db = DbPool::new(test_db.url()).unwrap().get().unwrap();
+        migrations::run_pending(&db).unwrap();
+        let date = "2020-06-08".parse().unwrap();
+        for &id in &[1, 2] {
+            let race = detect_format(RESULTS)
+                .unwrap()
+                .parse(RESULTS, &ParseOptions::default())
+                .unwrap();
+            race.insert_into(
+                &db,
+                id,
+                date,
+                AddMode::Insert,
+                Some(RESULTS),
+                &Broadcaster::default(),
+            )
+            .unwrap();
+        }
+        let time = || -> Option<i32> {
+            with_conn!(&db, |conn| race_entrants::table
+                .select(race_entrants::time)
+                .filter(race_entrants::race_id.eq(1))
+                .filter(race_entrants::position.eq(2))
+                .first(conn))
+            .unwrap()
+        };
+        with_conn!(&db, |conn| diesel::update(
+            race_entrants::table.filter(race_entrants::race_id.eq(1))
+        )
+        .set(race_entrants::time.eq(None::<i32>))
+        .execute(conn))
+        .unwrap();
+        let error = anyhow::anyhow!("unknown results format");
+        store_failed(&db, 3, date, "not results", &error).unwrap();
+        store_failed(&db, 4, date, RESULTS, &error).unwrap();
+
+        let previewed = reparse_all(&db, false);
+        assert_eq!(previewed.len(), 4);
+        let diff = |index: usize| previewed[index].1.as_ref().unwrap();
+        assert_eq!(previewed[0].0, 1);
+        assert_eq!(diff(0).changes.len(), 2);
+        assert!(diff(1).is_unchanged());
+        assert!(previewed[2].1.is_err());
+        assert!(diff(3).created);
+        assert_eq!(time(), None);
+
+        let applied = reparse_all(&db, true);
+        assert_eq!(applied.len(), 4);
+        assert_eq!(time(), Some(313_345));
+        let stored: Vec<i32> = with_conn!(&db, |conn| races::table
+            .select(races::id)
+            .order(races::id)
+            .load(conn))
+        .unwrap();
+        assert_eq!(stored, [1, 2, 4]);
+        let failed: Vec<i32> = with_conn!(&db, |conn| failed_sources::table
+            .select(failed_sources::race_id)
+            .load(conn))
+        .unwrap();
+        assert_eq!(failed, [3]);
+        let rated: i64 = with_conn!(&db, |conn| driver_ratings::table
+            .filter(driver_ratings::race_id.eq(4))
+            .count()
+            .get_result(conn))
+        .unwrap();
+        assert_eq!(rated, 2);
+
+        let again = reparse_all(&db, false);
+        assert!(again
+            .iter()
+            .filter(|(id, _)| *id != 3)
+            .all(|(_, diff)| diff.as_ref().unwrap().is_unchanged()));
+    }
+}
@@ -1,3 +1,48 @@
+table! {
+    api_tokens (id) {
+        id -> Integer,
+        name -> Varchar,
+        token_hash -> Varchar,
+        role -> crate::model::RoleMapping,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    championship_rounds (championship_id, race_id) {
+        championship_id -> Integer,
+        race_id -> Integer,
+    }
+}
+
+table! {
+    championships (id) {
+        id -> Integer,
+        season_id -> Integer,
+        name -> Varchar,
+        points -> Varchar,
+        best_lap_points -> Integer,
+        drop_rounds -> Integer,
+    }
+}
+
+table! {
+    driver_aliases (name) {
+        name -> Varchar,
+        driver_id -> Integer,
+    }
+}
+
+table! {
+    driver_ratings (race_id, driver_id) {
+        race_id -> Integer,
+        driver_id -> Integer,
+        rating -> Double,
+        delta -> Double,
+    }
+}
+
 table! {
     drivers (id) {
         id -> Integer,
@@ -5,13 +50,43 @@ table! {
     }
 }
 
+table! {
+    failed_sources (race_id) {
+        race_id -> Integer,
+        date -> Date,
+        document -> Binary,
+        error -> Text,
+        failed_at -> Timestamp,
+    }
+}
+
+table! {
+    fetch_cursors (base_url) {
+        base_url -> Varchar,
+        race_id -> Integer,
+        date -> Date,
+    }
+}
+
+table! {
+    import_log (id) {
+        id -> Integer,
+        file -> Varchar,
+        race_id -> Nullable<Integer>,
+        outcome -> crate::model::ImportOutcomeMapping,
+        error -> Nullable<Text>,
+        logged_at -> Timestamp,
+    }
+}
+
 table! {
     races (id) {
         id -> Integer,
         date -> Date,
-        track -> Text,
+        track_id -> Integer,
         laps -> Nullable<Integer>,
         minutes -> Nullable<Integer>,
+        mode -> Nullable<crate::model::RaceModeMapping>,
     }
 }
 
@@ -20,22 +95,77 @@ table! {
         race_id -> Integer,
         driver_id -> Integer,
         position -> Nullable<Integer>,
-        vehicle -> Nullable<Text>,
+        vehicle_id -> Nullable<Integer>,
         time -> Nullable<Integer>,
+        gap -> Nullable<Integer>,
         best_lap -> Nullable<Integer>,
         lap -> Nullable<Integer>,
         reason -> Nullable<crate::model::ReasonMapping>,
         ping -> Nullable<Integer>,
         fps -> Nullable<Integer>,
         fps_locked -> Bool,
+        entered_as -> Nullable<Varchar>,
+        row_class -> Nullable<Varchar>,
+        name_class -> Nullable<Varchar>,
+        extras -> Nullable<Text>,
+    }
+}
+
+table! {
+    race_sources (race_id) {
+        race_id -> Integer,
+        document -> Binary,
+    }
+}
+
+table! {
+    seasons (id) {
+        id -> Integer,
+        name -> Varchar,
+    }
+}
+
+table! {
+    tracks (id) {
+        id -> Integer,
+        name -> Varchar,
+    }
+}
+
+table! {
+    vehicles (id) {
+        id -> Integer,
+        name -> Varchar,
+        normalized_name -> Varchar,
     }
 }
 
+joinable!(championship_rounds -> championships (championship_id));
+joinable!(championship_rounds -> races (race_id));
+joinable!(championships -> seasons (season_id));
+joinable!(driver_aliases -> drivers (driver_id));
+joinable!(driver_ratings -> drivers (driver_id));
+joinable!(driver_ratings -> races (race_id));
 joinable!(race_entrants -> drivers (driver_id));
 joinable!(race_entrants -> races (race_id));
+joinable!(race_entrants -> vehicles (vehicle_id));
+joinable!(race_sources -> races (race_id));
+joinable!(races -> tracks (track_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    championship_rounds,
+    championships,
+    driver_aliases,
+    driver_ratings,
     drivers,
+    failed_sources,
+    fetch_cursors,
+    import_log,
     races,
     race_entrants,
+    race_sources,
+    seasons,
+    tracks,
+    vehicles,
 );
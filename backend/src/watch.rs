@@ -0,0 +1,155 @@
+use crate::db::DbConnection;
+use crate::import::{is_result_path, parse_result_path, DEFAULT_EXTENSIONS};
+use crate::model::NewImportLogEntry;
+use anyhow::Context;
+use chrono::naive::NaiveDate;
+use chrono::{DateTime, Local};
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+const IMPORTED: &str = "imported";
+const FAILED: &str = "failed";
+
+/// Picks up the results files written into a directory, which are moved to
+/// its `imported/` or `failed/` subdirectory once they have been handled.
+///
+/// Files are found by scanning the directory, and are only handed out once
+/// they are unchanged since the previous scan, so that files that are still
+/// being written are left alone.
+pub struct Watcher {
+    dir: PathBuf,
+    /// The extensions of the files that are picked up.
+    extensions: Vec<String>,
+    /// The size and modification time of every file seen by the last scan.
+    seen: HashMap<PathBuf, (u64, SystemTime)>,
+}
+
+impl Watcher {
+    pub fn new(dir: &Path) -> anyhow::Result<Watcher> {
+        for subdir in &[IMPORTED, FAILED] {
+            fs::create_dir_all(dir.join(subdir))
+                .with_context(|| format!("failed to create {}", dir.join(subdir).display()))?;
+        }
+        Ok(Watcher {
+            dir: dir.to_owned(),
+            extensions: DEFAULT_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            seen: HashMap::new(),
+        })
+    }
+
+    /// Sets the extensions of the files that are picked up, which are
+    /// `DEFAULT_EXTENSIONS` unless this is called.
+    pub fn set_extensions(&mut self, extensions: Vec<String>) {
+        self.extensions = extensions;
+    }
+
+    /// Scans the directory, returning the results files that have settled,
+    /// relative to the directory.
+    pub(crate) fn settled_files(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut seen = HashMap::new();
+        let mut settled = Vec::new();
+        let walk = WalkDir::new(&self.dir)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() != 1 || (entry.file_name() != IMPORTED && entry.file_name() != FAILED)
+            });
+        for entry in walk {
+            let entry = entry?;
+            let path = entry.path();
+            if !entry.file_type().is_file() || !is_result_path(path, &self.extensions) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let state = (metadata.len(), metadata.modified()?);
+            let relative = path.strip_prefix(&self.dir)?.to_owned();
+            if self.seen.get(&relative) == Some(&state) {
+                settled.push(relative.clone());
+            }
+            seen.insert(relative, state);
+        }
+        self.seen = seen;
+        Ok(settled)
+    }
+
+    /// The id and date of the race in a file. Files in a `YYYY/MM/DD`
+    /// directory are dated by it, and files directly in the watched directory
+    /// by when they were last modified.
+    pub(crate) fn race_of(&self, file: &Path) -> anyhow::Result<(i32, NaiveDate)> {
+        if file.parent() != Some(Path::new("")) {
+            return parse_result_path(file);
+        }
+        let id = file
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .and_then(|stem| stem.parse().ok())
+            .context("file name is not a race id")?;
+        let modified: DateTime<Local> = fs::metadata(self.dir.join(file))?.modified()?.into();
+        Ok((id, modified.naive_local().date()))
+    }
+
+    pub(crate) fn read(&self, file: &Path) -> anyhow::Result<String> {
+        Ok(fs::read_to_string(self.dir.join(file))?)
+    }
+
+    /// Moves a file into `imported/` or `failed/`, keeping its path below
+    /// the watched directory.
+    pub(crate) fn file_away(&mut self, file: &Path, failed: bool) -> anyhow::Result<()> {
+        let target = self
+            .dir
+            .join(if failed { FAILED } else { IMPORTED })
+            .join(file);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.dir.join(file), &target).with_context(|| {
+            format!("failed to move {} to {}", file.display(), target.display())
+        })?;
+        self.seen.remove(file);
+        Ok(())
+    }
+}
+
+pub(crate) fn log_import(db: &DbConnection, entry: &NewImportLogEntry) -> anyhow::Result<()> {
+    use crate::schema::import_log::dsl::import_log;
+    with_conn!(db, |conn| diesel::insert_into(import_log)
+        .values(entry)
+        .execute(conn))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_files_to_settle() {
+        let dir = std::env::temp_dir().join(format!("phr-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut watcher = Watcher::new(&dir).unwrap();
+        let file = Path::new("2020/01/25/20630.html");
+        fs::create_dir_all(dir.join("2020/01/25")).unwrap();
+        fs::write(dir.join(file), "<html>").unwrap();
+        fs::write(dir.join("imported/1.html"), "").unwrap();
+
+        assert!(watcher.settled_files().unwrap().is_empty());
+        assert_eq!(watcher.settled_files().unwrap(), vec![file.to_owned()]);
+        assert_eq!(
+            watcher.race_of(file).unwrap(),
+            (20630, "2020-01-25".parse().unwrap())
+        );
+
+        watcher.file_away(file, true).unwrap();
+        assert!(dir.join("failed").join(file).is_file());
+        assert!(watcher.settled_files().unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
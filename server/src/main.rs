@@ -10,9 +10,12 @@ fn main() -> anyhow::Result<()> {
     let database_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
     let frontend_static_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../frontend/static");
 
+    let api = Api::new(&database_url)?;
+    let export = api.export_filter();
     let filter = (warp::path("api").and(
-        (warp::path("graphql").and(Api::new(&database_url)?.to_filter()))
-            .or(warp::path("graphiql").and(graphiql_filter("/api/graphql"))),
+        (warp::path("graphql").and(api.to_filter()))
+            .or(warp::path("graphiql").and(graphiql_filter("/api/graphql")))
+            .or(warp::path("export").and(export)),
     ))
     .or(warp::fs::dir(frontend_static_dir))
     .or(warp::fs::file(format!(